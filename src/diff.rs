@@ -0,0 +1,275 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Unified diffs between two versions of a text file,
+//! in the format understood by `patch` and `git apply`.
+
+use std::fmt::Write;
+
+/// The number of unchanged lines shown around each change.
+const CONTEXT: usize = 3;
+
+/// What happens to a line when going from the old text to the new text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Returns a unified diff that turns `old` into `new`,
+/// or an empty string if they're the same.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old, &new);
+
+    let mut output = String::new();
+    let mut hunks = hunks(&edits).peekable();
+    if hunks.peek().is_none() {
+        return output;
+    }
+    writeln!(output, "--- {}", old_name).unwrap();
+    writeln!(output, "+++ {}", new_name).unwrap();
+
+    // The position in the old and new lines before each edit.
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    positions.push((old_line, new_line));
+    for edit in &edits {
+        match edit {
+            Edit::Keep => (old_line, new_line) = (old_line + 1, new_line + 1),
+            Edit::Delete => old_line += 1,
+            Edit::Insert => new_line += 1,
+        }
+        positions.push((old_line, new_line));
+    }
+
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        writeln!(
+            output,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        )
+        .unwrap();
+        for (i, edit) in edits[start..end].iter().enumerate() {
+            let (old_line, new_line) = positions[start + i];
+            let (prefix, line) = match edit {
+                Edit::Keep => (' ', old[old_line]),
+                Edit::Delete => ('-', old[old_line]),
+                Edit::Insert => ('+', new[new_line]),
+            };
+            output.push(prefix);
+            output.push_str(line);
+            if !line.ends_with('\n') {
+                output.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    output
+}
+
+/// Formats the range of a hunk, given the index of its first line.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        // An empty range is given by the line before it.
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Returns the ranges of edits to show in each hunk:
+/// the changes with their context,
+/// merging changes whose contexts overlap.
+fn hunks(edits: &[Edit]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| **edit != Edit::Keep)
+        .map(|(i, _)| i)
+        .peekable();
+    std::iter::from_fn(move || {
+        let first = changes.next()?;
+        let mut last = first;
+        while let Some(&next) = changes.peek() {
+            if next - last - 1 > 2 * CONTEXT {
+                break;
+            }
+            last = next;
+            changes.next();
+        }
+        Some((
+            first.saturating_sub(CONTEXT),
+            (last + 1 + CONTEXT).min(edits.len()),
+        ))
+    })
+}
+
+/// Finds the shortest list of edits that turns `old` into `new`,
+/// with the algorithm from Eugene W. Myers'
+/// "An O(ND) Difference Algorithm and Its Variations".
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let index = |k: isize| (k + max + 1) as usize;
+
+    // `v[index(k)]` is the furthest position in `old`
+    // reached on diagonal `k` (`x - y`) with the edits so far.
+    // `trace[d]` keeps the diagonals `-d..=d` after `d` edits.
+    let mut v = vec![0; 2 * max as usize + 3];
+    let mut trace: Vec<Vec<isize>> = vec![];
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                trace.push(v[index(-d)..=index(d)].to_vec());
+                break 'search;
+            }
+        }
+        trace.push(v[index(-d)..=index(d)].to_vec());
+    }
+
+    // Walk back from the end to find the edits that led there.
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        edits.push(if x == previous_x {
+            Edit::Insert
+        } else {
+            Edit::Delete
+        });
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.extend((0..x).map(|_| Edit::Keep));
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<&str> {
+        s.split_inclusive('\n').collect()
+    }
+
+    #[test]
+    fn diff_lines_finds_the_shortest_edits() {
+        use Edit::*;
+        assert_eq!(diff_lines(&[], &[]), []);
+        assert_eq!(diff_lines(&lines("a\n"), &[]), [Delete]);
+        assert_eq!(diff_lines(&[], &lines("a\n")), [Insert]);
+        assert_eq!(
+            diff_lines(&lines("a\nb\nc\n"), &lines("a\nx\nc\nd\n")),
+            [Keep, Delete, Insert, Keep, Insert]
+        );
+        // The example from Myers' paper.
+        let old = lines("a\nb\nc\na\nb\nb\na\n");
+        let new = lines("c\nb\na\nb\na\nc\n");
+        let edits = diff_lines(&old, &new);
+        assert_eq!(edits.iter().filter(|&&edit| edit != Keep).count(), 5);
+    }
+
+    #[test]
+    fn same_texts_have_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+    }
+
+    #[test]
+    fn unified_diff_shows_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n";
+        let new = "1\n2\n3\n4\n5\n6\n7\nseven and a half\n8\n9\n10\n11\n12\n13\n14\n15\n";
+        assert_eq!(
+            unified_diff(old, new, "a/feeds.yaml", "b/feeds.yaml"),
+            "--- a/feeds.yaml\n\
+             +++ b/feeds.yaml\n\
+             @@ -5,6 +5,7 @@\n \
+             5\n \
+             6\n \
+             7\n\
+             +seven and a half\n \
+             8\n \
+             9\n \
+             10\n\
+             @@ -13,4 +14,3 @@\n \
+             13\n \
+             14\n \
+             15\n\
+             -16\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_merges_close_changes() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\n";
+        let new = "A\nb\nc\nd\ne\nf\ng\nH\n";
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "--- old\n\
+             +++ new\n\
+             @@ -1,8 +1,8 @@\n\
+             -a\n\
+             +A\n \
+             b\n \
+             c\n \
+             d\n \
+             e\n \
+             f\n \
+             g\n\
+             -h\n\
+             +H\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_handles_empty_files_and_missing_newlines() {
+        assert_eq!(
+            unified_diff("", "feeds: []\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1 @@\n+feeds: []\n"
+        );
+        assert_eq!(
+            unified_diff("feeds: []", "feeds: []\n", "old", "new"),
+            "--- old\n\
+             +++ new\n\
+             @@ -1 +1 @@\n\
+             -feeds: []\n\
+             \\ No newline at end of file\n\
+             +feeds: []\n"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Utc, Weekday};
+use quick_error::quick_error;
+use serde::{Deserialize, Serialize};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum DigestDayError {
+        UnknownDay(day: String) {
+            display("{:?} isn't a day of the week like sunday", day)
+        }
+    }
+}
+
+/// The day of the week on which a feed's entries are sent to Pocket,
+/// for feeds that send their entries as a weekly digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DigestDay(Weekday);
+
+impl DigestDay {
+    /// Returns true if a digest is due at `now`,
+    /// i.e. if the last one was sent before the last digest day.
+    pub fn is_due(self, last_digest: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        let date = |date: DateTime<Utc>| date.with_timezone(&Local).date_naive();
+        self.is_due_on(last_digest.map(date), date(now))
+    }
+
+    fn is_due_on(self, last_digest: Option<NaiveDate>, today: NaiveDate) -> bool {
+        last_digest.is_none_or(|last_digest| last_digest < self.latest(today))
+    }
+
+    /// Returns the last digest day on or before `today`.
+    fn latest(self, today: NaiveDate) -> NaiveDate {
+        let days_since = today.weekday().days_since(self.0);
+        today - Days::new(days_since.into())
+    }
+}
+
+impl Display for DigestDay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self.0 {
+            Weekday::Mon => "monday",
+            Weekday::Tue => "tuesday",
+            Weekday::Wed => "wednesday",
+            Weekday::Thu => "thursday",
+            Weekday::Fri => "friday",
+            Weekday::Sat => "saturday",
+            Weekday::Sun => "sunday",
+        })
+    }
+}
+
+impl FromStr for DigestDay {
+    type Err = DigestDayError;
+
+    /// Parses the English name of a day, or its first three letters.
+    fn from_str(s: &str) -> Result<DigestDay, DigestDayError> {
+        s.parse()
+            .map(DigestDay)
+            .map_err(|_| DigestDayError::UnknownDay(s.into()))
+    }
+}
+
+impl TryFrom<String> for DigestDay {
+    type Error = DigestDayError;
+
+    fn try_from(s: String) -> Result<DigestDay, DigestDayError> {
+        s.parse()
+    }
+}
+
+impl From<DigestDay> for String {
+    fn from(day: DigestDay) -> String {
+        day.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    const SUNDAY: DigestDay = DigestDay(Weekday::Sun);
+
+    #[test]
+    fn latest_digest_day() {
+        // 2024-01-07 is a Sunday.
+        assert_eq!(SUNDAY.latest(date("2024-01-07")), date("2024-01-07"));
+        assert_eq!(SUNDAY.latest(date("2024-01-13")), date("2024-01-07"));
+        // Across the end of a year and of a leap month.
+        assert_eq!(SUNDAY.latest(date("2024-01-02")), date("2023-12-31"));
+        assert_eq!(SUNDAY.latest(date("2024-03-01")), date("2024-02-25"));
+        assert_eq!(
+            DigestDay(Weekday::Fri).latest(date("2023-01-05")),
+            date("2022-12-30")
+        );
+    }
+
+    #[test]
+    fn digest_is_due_once_per_week() {
+        assert!(SUNDAY.is_due_on(None, date("2024-01-03")));
+
+        // Sent on Sunday: not due again until the next Sunday.
+        let sent = Some(date("2023-12-31"));
+        assert!(!SUNDAY.is_due_on(sent, date("2023-12-31")));
+        assert!(!SUNDAY.is_due_on(sent, date("2024-01-06")));
+        assert!(SUNDAY.is_due_on(sent, date("2024-01-07")));
+
+        // No sync on Sunday: the first sync after it sends the digest.
+        let sent = Some(date("2024-02-22"));
+        assert!(!SUNDAY.is_due_on(sent, date("2024-02-24")));
+        assert!(SUNDAY.is_due_on(sent, date("2024-02-27")));
+        assert!(SUNDAY.is_due_on(sent, date("2024-03-02")));
+    }
+
+    #[test]
+    fn parse_days() {
+        assert_eq!("sunday".parse::<DigestDay>().unwrap(), SUNDAY);
+        assert_eq!("Sun".parse::<DigestDay>().unwrap(), SUNDAY);
+        assert!(matches!(
+            "weekly".parse::<DigestDay>(),
+            Err(DigestDayError::UnknownDay(day)) if day == "weekly"
+        ));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let day: DigestDay = serde_yaml::from_str("Friday").unwrap();
+        assert_eq!(day, DigestDay(Weekday::Fri));
+        assert_eq!(serde_yaml::to_string(&day).unwrap(), "friday\n");
+    }
+}
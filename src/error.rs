@@ -0,0 +1,819 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::iter;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use quick_error::quick_error;
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::pocket::PocketError;
+
+/// A boxed error that can be sent across threads.
+pub type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+macro_rules! try_with_context {
+    ($expr:expr, $context:expr) => {
+        match $expr {
+            ::std::result::Result::Ok(val) => val,
+            ::std::result::Result::Err(err) => {
+                return ::std::result::Result::Err($crate::error::ErrorWithContext::new(
+                    ::std::convert::From::from(err),
+                    $context,
+                ))
+            }
+        }
+    };
+}
+
+#[derive(Debug)]
+pub struct ErrorWithContext {
+    error: BoxError,
+    context: String,
+    kind: ErrorKind,
+}
+
+impl ErrorWithContext {
+    pub fn new<S: Into<String>>(error: BoxError, context: S) -> ErrorWithContext {
+        ErrorWithContext {
+            kind: ErrorKind::of(&*error),
+            error,
+            context: context.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Overrides the kind deduced from the error's type.
+    pub fn with_kind(self, kind: ErrorKind) -> ErrorWithContext {
+        ErrorWithContext { kind, ..self }
+    }
+}
+
+/// What kind of problem stopped a command,
+/// so that scripts can react to it through the exit status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The configuration file couldn't be loaded
+    /// or a command refers to something that isn't in it.
+    Config,
+    /// The consumer key or the access token is missing or was rejected.
+    Auth,
+    /// A sync finished, but some feeds failed.
+    FeedFailures,
+    /// A sync was stopped because it exceeded its budget.
+    BudgetExceeded,
+    Other,
+}
+
+impl ErrorKind {
+    /// Classifies an error by its type,
+    /// looking into the errors that group other errors.
+    fn of(error: &(dyn Error + 'static)) -> ErrorKind {
+        if let Some(error) = error.downcast_ref::<ErrorWithContext>() {
+            error.kind
+        } else if error.is::<BudgetExceeded>() {
+            ErrorKind::BudgetExceeded
+        } else if let Some(failures) = error.downcast_ref::<FeedFailures>() {
+            // Logging in again is the most urgent fix.
+            if ErrorKind::any_auth(failures.failures.iter().map(|(_, e)| e)) {
+                ErrorKind::Auth
+            } else {
+                ErrorKind::FeedFailures
+            }
+        } else if let Some(Errors::Errors(errors)) = error.downcast_ref::<Errors>() {
+            if ErrorKind::any_auth(errors) {
+                ErrorKind::Auth
+            } else {
+                ErrorKind::Other
+            }
+        } else if error.is::<PocketSetupError>()
+            || error
+                .downcast_ref::<PocketError>()
+                .is_some_and(PocketError::is_auth_error)
+        {
+            ErrorKind::Auth
+        } else if error.is::<FeedNotFound>()
+            || error.is::<InvalidFeedName>()
+            || error.is::<AmbiguousTags>()
+            || error.is::<InvalidInclude>()
+            || error.is::<ReadOnly>()
+            || error.is::<UnsupportedScheme>()
+            || error.is::<UnknownField>()
+        {
+            ErrorKind::Config
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    fn any_auth<'a>(errors: impl IntoIterator<Item = &'a BoxError>) -> bool {
+        errors
+            .into_iter()
+            .any(|error| ErrorKind::of(&**error) == ErrorKind::Auth)
+    }
+
+    /// Returns the exit status for errors of this kind.
+    pub fn exit_status(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            // Like clap's usage errors.
+            ErrorKind::Config => 2,
+            ErrorKind::BudgetExceeded => 3,
+            ErrorKind::FeedFailures => 4,
+            ErrorKind::Auth => 5,
+        }
+    }
+}
+
+impl Display for ErrorWithContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:\n  {}", self.context, Indented(&self.error))
+    }
+}
+
+impl Error for ErrorWithContext {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum PocketSetupError {
+        MissingConsumerKey {
+            display("The consumer key is not set in the configuration file. Run `feeds-to-pocket help set-consumer-key` for help and instructions.")
+        }
+        MissingAccessToken {
+            display("The access token is not set in the configuration file. Run `feeds-to-pocket help login` for help and instructions.")
+        }
+        UnknownAccount(name: String) {
+            display("There is no account named \"{}\" in the configuration file. Run `feeds-to-pocket set-consumer-key --account {}` to add it.", name, name)
+        }
+        NoPendingLogin(name: String) {
+            display("There is no pending login for the account \"{}\". Run `feeds-to-pocket login --no-wait --account {}` to start one.", name, name)
+        }
+        PendingLoginExpired(name: String) {
+            display("The pending login for the account \"{}\" has expired. Run `feeds-to-pocket login --no-wait --account {}` to start over.", name, name)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum UnacceptableHttpStatus {
+        UnacceptableHttpStatus(status: StatusCode) {
+            display("{}", status)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ResponseTooLarge {
+        ResponseTooLarge(limit: u64) {
+            display("response exceeded {} bytes", limit)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InvalidUtf8 {
+        /// The offset of the invalid sequence, the Content-Type of the response (if known)
+        /// and the first bytes of the body, as hexadecimal.
+        InvalidUtf8(offset: usize, content_type: Option<String>, first_bytes: String) {
+            display("invalid UTF-8 sequence at byte {}{}; the body starts with: {}",
+                offset,
+                content_type.as_ref().map_or(String::new(), |content_type| format!(" (Content-Type: {})", content_type)),
+                first_bytes)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InvalidSavedConfig {
+        /// The number of feeds that were saved and the number of feeds read back.
+        FeedCount(expected: usize, actual: usize) {
+            display("expected {} feeds, but the file has {}", expected, actual)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum UnknownCharset {
+        UnknownCharset(charset: String) {
+            display("unknown charset: {}", charset)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum BudgetExceeded {
+        /// Why the sync was stopped and the URLs of the feeds that were skipped.
+        BudgetExceeded(reason: String, skipped: Vec<String>) {
+            display("{}; {} feeds were skipped and will be synced first next time:{}",
+                reason,
+                skipped.len(),
+                skipped.iter().map(|url| format!("\n- {}", url)).collect::<String>())
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum CommandError {
+        MissingCommand {
+            display("no command was specified after `exec:`")
+        }
+        Failed(status: ExitStatus, stderr: String) {
+            display("{}{}", status, if stderr.is_empty() { String::new() } else { format!("; standard error:\n{}", stderr) })
+        }
+        TimedOut(timeout: Duration) {
+            display("the command didn't finish within {} seconds and was killed", timeout.as_secs_f64())
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum UnsupportedScheme {
+        UnsupportedScheme(scheme: String) {
+            display("unsupported URL scheme `{}:`; feeds must be http, https, file or exec URLs or local paths", scheme)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum FilterError {
+        MissingCommand {
+            display("filter_command is empty")
+        }
+        /// A line of the output of a batch filter command.
+        UnknownVerdict(line: String) {
+            display("the filter command printed {:?} instead of `push` or `skip`", line)
+        }
+        /// The number of entries and the number of verdicts.
+        VerdictCount(entries: usize, verdicts: usize) {
+            display("the filter command printed {} verdicts for {} entries", verdicts, entries)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Clone, Debug)]
+    pub enum ResolveError {
+        Failed(host: String, error: String) {
+            display("failed to resolve {}: {}", host, error)
+        }
+        NoIpv4Address(host: String) {
+            display("{} has no IPv4 address (force_ipv4 is set)", host)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InvalidResolveOverride {
+        InvalidResolveOverride(value: String) {
+            display("invalid resolve override {:?} (expected HOST:PORT:ADDRESS, e.g. feeds.example.com:443:192.0.2.10)", value)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum RedirectError {
+    /// There were more redirects than the limit.
+    TooManyRedirects(usize, Vec<Url>),
+    /// A redirect led back to a URL that was already requested.
+    Loop(Vec<Url>),
+}
+
+impl Display for RedirectError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let chain = match self {
+            RedirectError::TooManyRedirects(0, chain) => {
+                write!(fmt, "the server redirected the request, but redirects are disabled (max_redirects is 0)")?;
+                chain
+            }
+            RedirectError::TooManyRedirects(max_redirects, chain) => {
+                write!(fmt, "too many redirects (the limit is {})", max_redirects)?;
+                chain
+            }
+            RedirectError::Loop(chain) => {
+                write!(fmt, "redirect loop")?;
+                chain
+            }
+        };
+
+        if let Some((first, rest)) = chain.split_first() {
+            write!(fmt, ":\n  {}", first)?;
+            for url in rest {
+                write!(fmt, "\n  -> {}", url)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for RedirectError {}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Errors {
+        Errors(errors: Vec<BoxError>) {
+            display("{}", errors.iter().map(|error| format!("- {}", Indented(error))).collect::<Vec<_>>().join("\n"))
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InvalidFeedName {
+        Empty(feed_url: String) {
+            display("the name of feed {} is empty", feed_url)
+        }
+        Duplicate(name: String, feed_url: String) {
+            display("the name {:?} is already used by feed {}", name, feed_url)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ReadOnly {
+        ReadOnly {
+            display("nothing can be saved with --read-only")
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum InvalidInclude {
+        Cycle(file_name: String) {
+            display("{} includes itself", file_name)
+        }
+        DuplicateFeed(feed_url: String, first: String, second: String) {
+            display("feed {} is in both {} and {}", feed_url, first, second)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum AmbiguousTags {
+        FeedExists(feed_url: String) {
+            display("feed {} exists; use --tags-replace or --tags-append", feed_url)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum UnknownField {
+        UnknownField(location: String, key: String, suggestion: Option<&'static str>) {
+            display(
+                "unknown field `{}` in {}{}",
+                key,
+                location,
+                suggestion.map_or_else(String::new, |field| format!(" (did you mean `{}`?)", field))
+            )
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum Republished {
+        /// The URL of the feed, the number of new entries,
+        /// the number of entries in the feed
+        /// and the number of new entries older than the newest processed entry.
+        Republished(feed_url: String, new: usize, total: usize, older: usize) {
+            display(
+                "{} of the {} entries look new, but {} of them were published before the newest entry that was already processed; \
+                the feed probably changed its entry URLs. Nothing was sent to Pocket. \
+                If the entries are really new, run `feeds-to-pocket sync --accept-republished {}`; \
+                otherwise, run `feeds-to-pocket mark-read {}`.",
+                new, total, older, feed_url, feed_url
+            )
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum LargeFeedError {
+        Xml(err: quick_xml::Error) {
+            from()
+            from(err: quick_xml::encoding::EncodingError) -> (err.into())
+            display("{}", err)
+            source(err)
+        }
+        /// The name of the root element.
+        UnknownRoot(name: String) {
+            display("the root element <{}> isn't an Atom or RSS feed", name)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum YamlError {
+        /// The error and the lines of the file around its location.
+        Yaml(err: serde_yaml::Error, excerpt: Option<String>) {
+            display("{}{}", err, excerpt.as_ref().map_or_else(String::new, |excerpt| format!("\n{}", excerpt)))
+            source(err)
+        }
+    }
+}
+
+impl YamlError {
+    /// The number of lines shown before and after the line of the error.
+    const CONTEXT_LINES: usize = 2;
+
+    /// Wraps an error from parsing `contents`,
+    /// adding the lines around the error's location.
+    pub fn new(err: serde_yaml::Error, contents: &str) -> YamlError {
+        let excerpt = err.location().map(|location| {
+            let line = location.line();
+            let first = line.saturating_sub(YamlError::CONTEXT_LINES).max(1);
+            let last = line + YamlError::CONTEXT_LINES;
+            let width = last.to_string().len();
+            let mut excerpt = vec![];
+            for (number, text) in (1..).zip(contents.lines()).take(last) {
+                if (first..=last).contains(&number) {
+                    excerpt.push(format!("{:>width$} | {}", number, text));
+                }
+                if number == line {
+                    let caret = format!("{:>column$}", "^", column = location.column());
+                    excerpt.push(format!("{:>width$} | {}", "", caret));
+                }
+            }
+            excerpt.join("\n")
+        });
+        YamlError::Yaml(err, excerpt)
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum StaleSync {
+        Never {
+            display("no sync has finished without failing feeds yet")
+        }
+        /// When the last successful sync finished and the maximum staleness.
+        TooOld(last_successful_sync: String, max_staleness: String) {
+            display("the last successful sync finished at {}, more than {} ago", last_successful_sync, max_staleness)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum FeedNotFound {
+        FeedNotFound(url: String) {
+            display("No feed with URL {} was found.", url)
+        }
+        EmptyGroup(group: String) {
+            display("No feed is in group {}.", group)
+        }
+    }
+}
+
+impl Errors {
+    pub fn new(errors: Vec<BoxError>) -> Errors {
+        Errors::Errors(errors)
+    }
+}
+
+/// The errors that occurred while processing feeds during a sync,
+/// along with the URL (or label) of the feed each error relates to.
+#[derive(Debug, Default)]
+pub struct FeedFailures {
+    failures: Vec<(String, BoxError)>,
+}
+
+impl FeedFailures {
+    pub fn new() -> FeedFailures {
+        FeedFailures::default()
+    }
+
+    pub fn push<E: Into<BoxError>>(&mut self, feed_url: &str, error: E) {
+        self.failures.push((feed_url.into(), error.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl Display for FeedFailures {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.failures.len() {
+            1 => write!(fmt, "1 feed failed:")?,
+            n => write!(fmt, "{} feeds failed:", n)?,
+        }
+
+        for (feed_url, error) in &self.failures {
+            write!(fmt, "\n- {}:\n    {}", feed_url, Indented(Indented(error)))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for FeedFailures {}
+
+/// Formats an error and its sources, one per line,
+/// with their debug output, which includes details
+/// (e.g. the positions in a YAML file) that their messages leave out.
+pub struct ErrorChain<'a>(pub &'a (dyn Error + 'static));
+
+impl Display for ErrorChain<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let chain = iter::successors(Some(self.0), |&error| error.source());
+        for (depth, error) in chain.enumerate() {
+            writeln!(fmt, "{}: {:?}", depth, error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a type implementing Display
+/// and adds two spaces after each line feed in its display output.
+pub struct Indented<D: Display>(pub D);
+
+impl<D: Display> Display for Indented<D> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use std::fmt::Write;
+        write!(IndentedWrite(fmt), "{}", self.0)
+    }
+}
+
+/// Intercepts writes to a `std::fmt::Formatter`
+/// and adds two spaces after each line feed written to it.
+struct IndentedWrite<'a: 'f, 'f>(&'f mut fmt::Formatter<'a>);
+
+// The documentation recommends implementing std::io::Write,
+// but that trait operates on a stream of bytes,
+// whereas std::fmt::Write operates on string slices.
+// Additionally, we call Formatter::write_str(),
+// which returns a Result<(), std::fmt::Error>,
+// which matches the signature of std::fmt::Write::write_str().
+impl<'a: 'f, 'f> fmt::Write for IndentedWrite<'a, 'f> {
+    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
+        let mut lines = s.split('\n');
+        if let Some(line) = lines.next() {
+            self.0.write_str(line)?;
+            for line in lines {
+                self.0.write_str("\n  ")?;
+                self.0.write_str(line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(error: impl Into<BoxError>) -> ErrorKind {
+        ErrorWithContext::new(error.into(), "context").kind()
+    }
+
+    #[test]
+    fn error_kinds() {
+        assert_eq!(kind("something broke"), ErrorKind::Other);
+        assert_eq!(
+            kind(FeedNotFound::FeedNotFound("https://a.example/feed".into())),
+            ErrorKind::Config
+        );
+        assert_eq!(kind(PocketSetupError::MissingAccessToken), ErrorKind::Auth);
+        let rejected = || {
+            PocketError::Proto(
+                StatusCode::UNAUTHORIZED,
+                "107".into(),
+                "Consumer key mismatch".into(),
+                None,
+            )
+        };
+        assert_eq!(kind(rejected()), ErrorKind::Auth);
+        assert_eq!(
+            kind(BudgetExceeded::BudgetExceeded("time".into(), vec![])),
+            ErrorKind::BudgetExceeded
+        );
+
+        // The kind survives more context.
+        let error = ErrorWithContext::new(
+            Box::new(ErrorWithContext::new(
+                Box::new(PocketSetupError::MissingConsumerKey),
+                "unable to sync",
+            )),
+            "failed",
+        );
+        assert_eq!(error.kind(), ErrorKind::Auth);
+        let error =
+            ErrorWithContext::new("bad YAML".into(), "failed to load").with_kind(ErrorKind::Config);
+        assert_eq!(kind(error), ErrorKind::Config);
+
+        // Feeds failing because Pocket rejects the access token
+        // are an authentication problem.
+        let mut failures = FeedFailures::new();
+        failures.push("https://a.example/feed", "download error");
+        assert_eq!(kind(failures), ErrorKind::FeedFailures);
+        let mut failures = FeedFailures::new();
+        failures.push("https://a.example/feed", "download error");
+        failures.push(
+            "https://b.example/feed",
+            ErrorWithContext::new(
+                Box::new(Errors::new(vec![Box::new(rejected())])),
+                "failed to push 1 entries to Pocket",
+            ),
+        );
+        assert_eq!(kind(failures), ErrorKind::Auth);
+    }
+
+    #[test]
+    fn yaml_errors_show_the_lines_around_the_error() {
+        let yaml = "a: 1\nb: 2\nc: [3\nd: 4\ne: 5\nf: 6\ng: 7\n";
+        let error = serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap_err();
+        assert_eq!(
+            YamlError::new(error, yaml).to_string(),
+            "did not find expected ',' or ']' at line 4 column 2, \
+             while parsing a flow sequence at line 3 column 4\n\
+             2 | b: 2\n\
+             3 | c: [3\n\
+             4 | d: 4\n  \
+               |  ^\n\
+             5 | e: 5\n\
+             6 | f: 6"
+        );
+    }
+
+    #[test]
+    fn yaml_errors_at_the_end_of_the_file() {
+        let yaml = "[\n";
+        let error = serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap_err();
+        assert_eq!(
+            YamlError::new(error, yaml).to_string(),
+            "did not find expected node content at line 2 column 1, \
+             while parsing a flow node\n\
+             1 | ["
+        );
+    }
+
+    #[test]
+    fn error_chain_lists_the_sources() {
+        let error = ErrorWithContext::new("inner".into(), "outer");
+        let chain = ErrorChain(&error).to_string();
+        let lines: Vec<_> = chain.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0: ErrorWithContext {"));
+        assert_eq!(lines[1], "1: \"inner\"");
+    }
+
+    #[test]
+    fn indented_single_line() {
+        assert_eq!(Indented("abc").to_string(), "abc");
+    }
+
+    #[test]
+    fn indented_multiple_lines() {
+        assert_eq!(Indented("a\nb\nc").to_string(), "a\n  b\n  c");
+    }
+
+    #[test]
+    fn indented_trailing_line_feed() {
+        assert_eq!(Indented("a\n").to_string(), "a\n  ");
+    }
+
+    #[test]
+    fn indented_empty() {
+        assert_eq!(Indented("").to_string(), "");
+    }
+
+    #[test]
+    fn indented_nested() {
+        assert_eq!(Indented(Indented("a\nb")).to_string(), "a\n    b");
+    }
+
+    #[test]
+    fn indented_respects_split_writes() {
+        // Each argument is written to the formatter separately,
+        // so line feeds must be handled in every write.
+        let formatted = Indented(format_args!("{}\n{}", "a", "b\nc")).to_string();
+        assert_eq!(formatted, "a\n  b\n  c");
+    }
+
+    #[test]
+    fn error_with_context_display() {
+        let error = ErrorWithContext::new("first\nsecond".into(), "context");
+        assert_eq!(error.to_string(), "context:\n  first\n  second");
+    }
+
+    #[test]
+    fn error_with_context_source() {
+        let error = ErrorWithContext::new(FeedNotFound::FeedNotFound("x".into()).into(), "c");
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "No feed with URL x was found.");
+    }
+
+    #[test]
+    fn nested_error_with_context_display() {
+        let inner = ErrorWithContext::new("cause".into(), "inner");
+        let outer = ErrorWithContext::new(inner.into(), "outer");
+        assert_eq!(outer.to_string(), "outer:\n  inner:\n    cause");
+    }
+
+    #[test]
+    fn errors_display() {
+        let errors = Errors::new(vec!["a\nb".into(), "c".into()]);
+        assert_eq!(errors.to_string(), "- a\n  b\n- c");
+    }
+
+    #[test]
+    fn feed_failures_display() {
+        let mut failures = FeedFailures::new();
+        failures.push("https://a.example/feed", "first\nsecond");
+        failures.push("https://b.example/feed", "third");
+        assert_eq!(
+            failures.to_string(),
+            "2 feeds failed:\n\
+            - https://a.example/feed:\n    first\n    second\n\
+            - https://b.example/feed:\n    third"
+        );
+    }
+
+    #[test]
+    fn redirect_error_display() {
+        let a = Url::parse("https://a.example/feed").unwrap();
+        let b = Url::parse("https://b.example/feed").unwrap();
+        let error = RedirectError::Loop(vec![a.clone(), b, a.clone()]);
+        assert_eq!(
+            error.to_string(),
+            "redirect loop:\n  \
+            https://a.example/feed\n  \
+            -> https://b.example/feed\n  \
+            -> https://a.example/feed"
+        );
+
+        let error = RedirectError::TooManyRedirects(0, vec![a]);
+        assert_eq!(
+            error.to_string(),
+            "the server redirected the request, but redirects are disabled (max_redirects is 0):\n  \
+            https://a.example/feed"
+        );
+    }
+
+    #[test]
+    fn errors_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<ErrorWithContext>();
+        assert_send_sync::<Errors>();
+        assert_send_sync::<PocketSetupError>();
+        assert_send_sync::<UnacceptableHttpStatus>();
+        assert_send_sync::<ResponseTooLarge>();
+        assert_send_sync::<InvalidUtf8>();
+        assert_send_sync::<UnknownCharset>();
+        assert_send_sync::<InvalidSavedConfig>();
+        assert_send_sync::<BudgetExceeded>();
+        assert_send_sync::<CommandError>();
+        assert_send_sync::<UnsupportedScheme>();
+        assert_send_sync::<FilterError>();
+        assert_send_sync::<RedirectError>();
+        assert_send_sync::<ResolveError>();
+        assert_send_sync::<InvalidResolveOverride>();
+        assert_send_sync::<FeedNotFound>();
+        assert_send_sync::<InvalidFeedName>();
+        assert_send_sync::<AmbiguousTags>();
+        assert_send_sync::<InvalidInclude>();
+        assert_send_sync::<ReadOnly>();
+        assert_send_sync::<UnknownField>();
+        assert_send_sync::<Republished>();
+        assert_send_sync::<StaleSync>();
+        assert_send_sync::<LargeFeedError>();
+        assert_send_sync::<YamlError>();
+        assert_send_sync::<FeedFailures>();
+        assert_send_sync::<crate::feed::FeedError>();
+        assert_send_sync::<crate::pocket::PocketError>();
+    }
+}
@@ -0,0 +1,807 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::error::{BoxError, Indented};
+use crate::large_feed;
+
+/// A feed in any of the supported formats
+/// (Atom, RSS 0.9x, 1.0 and 2.0, and JSON Feed),
+/// normalized to the parts we care about.
+// Not all of the metadata is used yet.
+#[allow(dead_code)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub links: Vec<Link>,
+    /// The entries of the feed, in document order.
+    pub entries: Vec<Entry>,
+}
+
+#[allow(dead_code)]
+pub struct Entry {
+    pub id: String,
+    pub title: Option<String>,
+    pub links: Vec<Link>,
+    /// When the entry was published,
+    /// or last updated if the feed doesn't say when it was published.
+    pub published: Option<DateTime<Utc>>,
+    pub categories: Vec<String>,
+}
+
+/// A link to an entry, with the entry's title.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryRef<'a> {
+    pub url: &'a str,
+    pub title: Option<&'a str>,
+}
+
+#[allow(dead_code)]
+pub struct Link {
+    pub href: String,
+    pub rel: Option<String>,
+    pub media_type: Option<String>,
+}
+
+impl Feed {
+    /// Parses a feed downloaded from `url`.
+    ///
+    /// Relative URLs are resolved against the nearest `xml:base` attribute
+    /// (on the link, its entry or the feed) in Atom feeds,
+    /// or else against `url`,
+    /// so scheme-relative URLs get the scheme of the feed.
+    ///
+    /// If `large` is true, Atom and RSS feeds are parsed as a stream
+    /// (see the `large_feed` module) to use less memory.
+    pub fn parse_at(s: &str, url: &str, large: bool) -> Result<Self, FeedError> {
+        // `exec:` feeds have no URL to resolve against.
+        let base_url = Url::parse(url).ok().filter(|url| !url.cannot_be_a_base());
+        Feed::parse_lenient(s, base_url.as_ref().map(Url::as_str), large)
+    }
+
+    fn parse(s: &str, base_url: Option<&str>, large: bool) -> Result<Self, FeedError> {
+        // JSON feeds are always parsed by feed-rs.
+        let json = s
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '\u{feff}')
+            .starts_with('{');
+        if large && !json {
+            return large_feed::parse(s, base_url).map_err(|e| FeedError(Box::new(e)));
+        }
+
+        let feed = feed_rs::parser::Builder::new()
+            .base_uri(base_url)
+            .build()
+            .parse(s.as_bytes())
+            .map_err(|e| FeedError(Box::new(e)))?;
+        Ok(Feed {
+            title: feed.title.map(|title| title.content),
+            links: feed.links.into_iter().map(Link::from).collect(),
+            entries: feed.entries.into_iter().map(Entry::from).collect(),
+        })
+    }
+
+    fn parse_lenient(s: &str, base_url: Option<&str>, large: bool) -> Result<Self, FeedError> {
+        Feed::parse(s, base_url, large).or_else(|error| {
+            // Some servers emit a blank line, a byte order mark
+            // or even an error message before the XML declaration,
+            // which makes the parser fail.
+            // Skip everything before the XML declaration
+            // (or before the first tag if there's no XML declaration)
+            // and try again.
+            match s.find("<?xml").or_else(|| s.find('<')) {
+                Some(start) if start > 0 => {
+                    debug!(
+                        "skipping {} bytes before the start of the feed and parsing it again",
+                        start
+                    );
+                    // Report the original error if the second attempt also fails.
+                    Feed::parse(&s[start..], base_url, large).map_err(|_| error)
+                }
+                _ => Err(error),
+            }
+        })
+    }
+
+    /// Returns the links to the entries of the feed
+    /// in the order they are sent to Pocket.
+    ///
+    /// Feeds list their newest entries first,
+    /// so the entries are returned in the reverse of document order,
+    /// so that they're added to Pocket in chronological order.
+    /// An entry with several alternate links gives one `EntryRef` per link,
+    /// in document order;
+    /// an entry without alternate links gives none.
+    /// Entries that appear more than once are not removed.
+    pub fn entry_refs(&self) -> Vec<EntryRef<'_>> {
+        self.entries
+            .iter()
+            .rev()
+            .flat_map(|entry| {
+                entry.alternate_links().map(|url| EntryRef {
+                    url,
+                    title: entry.title.as_deref(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the URL the feed says it's published at,
+    /// i.e. its link with a "self" relation type.
+    /// In RSS, that's an `atom:link` element.
+    pub fn self_link(&self) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.rel.as_deref() == Some("self"))
+            .map(|link| link.href.trim())
+    }
+}
+
+impl FromStr for Feed {
+    type Err = FeedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Feed::parse_lenient(s, None, false)
+    }
+}
+
+impl Entry {
+    /// Returns the URLs of the links to this entry,
+    /// i.e. the links with an "alternate" relation type.
+    /// In RSS, that's the item's `<link>` element.
+    pub fn alternate_links(&self) -> impl Iterator<Item = &str> {
+        self.links
+            .iter()
+            .filter(|link| match link.rel.as_deref() {
+                None | Some("alternate" | "http://www.iana.org/assignments/relation/alternate") => {
+                    true
+                }
+                Some(_) => false,
+            })
+            // Feeds sometimes have whitespace around URLs.
+            .map(|link| link.href.trim())
+    }
+}
+
+impl From<feed_rs::model::Entry> for Entry {
+    fn from(entry: feed_rs::model::Entry) -> Entry {
+        Entry {
+            id: entry.id,
+            title: entry.title.map(|title| title.content),
+            links: entry.links.into_iter().map(Link::from).collect(),
+            // Atom entries only require an updated date.
+            published: entry.published.or(entry.updated),
+            categories: entry
+                .categories
+                .into_iter()
+                .map(|category| category.term)
+                .collect(),
+        }
+    }
+}
+
+impl From<feed_rs::model::Link> for Link {
+    fn from(link: feed_rs::model::Link) -> Link {
+        Link {
+            href: link.href,
+            rel: link.rel,
+            media_type: link.media_type,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FeedError(BoxError);
+
+impl Display for FeedError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "could not parse input as a feed:\n  {}",
+            Indented(&self.0)
+        )
+    }
+}
+
+impl Error for FeedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+/// The maximum number of characters of a response body
+/// to include in error messages.
+const SNIPPET_LENGTH: usize = 200;
+
+/// Returns `true` if a response looks like an HTML page
+/// based on its Content-Type or on the start of its body.
+pub fn looks_like_html(content_type: Option<&str>, body: &str) -> bool {
+    if let Some(content_type) = content_type {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        if media_type.eq_ignore_ascii_case("text/html")
+            || media_type.eq_ignore_ascii_case("application/xhtml+xml")
+        {
+            return true;
+        }
+    }
+
+    let start = body
+        .trim_start()
+        .chars()
+        .take(14)
+        .collect::<String>()
+        .to_ascii_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Extracts the title of an HTML page,
+/// preferring its `og:title` meta tag over its `<title>` element.
+pub fn html_title(html: &str) -> Option<String> {
+    // ASCII lowercasing preserves byte offsets.
+    let lower = html.to_ascii_lowercase();
+
+    let mut og_title = None;
+    let mut position = 0;
+    while let Some(start) = lower[position..].find("<meta").map(|i| position + i) {
+        let end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        let (tag, lower_tag) = (&html[start..end], &lower[start..end]);
+        if attribute(tag, lower_tag, "property").is_some_and(|x| x == "og:title") {
+            og_title = attribute(tag, lower_tag, "content");
+            break;
+        }
+        position = end;
+    }
+
+    let title = og_title.or_else(|| {
+        let start = lower.find("<title")?;
+        let start = start + lower[start..].find('>')? + 1;
+        let end = start + lower[start..].find("</title")?;
+        Some(&html[start..end])
+    })?;
+
+    let title = decode_entities(title)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+/// Extracts the canonical URL of an HTML page,
+/// preferring its `<link rel="canonical">` element over its `og:url` meta tag.
+/// The URL may be relative to the page's URL.
+pub fn html_canonical_url(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+
+    let mut og_url = None;
+    let mut position = 0;
+    while let Some(start) = lower[position..].find('<').map(|i| position + i) {
+        let end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        let (tag, lower_tag) = (&html[start..end], &lower[start..end]);
+        position = end;
+        if lower_tag.starts_with("<link")
+            && attribute(tag, lower_tag, "rel").is_some_and(|rel| {
+                rel.split_ascii_whitespace()
+                    .any(|x| x.eq_ignore_ascii_case("canonical"))
+            })
+        {
+            let href = attribute(tag, lower_tag, "href").map(str::trim);
+            if let Some(href) = href.filter(|href| !href.is_empty()) {
+                return Some(decode_entities(href));
+            }
+        } else if lower_tag.starts_with("<meta")
+            && og_url.is_none()
+            && attribute(tag, lower_tag, "property").is_some_and(|x| x == "og:url")
+        {
+            og_url = attribute(tag, lower_tag, "content");
+        } else if lower_tag.starts_with("</head") || lower_tag.starts_with("<body") {
+            break;
+        }
+    }
+
+    og_url
+        .map(|url| decode_entities(url.trim()))
+        .filter(|url| !url.is_empty())
+}
+
+/// Returns the value of an attribute in an HTML tag.
+/// `lower_tag` must be `tag` in ASCII lowercase.
+fn attribute<'a>(tag: &'a str, lower_tag: &str, name: &str) -> Option<&'a str> {
+    let mut position = 0;
+    loop {
+        let start = position + lower_tag[position..].find(name)?;
+        position = start + name.len();
+
+        // Make sure we found the whole attribute name.
+        if !lower_tag[..start].ends_with(|c: char| c.is_ascii_whitespace()) {
+            continue;
+        }
+        let rest = lower_tag[position..].trim_start();
+        let Some(rest) = rest.strip_prefix('=') else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let value_start = lower_tag.len() - rest.len();
+        return match rest.chars().next()? {
+            quote @ ('"' | '\'') => {
+                let value_end = value_start + 1 + rest[1..].find(quote)?;
+                Some(&tag[value_start + 1..value_end])
+            }
+            _ => {
+                let value_end = rest
+                    .find(|c: char| c.is_ascii_whitespace() || c == '/')
+                    .map_or(lower_tag.len(), |i| value_start + i);
+                Some(&tag[value_start..value_end])
+            }
+        };
+    }
+}
+
+/// Decodes the most common HTML character references.
+fn decode_entities(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = entity.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the start of a response body on a single line.
+pub fn snippet(body: &str) -> String {
+    let mut snippet = body
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(SNIPPET_LENGTH + 1)
+        .collect::<String>();
+    if snippet.chars().count() > SNIPPET_LENGTH {
+        snippet = snippet.chars().take(SNIPPET_LENGTH).collect();
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+#[derive(Debug)]
+pub struct NotAFeed {
+    pub content_type: Option<String>,
+    pub snippet: String,
+    /// The detailed parser errors, only reported in verbose mode.
+    pub feed_error: Option<FeedError>,
+}
+
+impl Display for NotAFeed {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "server returned an HTML page, not a feed (Content-Type: {})\n  body starts with: {}",
+            self.content_type.as_deref().unwrap_or("unknown"),
+            self.snippet
+        )?;
+        if let Some(feed_error) = &self.feed_error {
+            write!(fmt, "\n  {}", Indented(feed_error))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for NotAFeed {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.feed_error
+            .as_ref()
+            .map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATOM: &str = include_str!("../tests/fixtures/atom.xml");
+    const RSS: &str = include_str!("../tests/fixtures/rss.xml");
+    const RSS_1_0: &str = include_str!("../tests/fixtures/rss-1.0.xml");
+    const MALFORMED_RSS: &str = include_str!("../tests/fixtures/malformed-rss.xml");
+
+    fn entry_urls(feed: &Feed) -> Vec<&str> {
+        feed.entries
+            .iter()
+            .flat_map(|entry| entry.alternate_links())
+            .collect()
+    }
+
+    const EXAMPLE_URLS: [&str; 3] = [
+        "https://example.com/posts/3",
+        "https://example.com/posts/2",
+        "https://example.com/posts/1",
+    ];
+
+    #[test]
+    fn parse_atom() {
+        let feed = ATOM.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example Feed"));
+        assert_eq!(entry_urls(&feed), EXAMPLE_URLS);
+        assert_eq!(feed.entries[0].title.as_deref(), Some("Third post"));
+        assert_eq!(feed.entries[0].id, "https://example.com/posts/3");
+        assert_eq!(feed.entries[0].categories, ["news"]);
+        assert!(feed.entries[0].published.is_some());
+    }
+
+    #[test]
+    fn parse_rss() {
+        let feed = RSS.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example Feed"));
+        assert_eq!(entry_urls(&feed), EXAMPLE_URLS);
+        assert_eq!(feed.entries[0].title.as_deref(), Some("Third post"));
+        assert_eq!(feed.entries[0].id, "https://example.com/posts/3");
+        assert_eq!(feed.entries[0].categories, ["news"]);
+        assert!(feed.entries[0].published.is_some());
+    }
+
+    #[test]
+    fn parse_rss_1_0() {
+        let feed = RSS_1_0.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Example Feed"));
+        assert_eq!(entry_urls(&feed), EXAMPLE_URLS);
+        assert_eq!(feed.entries[0].title.as_deref(), Some("Third post"));
+    }
+
+    #[test]
+    fn self_link() {
+        let feed = ATOM.parse::<Feed>().unwrap();
+        assert_eq!(feed.self_link(), Some("https://example.com/atom.xml"));
+
+        let feed = RSS.parse::<Feed>().unwrap();
+        assert_eq!(feed.self_link(), None);
+
+        let rss = RSS
+            .replace(
+                r#"<rss version="2.0">"#,
+                r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">"#,
+            )
+            .replace(
+                "<description>",
+                r#"<atom:link href="https://example.com/rss.xml" rel="self" type="application/rss+xml"/>
+    <description>"#,
+            );
+        let feed = rss.parse::<Feed>().unwrap();
+        assert_eq!(feed.self_link(), Some("https://example.com/rss.xml"));
+    }
+
+    #[test]
+    fn resolve_xml_base_at_each_level() {
+        let atom = include_str!("../tests/fixtures/atom-xml-base.xml");
+        for large in [false, true] {
+            let feed = Feed::parse_at(atom, "https://example.com/feeds/atom.xml", large).unwrap();
+            assert_eq!(feed.self_link(), Some("https://example.com/blog/atom.xml"));
+            assert_eq!(
+                entry_urls(&feed),
+                [
+                    // Link base, relative to the feed base
+                    "https://example.com/blog/posts/3",
+                    // Entry base
+                    "https://mirror.example.com/blog/posts/2",
+                    // Feed base
+                    "https://example.com/posts/1",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_relative_urls_against_feed_url() {
+        let atom = ATOM
+            .replace("https://example.com/posts/3", "/posts/3")
+            .replace("https://example.com/posts/2", "//example.com/posts/2");
+        for large in [false, true] {
+            let feed = Feed::parse_at(&atom, "http://example.com/atom.xml", large).unwrap();
+            assert_eq!(
+                entry_urls(&feed),
+                [
+                    "http://example.com/posts/3",
+                    "http://example.com/posts/2",
+                    "https://example.com/posts/1",
+                ]
+            );
+
+            // There's nothing to resolve against.
+            let feed = Feed::parse_at(&atom, "exec:cat feed.xml", large).unwrap();
+            assert_eq!(entry_urls(&feed)[0], "/posts/3");
+        }
+    }
+
+    #[test]
+    fn parse_malformed_rss() {
+        // The channel has no description or link,
+        // items have no guid and there's whitespace around the links.
+        let feed = MALFORMED_RSS.parse::<Feed>().unwrap();
+        assert_eq!(entry_urls(&feed), EXAMPLE_URLS);
+    }
+
+    #[test]
+    fn ignore_links_with_other_relation_types() {
+        let atom = ATOM.replace(
+            r#"<link href="https://example.com/posts/2" rel="alternate"/>"#,
+            r#"<link href="https://example.com/posts/2" rel="alternate"/>
+    <link href="https://example.com/posts/2/comments" rel="replies"/>"#,
+        );
+        let feed = atom.parse::<Feed>().unwrap();
+        assert_eq!(entry_urls(&feed), EXAMPLE_URLS);
+    }
+
+    fn entry_ref_urls(feed: &Feed) -> Vec<&str> {
+        feed.entry_refs().iter().map(|entry| entry.url).collect()
+    }
+
+    #[test]
+    fn entry_refs_oldest_first() {
+        for s in [ATOM, RSS, RSS_1_0] {
+            let feed = s.parse::<Feed>().unwrap();
+            assert_eq!(
+                feed.entry_refs(),
+                [
+                    EntryRef {
+                        url: "https://example.com/posts/1",
+                        title: Some("First post"),
+                    },
+                    EntryRef {
+                        url: "https://example.com/posts/2",
+                        title: Some("Second post"),
+                    },
+                    EntryRef {
+                        url: "https://example.com/posts/3",
+                        title: Some("Third post"),
+                    },
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn entry_refs_skip_entries_without_links() {
+        let rss = RSS.replace("<link>https://example.com/posts/2</link>", "");
+        let feed = rss.parse::<Feed>().unwrap();
+        assert_eq!(
+            entry_ref_urls(&feed),
+            ["https://example.com/posts/1", "https://example.com/posts/3"]
+        );
+
+        let atom = ATOM.replace(
+            r#"<link href="https://example.com/posts/2" rel="alternate"/>"#,
+            r#"<link href="https://example.com/posts/2/comments" rel="replies"/>"#,
+        );
+        let feed = atom.parse::<Feed>().unwrap();
+        assert_eq!(
+            entry_ref_urls(&feed),
+            ["https://example.com/posts/1", "https://example.com/posts/3"]
+        );
+    }
+
+    #[test]
+    fn entry_refs_keep_the_order_of_links_in_an_entry() {
+        // Only the entries are reversed, not the links of each entry.
+        let atom = ATOM.replace(
+            r#"<link href="https://example.com/posts/2" rel="alternate"/>"#,
+            r#"<link href="https://example.com/posts/2" rel="alternate"/>
+    <link href="https://example.com/posts/2/comments" rel="replies"/>
+    <link href="https://mirror.example.com/posts/2" rel="alternate" type="text/html"/>"#,
+        );
+        for large in [false, true] {
+            let feed = Feed::parse_at(&atom, "https://example.com/atom.xml", large).unwrap();
+            let entry_refs = feed.entry_refs();
+            assert_eq!(
+                entry_refs.iter().map(|entry| entry.url).collect::<Vec<_>>(),
+                [
+                    "https://example.com/posts/1",
+                    "https://example.com/posts/2",
+                    "https://mirror.example.com/posts/2",
+                    "https://example.com/posts/3",
+                ]
+            );
+            assert_eq!(entry_refs[2].title, Some("Second post"));
+        }
+    }
+
+    #[test]
+    fn entry_refs_keep_duplicates() {
+        let atom = ATOM.replace(
+            "https://example.com/posts/1\"/>",
+            "https://example.com/posts/3\"/>",
+        );
+        let feed = atom.parse::<Feed>().unwrap();
+        assert_eq!(
+            entry_ref_urls(&feed),
+            [
+                "https://example.com/posts/3",
+                "https://example.com/posts/2",
+                "https://example.com/posts/3",
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_with_leading_blank_line() {
+        let atom = format!("\n{}", ATOM);
+        assert_eq!(entry_urls(&atom.parse().unwrap()), EXAMPLE_URLS);
+        let rss = format!("\r\n\n{}", RSS);
+        assert_eq!(entry_urls(&rss.parse().unwrap()), EXAMPLE_URLS);
+    }
+
+    #[test]
+    fn parse_with_byte_order_mark() {
+        let atom = format!("\u{feff}{}", ATOM);
+        assert_eq!(entry_urls(&atom.parse().unwrap()), EXAMPLE_URLS);
+        let rss = format!("\u{feff}{}", RSS);
+        assert_eq!(entry_urls(&rss.parse().unwrap()), EXAMPLE_URLS);
+    }
+
+    #[test]
+    fn parse_with_text_before_prolog() {
+        let rss = include_str!("../tests/fixtures/rss-with-leading-garbage.xml");
+        assert_eq!(entry_urls(&rss.parse().unwrap()), EXAMPLE_URLS);
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert!("Warning: no feed here".parse::<Feed>().is_err());
+        assert!("Warning: <b>no feed here</b>".parse::<Feed>().is_err());
+    }
+
+    #[test]
+    fn html_title_from_title_element() {
+        let html = "<html><head><TITLE>\n  Tom &amp; Jerry&#39;s  page\n</TITLE></head></html>";
+        assert_eq!(html_title(html).as_deref(), Some("Tom & Jerry's page"));
+    }
+
+    #[test]
+    fn html_title_prefers_og_title() {
+        let html = r#"<html><head>
+            <meta charset="utf-8">
+            <meta name="description" content="Not this">
+            <meta property='og:title' content='The &quot;real&quot; title' />
+            <title>Site name | The real title</title>
+        </head></html>"#;
+        assert_eq!(html_title(html).as_deref(), Some("The \"real\" title"));
+    }
+
+    #[test]
+    fn html_title_missing() {
+        assert_eq!(html_title("<html><body>no title</body></html>"), None);
+        assert_eq!(html_title("<title>  </title>"), None);
+    }
+
+    #[test]
+    fn html_canonical_url_prefers_link_element() {
+        let html = r#"<html><head>
+            <meta property="og:url" content="https://example.com/og">
+            <link rel="stylesheet" href="/style.css">
+            <LINK REL="Canonical" HREF="/posts/1?a=1&amp;b=2">
+        </head></html>"#;
+        assert_eq!(
+            html_canonical_url(html).as_deref(),
+            Some("/posts/1?a=1&b=2")
+        );
+    }
+
+    #[test]
+    fn html_canonical_url_from_og_url() {
+        let html =
+            r#"<head><meta property='og:url' content=' https://example.com/posts/1 '></head>"#;
+        assert_eq!(
+            html_canonical_url(html).as_deref(),
+            Some("https://example.com/posts/1")
+        );
+    }
+
+    #[test]
+    fn html_canonical_url_missing() {
+        assert_eq!(html_canonical_url("<html><head></head></html>"), None);
+        assert_eq!(
+            html_canonical_url(r#"<link rel="canonical" href="">"#),
+            None
+        );
+        // Only the head of the page counts.
+        let html = r#"<head></head><body><link rel="canonical" href="/other"></body>"#;
+        assert_eq!(html_canonical_url(html), None);
+    }
+
+    const LOGIN_PAGE: &str =
+        "<!DOCTYPE html>\n<html>\n  <head><title>Sign in</title></head>\n</html>";
+
+    #[test]
+    fn looks_like_html_by_content_type() {
+        assert!(looks_like_html(Some("text/html; charset=utf-8"), "<rss/>"));
+        assert!(looks_like_html(Some("application/xhtml+xml"), "<rss/>"));
+        assert!(!looks_like_html(Some("application/rss+xml"), RSS));
+        assert!(!looks_like_html(None, ATOM));
+    }
+
+    #[test]
+    fn looks_like_html_by_sniffing() {
+        assert!(looks_like_html(None, LOGIN_PAGE));
+        assert!(looks_like_html(
+            Some("text/xml"),
+            "\n  <!doctype html><html>"
+        ));
+        assert!(looks_like_html(None, "<HTML><body></body></HTML>"));
+    }
+
+    #[test]
+    fn snippet_is_truncated_on_a_single_line() {
+        assert_eq!(
+            snippet(LOGIN_PAGE),
+            "<!DOCTYPE html> <html> <head><title>Sign in</title></head> </html>"
+        );
+        let body = "a".repeat(SNIPPET_LENGTH);
+        assert_eq!(snippet(&body), body);
+        let body = "b".repeat(SNIPPET_LENGTH + 1);
+        assert_eq!(snippet(&body), format!("{}…", "b".repeat(SNIPPET_LENGTH)));
+    }
+
+    #[test]
+    fn not_a_feed_only_shows_parser_errors_when_verbose() {
+        let not_a_feed = |verbose| NotAFeed {
+            content_type: Some("text/html".into()),
+            snippet: snippet(LOGIN_PAGE),
+            feed_error: LOGIN_PAGE.parse::<Feed>().err().filter(|_| verbose),
+        };
+        let message = not_a_feed(false).to_string();
+        assert_eq!(
+            message,
+            "server returned an HTML page, not a feed (Content-Type: text/html)\n  \
+             body starts with: <!DOCTYPE html> <html> <head><title>Sign in</title></head> </html>"
+        );
+        let message = not_a_feed(true).to_string();
+        assert!(
+            message.contains("\n  could not parse input as a feed:"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,2203 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::iter;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::GzDecoder;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{redirect, Certificate, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use url::Url;
+
+use crate::error::{
+    BoxError, CommandError, ErrorWithContext, InvalidResolveOverride, InvalidUtf8, RedirectError,
+    ResolveError, ResponseTooLarge, UnacceptableHttpStatus, UnknownCharset, UnsupportedScheme,
+};
+use crate::feed::{html_canonical_url, html_title, looks_like_html};
+use crate::proxy;
+use crate::robots::{product_token, RobotsRules};
+use crate::{Configuration, FeedConfiguration};
+
+/// The default minimum delay between two requests to the same host.
+pub const DEFAULT_HOST_DELAY: Duration = Duration::from_millis(1000);
+
+/// The default maximum size of a response body, in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The default maximum number of redirects to follow for a request.
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// The default maximum time a feed command may run.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The default number of times to retry downloading a feed after a network error.
+pub const DEFAULT_FETCH_RETRIES: u32 = 2;
+
+/// The default delay before the first retry of a feed download.
+pub const DEFAULT_FETCH_RETRY_DELAY: Duration = Duration::from_millis(1000);
+
+/// The bytes at the start of gzip data.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// The maximum number of gzip layers to remove from a response body.
+const MAX_GZIP_LAYERS: usize = 2;
+
+/// The number of bytes at the start of a body to show
+/// when the body isn't valid UTF-8.
+const UTF8_ERROR_DUMP_SIZE: usize = 16;
+
+/// The maximum number of bytes to read from a web page
+/// to find its title or canonical URL.
+const MAX_TITLE_BODY_SIZE: u64 = 256 * 1024;
+
+/// The maximum time to wait for a response when checking if a link is dead.
+const LINK_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum number of bytes to read from a robots.txt file.
+const MAX_ROBOTS_TXT_SIZE: u64 = 512 * 1024;
+
+/// The maximum number of bytes of a feed command's standard error
+/// to show when the command fails.
+const MAX_COMMAND_STDERR_SIZE: u64 = 64 * 1024;
+
+/// The User-Agent sent when none is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!("feeds-to-pocket/", env!("CARGO_PKG_VERSION"));
+
+/// The media types we accept for feeds, in order of preference.
+const ACCEPT: &str = "application/atom+xml, application/rss+xml, application/xml;q=0.9, */*;q=0.8";
+
+pub enum FeedResponse {
+    Success {
+        body: String,
+        content_type: Option<String>,
+        last_modified: Option<String>,
+        e_tag: Option<String>,
+        /// Until when the response may be reused without a new request,
+        /// according to its Cache-Control header.
+        fresh_until: Option<DateTime<Utc>>,
+        details: FetchDetails,
+    },
+    NotModified {
+        fresh_until: Option<DateTime<Utc>>,
+        /// The ETag sent with the 304 response, if any.
+        e_tag: Option<String>,
+        details: FetchDetails,
+    },
+}
+
+impl FeedResponse {
+    pub fn details(&self) -> &FetchDetails {
+        match self {
+            FeedResponse::Success { details, .. } | FeedResponse::NotModified { details, .. } => {
+                details
+            }
+        }
+    }
+}
+
+/// How a feed was downloaded, for the verbose output.
+pub struct FetchDetails {
+    /// The URL of the response after following redirects (HTTP feeds only).
+    pub final_url: Option<String>,
+    /// The status of the response (HTTP feeds only).
+    pub status: Option<StatusCode>,
+    /// The size of the body as it was received, before decompression.
+    pub size: usize,
+    /// The time taken by the request, not counting the delays between requests.
+    pub elapsed: Duration,
+}
+
+/// Where a feed is read from.
+pub enum FeedSource {
+    Http,
+    File(PathBuf),
+    /// A command (program and arguments) whose output is the feed.
+    Exec(Vec<String>),
+    /// A URL whose scheme can't be read from, e.g. `gemini:`.
+    Unsupported(String),
+}
+
+impl FeedSource {
+    /// Determines the source of a feed from its configured URL,
+    /// which can be an HTTP(S) URL, a `file://` URL, a local path
+    /// or an `exec:` URL followed by a command.
+    pub fn of(feed_url: &str) -> FeedSource {
+        if let Some(command) = feed_url
+            .strip_prefix("exec://")
+            .or_else(|| feed_url.strip_prefix("exec:"))
+        {
+            return FeedSource::Exec(command.split_whitespace().map(String::from).collect());
+        }
+
+        match Url::parse(feed_url) {
+            Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+                Ok(path) => FeedSource::File(path),
+                // Let the HTTP client report the error.
+                Err(()) => FeedSource::Http,
+            },
+            // A Windows path with a drive letter parses as a URL
+            // with a single-letter scheme.
+            Ok(url) if url.scheme().len() == 1 => FeedSource::File(feed_url.into()),
+            Ok(url) if matches!(url.scheme(), "http" | "https") => FeedSource::Http,
+            Ok(url) => FeedSource::Unsupported(url.scheme().to_owned()),
+            Err(url::ParseError::RelativeUrlWithoutBase) => FeedSource::File(feed_url.into()),
+            Err(_) => FeedSource::Http,
+        }
+    }
+}
+
+/// Downloads feeds.
+///
+/// A single `Client` is shared by all requests
+/// so that connections to the same host can be reused.
+/// The requests run on the Tokio runtime
+/// that was current when the `Fetcher` was created;
+/// the `Fetcher`'s methods block until the requests complete.
+pub struct Fetcher {
+    runtime: Handle,
+    client: Client,
+    /// Clients for feeds that need specific client options.
+    clients: HashMap<ClientOptions, Client>,
+    host_throttle: HostThrottle,
+    max_body_size: u64,
+    command_timeout: Duration,
+    max_redirects: usize,
+    fetch_retries: u32,
+    fetch_retry_delay: Duration,
+    user_agent: Option<String>,
+    prefer_ipv4: bool,
+    force_ipv4: bool,
+    respect_robots_txt: bool,
+    /// The robots.txt rules for each origin and User-Agent product token,
+    /// fetched at most once per run.
+    robots_rules: HashMap<(String, String), RobotsRules>,
+    /// The number of bytes received in response bodies.
+    downloaded_bytes: u64,
+    force: bool,
+    /// Don't send requests for entries (titles and link checks).
+    offline: bool,
+}
+
+impl Fetcher {
+    pub fn new(config: &Configuration) -> Result<Fetcher, ErrorWithContext> {
+        let host_delay = config
+            .host_delay_ms
+            .map_or(DEFAULT_HOST_DELAY, Duration::from_millis);
+        let max_redirects = config.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+        let client = try_with_context!(
+            ClientOptions::shared(config.prefer_ipv4, config.force_ipv4)
+                .build_client(max_redirects),
+            "failed to configure the HTTP client"
+        );
+
+        Ok(Fetcher {
+            runtime: Handle::current(),
+            client,
+            clients: HashMap::new(),
+            host_throttle: HostThrottle::new(host_delay),
+            max_body_size: config.max_body_size.unwrap_or(DEFAULT_MAX_BODY_SIZE),
+            command_timeout: config
+                .command_timeout_secs
+                .map_or(DEFAULT_COMMAND_TIMEOUT, Duration::from_secs),
+            max_redirects,
+            fetch_retries: config.fetch_retries.unwrap_or(DEFAULT_FETCH_RETRIES),
+            fetch_retry_delay: config
+                .fetch_retry_delay_ms
+                .map_or(DEFAULT_FETCH_RETRY_DELAY, Duration::from_millis),
+            user_agent: config.user_agent.clone(),
+            prefer_ipv4: config.prefer_ipv4,
+            force_ipv4: config.force_ipv4,
+            respect_robots_txt: config.respect_robots_txt,
+            robots_rules: HashMap::new(),
+            downloaded_bytes: 0,
+            force: false,
+            offline: false,
+        })
+    }
+
+    /// Makes `is_fresh` return false for all feeds.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Makes `fetch_title`, `fetch_canonical_url` and `dead_link_status`
+    /// skip their requests, for `sync --offline`.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// Returns the maximum time a command (feed or filter) may run.
+    pub fn command_timeout(&self) -> Duration {
+        self.command_timeout
+    }
+
+    /// Returns the number of bytes received in response bodies so far.
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.downloaded_bytes
+    }
+
+    /// Returns true if the last response for the feed can be reused,
+    /// i.e. if the feed doesn't need to be fetched.
+    pub fn is_fresh(&self, feed: &FeedConfiguration) -> bool {
+        !self.force
+            && feed
+                .fresh_until
+                .is_some_and(|fresh_until| fresh_until > Utc::now())
+    }
+
+    pub fn fetch(&mut self, feed: &FeedConfiguration) -> Result<FeedResponse, ErrorWithContext> {
+        match FeedSource::of(&feed.url) {
+            FeedSource::Http => self.fetch_http(feed, true),
+            FeedSource::File(path) => self.fetch_file(feed, &path),
+            FeedSource::Exec(command) => self.fetch_exec(feed, &command),
+            // Fail before the HTTP client reports a confusing error.
+            FeedSource::Unsupported(scheme) => try_with_context!(
+                Err(UnsupportedScheme::UnsupportedScheme(scheme)),
+                format!("unable to read feed {}", feed.url)
+            ),
+        }
+    }
+
+    /// Downloads a feed without sending the validators from the last response,
+    /// for servers that answer 304 Not Modified even though the feed changed.
+    pub fn fetch_unconditionally(
+        &mut self,
+        feed: &FeedConfiguration,
+    ) -> Result<FeedResponse, ErrorWithContext> {
+        match FeedSource::of(&feed.url) {
+            FeedSource::Http => self.fetch_http(feed, false),
+            _ => self.fetch(feed),
+        }
+    }
+
+    /// Downloads a feed over HTTP,
+    /// retrying with an increasing delay after transient network errors.
+    /// If `conditional` is true, the request includes the validators from the last response.
+    fn fetch_http(
+        &mut self,
+        feed: &FeedConfiguration,
+        conditional: bool,
+    ) -> Result<FeedResponse, ErrorWithContext> {
+        let mut attempts = 1;
+        let mut delay = self.fetch_retry_delay;
+        loop {
+            match self.fetch_http_once(feed, conditional) {
+                Err(error) if is_transient(&error) => {
+                    if attempts > self.fetch_retries {
+                        if attempts == 1 {
+                            return Err(error);
+                        }
+
+                        return Err(ErrorWithContext::new(
+                            Box::new(error),
+                            format!("giving up on <{}> after {} attempts", feed.url, attempts),
+                        ));
+                    }
+
+                    debug!(
+                        "{}\nretrying <{}> in {:?} (attempt {} of {})",
+                        error,
+                        feed.url,
+                        delay,
+                        attempts + 1,
+                        self.fetch_retries + 1
+                    );
+                    thread::sleep(delay);
+                    attempts += 1;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn fetch_http_once(
+        &mut self,
+        feed: &FeedConfiguration,
+        conditional: bool,
+    ) -> Result<FeedResponse, ErrorWithContext> {
+        // Wait a bit if we've sent a request to the same host recently.
+        if let Ok(url) = Url::parse(&feed.url) {
+            if self.respect_robots_txt {
+                // This sets the host's crawl delay.
+                self.robots_rules(feed, &url);
+            }
+            self.host_throttle.wait(&url);
+            proxy::log_request(&url);
+        }
+
+        let start = Instant::now();
+        let client = self.client_for(feed)?;
+        let mut request = client.get(&feed.url);
+        request = request.header(header::USER_AGENT, self.user_agent_for(feed)?);
+        request = request.header(header::ACCEPT, HeaderValue::from_static(ACCEPT));
+
+        if conditional {
+            request = request.headers(conditional_headers(feed)?);
+        }
+
+        let response = match self.runtime.block_on(request.send()) {
+            Ok(response) => response,
+            Err(error) => {
+                // reqwest's error message doesn't include
+                // the error returned by our redirect policy.
+                if let Some(redirect_error) = error
+                    .source()
+                    .and_then(|source| source.downcast_ref::<RedirectError>())
+                {
+                    try_with_context!(Err(redirect_error.clone()), "failed to send request");
+                }
+
+                // Nor does it include the error returned by our resolver,
+                // which tells DNS problems apart from connection problems.
+                let mut sources = iter::successors(error.source(), |&source| source.source());
+                if let Some(resolve_error) =
+                    sources.find_map(|source| source.downcast_ref::<ResolveError>())
+                {
+                    try_with_context!(
+                        Err(resolve_error.clone()),
+                        format!("failed to resolve the host of <{}>", feed.url)
+                    );
+                }
+
+                let context = if error.is_connect() {
+                    format!("failed to connect to <{}>", feed.url)
+                } else {
+                    "failed to send request".into()
+                };
+                try_with_context!(Err(error), context)
+            }
+        };
+        let fresh_until = fresh_until(response.headers(), Utc::now());
+        let final_url = Some(response.url().to_string());
+        let status = Some(response.status());
+        if response.status() == StatusCode::NOT_MODIFIED {
+            // The server may send a new ETag along with a 304 response.
+            let e_tag = response
+                .headers()
+                .get(header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
+            Ok(FeedResponse::NotModified {
+                fresh_until,
+                e_tag,
+                details: FetchDetails {
+                    final_url,
+                    status,
+                    size: 0,
+                    elapsed: start.elapsed(),
+                },
+            })
+        } else {
+            if !response.status().is_success() {
+                try_with_context!(
+                    Err(UnacceptableHttpStatus::UnacceptableHttpStatus(
+                        response.status()
+                    )),
+                    format!(
+                        "the HTTP request to <{}> didn't return a success status",
+                        feed.url
+                    )
+                );
+            }
+
+            let header_str = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|v: &HeaderValue| v.to_str().ok())
+                    .map(String::from)
+            };
+            let content_type = header_str(header::CONTENT_TYPE);
+            let last_modified = header_str(header::LAST_MODIFIED);
+            let e_tag = header_str(header::ETAG);
+
+            let content_length = response.content_length();
+            if let Some(content_length) = content_length {
+                self.check_body_size(content_length, &feed.url)?;
+            }
+
+            let body = try_with_context!(
+                self.receive_body(response, self.max_body_size),
+                format!("failed to read the response from <{}>", feed.url)
+            );
+            let size = body.len();
+            let body = self.read_body(
+                &mut &body[..],
+                content_length,
+                content_type.as_deref(),
+                feed,
+            )?;
+
+            Ok(FeedResponse::Success {
+                body,
+                content_type,
+                last_modified,
+                e_tag,
+                fresh_until,
+                details: FetchDetails {
+                    final_url,
+                    status,
+                    size,
+                    elapsed: start.elapsed(),
+                },
+            })
+        }
+    }
+
+    /// Fetches the title of an entry's web page.
+    /// The requests use the same client and User-Agent as the feed
+    /// and are throttled like feed requests.
+    pub fn fetch_title(
+        &mut self,
+        feed: &FeedConfiguration,
+        url: &Url,
+    ) -> Result<Option<String>, ErrorWithContext> {
+        Ok(self
+            .fetch_page_head(feed, url)?
+            .and_then(|(_, html)| html_title(&html)))
+    }
+
+    /// Fetches the canonical URL of an entry's web page,
+    /// from its `<link rel="canonical">` element or its `og:url` meta tag.
+    /// Only HTTP and HTTPS URLs are returned.
+    pub fn fetch_canonical_url(
+        &mut self,
+        feed: &FeedConfiguration,
+        url: &Url,
+    ) -> Result<Option<Url>, ErrorWithContext> {
+        let Some((final_url, html)) = self.fetch_page_head(feed, url)? else {
+            return Ok(None);
+        };
+        Ok(html_canonical_url(&html)
+            // The canonical URL may be relative to the page, after redirects.
+            .and_then(|canonical_url| final_url.join(&canonical_url).ok())
+            .filter(|canonical_url| matches!(canonical_url.scheme(), "http" | "https")))
+    }
+
+    /// Downloads the start of a web page, if it's an HTML page,
+    /// and returns its URL after redirects and its text.
+    /// Returns `None` when offline.
+    fn fetch_page_head(
+        &mut self,
+        feed: &FeedConfiguration,
+        url: &Url,
+    ) -> Result<Option<(Url, String)>, ErrorWithContext> {
+        if self.offline {
+            return Ok(None);
+        }
+        let client = self.client_for(feed)?;
+        let user_agent = self.user_agent_for(feed)?;
+
+        // Check that the page is an HTML page before downloading it.
+        // Some servers don't support HEAD requests,
+        // so only skip the page if we get a successful response.
+        proxy::log_request(url);
+        self.host_throttle.wait(url);
+        let head = self.runtime.block_on(
+            client
+                .head(url.clone())
+                .header(header::USER_AGENT, user_agent.clone())
+                .send(),
+        );
+        if let Ok(head) = head {
+            let content_type = head
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok());
+            if head.status().is_success() && !looks_like_html(content_type, "") {
+                return Ok(None);
+            }
+        }
+
+        self.host_throttle.wait(url);
+        let request = client
+            .get(url.clone())
+            .header(header::USER_AGENT, user_agent);
+        let response = try_with_context!(
+            self.runtime
+                .block_on(request.send())
+                .and_then(|response| response.error_for_status()),
+            format!("failed to download <{}>", url)
+        );
+        let final_url = response.url().clone();
+
+        // The title and canonical URL are normally near the start of the page,
+        // so don't download the whole page if it's large.
+        let mut body = try_with_context!(
+            self.receive_body(response, MAX_TITLE_BODY_SIZE),
+            format!("failed to read <{}>", url)
+        );
+        body.truncate(MAX_TITLE_BODY_SIZE as usize);
+        Ok(Some((
+            final_url,
+            String::from_utf8_lossy(&body).into_owned(),
+        )))
+    }
+
+    /// Checks if an entry's web page is gone,
+    /// returning the status of the response if it's 404 Not Found or 410 Gone.
+    ///
+    /// A HEAD request is sent first; if the server rejects it,
+    /// a GET request for the first byte of the page is sent instead.
+    /// Errors, timeouts and other statuses (including server errors)
+    /// don't make a link dead, as the page may only be temporarily unavailable.
+    pub fn dead_link_status(&mut self, feed: &FeedConfiguration, url: &Url) -> Option<StatusCode> {
+        if self.offline {
+            return None;
+        }
+        let client = self.client_for(feed).ok()?;
+        let user_agent = self.user_agent_for(feed).ok()?;
+
+        proxy::log_request(url);
+        self.host_throttle.wait(url);
+        let head = client
+            .head(url.clone())
+            .header(header::USER_AGENT, user_agent.clone())
+            .timeout(LINK_CHECK_TIMEOUT)
+            .send();
+        let mut status = self.runtime.block_on(head).ok()?.status();
+        if status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED {
+            self.host_throttle.wait(url);
+            let get = client
+                .get(url.clone())
+                .header(header::USER_AGENT, user_agent)
+                .header(header::RANGE, "bytes=0-0")
+                .timeout(LINK_CHECK_TIMEOUT)
+                .send();
+            status = self.runtime.block_on(get).ok()?.status();
+        }
+
+        Some(status).filter(|&status| status == StatusCode::NOT_FOUND || status == StatusCode::GONE)
+    }
+
+    /// Returns false if `respect_robots_txt` is set
+    /// and the robots.txt file of the feed's host disallows the feed's URL.
+    pub fn is_allowed_by_robots_txt(&mut self, feed: &FeedConfiguration) -> bool {
+        if !self.respect_robots_txt || !matches!(FeedSource::of(&feed.url), FeedSource::Http) {
+            return true;
+        }
+
+        match Url::parse(&feed.url) {
+            Ok(url) => {
+                let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+                self.robots_rules(feed, &url).is_allowed(path)
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Returns the robots.txt rules for a URL,
+    /// downloading the host's robots.txt file if it wasn't downloaded yet.
+    ///
+    /// If robots.txt can't be downloaded, everything is allowed.
+    fn robots_rules(&mut self, feed: &FeedConfiguration, url: &Url) -> &RobotsRules {
+        let user_agent = self.user_agent_for(feed).ok();
+        let user_agent = user_agent
+            .as_ref()
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .unwrap_or(DEFAULT_USER_AGENT)
+            .to_owned();
+        let key = (
+            url.origin().ascii_serialization(),
+            product_token(&user_agent),
+        );
+        if !self.robots_rules.contains_key(&key) {
+            let rules = match self.fetch_robots_txt(feed, url) {
+                Ok(Some(robots_txt)) => RobotsRules::parse(&robots_txt, &user_agent),
+                Ok(None) => RobotsRules::default(),
+                Err(e) => {
+                    debug!("{}", e);
+                    RobotsRules::default()
+                }
+            };
+
+            if let (Some(crawl_delay), Some(host)) = (rules.crawl_delay(), url.host_str()) {
+                debug!("{} asks for a crawl delay of {:?}", host, crawl_delay);
+                self.host_throttle.set_crawl_delay(host, crawl_delay);
+            }
+            self.robots_rules.insert(key.clone(), rules);
+        }
+
+        &self.robots_rules[&key]
+    }
+
+    /// Downloads the robots.txt file of a URL's origin.
+    /// Returns None if there's no robots.txt file.
+    fn fetch_robots_txt(
+        &mut self,
+        feed: &FeedConfiguration,
+        url: &Url,
+    ) -> Result<Option<String>, ErrorWithContext> {
+        let robots_url = try_with_context!(url.join("/robots.txt"), "invalid robots.txt URL");
+        let client = self.client_for(feed)?;
+        let user_agent = self.user_agent_for(feed)?;
+
+        proxy::log_request(&robots_url);
+        self.host_throttle.wait(&robots_url);
+        let request = client
+            .get(robots_url.clone())
+            .header(header::USER_AGENT, user_agent);
+        let response = try_with_context!(
+            self.runtime.block_on(request.send()),
+            format!("failed to download <{}>", robots_url)
+        );
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+
+        let mut body = try_with_context!(
+            self.receive_body(response, MAX_ROBOTS_TXT_SIZE),
+            format!("failed to read <{}>", robots_url)
+        );
+        body.truncate(MAX_ROBOTS_TXT_SIZE as usize);
+        Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Returns the User-Agent to send when downloading the feed.
+    fn user_agent_for(&self, feed: &FeedConfiguration) -> Result<HeaderValue, ErrorWithContext> {
+        let user_agent = feed
+            .user_agent
+            .as_deref()
+            .or(self.user_agent.as_deref())
+            .unwrap_or(DEFAULT_USER_AGENT);
+        Ok(try_with_context!(
+            HeaderValue::from_str(user_agent),
+            "invalid user agent"
+        ))
+    }
+
+    /// Returns a client configured with the feed's client options.
+    fn client_for(&mut self, feed: &FeedConfiguration) -> Result<Client, ErrorWithContext> {
+        let options = ClientOptions::of(feed, self.prefer_ipv4, self.force_ipv4);
+        if options == ClientOptions::shared(self.prefer_ipv4, self.force_ipv4) {
+            return Ok(self.client.clone());
+        }
+
+        if let Some(client) = self.clients.get(&options) {
+            return Ok(client.clone());
+        }
+
+        if options.danger_accept_invalid_certs {
+            eprintln!(
+                "WARNING: certificate validation is disabled for feed {}. \
+                Anyone on the network path could tamper with this feed!",
+                feed.url
+            );
+        }
+
+        let client = try_with_context!(
+            options.build_client(self.max_redirects),
+            format!("failed to configure the HTTP client for feed {}", feed.url)
+        );
+        self.clients.insert(options, client.clone());
+        Ok(client)
+    }
+
+    /// Reads a feed from a local file.
+    /// The file's modification time takes the place of the Last-Modified header.
+    fn fetch_file(
+        &mut self,
+        feed: &FeedConfiguration,
+        path: &Path,
+    ) -> Result<FeedResponse, ErrorWithContext> {
+        let start = Instant::now();
+        let mut file = try_with_context!(
+            File::open(path),
+            format!("failed to open file {}", path.display())
+        );
+        let metadata = try_with_context!(
+            file.metadata(),
+            format!("failed to read metadata of file {}", path.display())
+        );
+
+        // Use a precise timestamp,
+        // as a file can be modified more than once per second.
+        let modified = metadata.modified().ok().map(|modified| {
+            DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Nanos, true)
+        });
+        if modified.is_some() && modified == feed.last_modified {
+            return Ok(FeedResponse::NotModified {
+                fresh_until: None,
+                e_tag: None,
+                details: FetchDetails {
+                    final_url: None,
+                    status: None,
+                    size: 0,
+                    elapsed: start.elapsed(),
+                },
+            });
+        }
+
+        let body = self.read_body(&mut file, Some(metadata.len()), None, feed)?;
+
+        Ok(FeedResponse::Success {
+            body,
+            content_type: None,
+            last_modified: modified,
+            e_tag: None,
+            fresh_until: None,
+            details: FetchDetails {
+                final_url: None,
+                status: None,
+                size: metadata.len() as usize,
+                elapsed: start.elapsed(),
+            },
+        })
+    }
+
+    /// Runs a command and reads the feed from its standard output.
+    /// There's no equivalent to the HTTP validators for commands,
+    /// so the output is always processed.
+    fn fetch_exec(
+        &mut self,
+        feed: &FeedConfiguration,
+        command: &[String],
+    ) -> Result<FeedResponse, ErrorWithContext> {
+        let start = Instant::now();
+        let (program, args) = match command.split_first() {
+            Some(split) => split,
+            None => try_with_context!(
+                Err(CommandError::MissingCommand),
+                format!("failed to run the command for feed {}", feed.url)
+            ),
+        };
+
+        let mut child = try_with_context!(
+            Command::new(program)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn(),
+            format!("failed to run {}", program)
+        );
+
+        // Read the outputs on separate threads
+        // so that the child doesn't block on a full pipe
+        // while we wait for it to exit.
+        let max_body_size = self.max_body_size;
+        let mut stdout = child.stdout.take().unwrap();
+        let stdout_reader = thread::spawn(move || read_pipe(&mut stdout, max_body_size));
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr_reader = thread::spawn(move || {
+            read_pipe(&mut stderr, MAX_COMMAND_STDERR_SIZE).unwrap_or_default()
+        });
+
+        let status = wait_with_timeout(&mut child, self.command_timeout);
+        let stdout = stdout_reader.join().expect("stdout reader thread panicked");
+        let mut stderr = stderr_reader.join().expect("stderr reader thread panicked");
+        stderr.truncate(MAX_COMMAND_STDERR_SIZE as usize);
+
+        // A command that prints too much may also run until it's killed.
+        if stdout
+            .as_ref()
+            .is_ok_and(|stdout| stdout.len() as u64 > max_body_size)
+        {
+            try_with_context!(
+                Err(ResponseTooLarge::ResponseTooLarge(max_body_size)),
+                format!(
+                    "failed to read the output of the command for feed {}",
+                    feed.url
+                )
+            );
+        }
+        let status = try_with_context!(status, format!("failed to run {}", program));
+
+        if !status.success() {
+            try_with_context!(
+                Err(CommandError::Failed(
+                    status,
+                    String::from_utf8_lossy(&stderr).trim().into()
+                )),
+                format!("the command for feed {} failed", feed.url)
+            );
+        }
+
+        let stdout = try_with_context!(stdout, "failed to read the command's output");
+        let body = self.read_body(&mut &stdout[..], None, None, feed)?;
+
+        Ok(FeedResponse::Success {
+            body,
+            content_type: None,
+            last_modified: None,
+            e_tag: None,
+            fresh_until: None,
+            details: FetchDetails {
+                final_url: None,
+                status: None,
+                size: stdout.len(),
+                elapsed: start.elapsed(),
+            },
+        })
+    }
+
+    /// Receives a response body with `read_response`
+    /// and counts the bytes received.
+    fn receive_body(&mut self, response: Response, limit: u64) -> Result<Vec<u8>, reqwest::Error> {
+        let body = self.runtime.block_on(read_response(response, limit))?;
+        self.downloaded_bytes += body.len() as u64;
+        Ok(body)
+    }
+
+    /// Reads a response body,
+    /// failing if it's larger than the maximum body size,
+    /// and decodes it (see `body_encoding`).
+    fn read_body(
+        &self,
+        reader: &mut dyn Read,
+        content_length: Option<u64>,
+        content_type: Option<&str>,
+        feed: &FeedConfiguration,
+    ) -> Result<String, ErrorWithContext> {
+        let feed_url = &feed.url;
+        if let Some(content_length) = content_length {
+            self.check_body_size(content_length, feed_url)?;
+        }
+
+        let mut body = try_with_context!(
+            read_limited(reader, self.max_body_size),
+            format!("failed to read the response from <{}>", feed_url)
+        );
+
+        // Some feeds are served as gzip files
+        // rather than with a Content-Encoding,
+        // and some servers compress an already compressed file.
+        let mut layers = 0;
+        while body.starts_with(GZIP_MAGIC) {
+            layers += 1;
+            if layers > MAX_GZIP_LAYERS {
+                try_with_context!(
+                    Err(format!(
+                        "the response is compressed more than {} times",
+                        MAX_GZIP_LAYERS
+                    )),
+                    format!("failed to decompress the response from <{}>", feed_url)
+                );
+            }
+
+            debug!("decompressing gzip data from <{}>", feed_url);
+            body = try_with_context!(
+                read_limited(&mut GzDecoder::new(&body[..]), self.max_body_size),
+                format!("failed to decompress the response from <{}>", feed_url)
+            );
+        }
+
+        let (encoding, source) = try_with_context!(
+            body_encoding(feed.charset.as_deref(), content_type, &body),
+            format!("failed to decode the response from <{}>", feed_url)
+        );
+        if encoding != UTF_8 {
+            debug!(
+                "decoding the response from <{}> as {} (from {})",
+                feed_url,
+                encoding.name(),
+                source
+            );
+            let (decoded, had_errors) = encoding.decode_with_bom_removal(&body);
+            if had_errors {
+                debug!(
+                    "the response from <{}> has invalid {} sequences",
+                    feed_url,
+                    encoding.name()
+                );
+            }
+            return Ok(declare_utf8(decoded.into_owned()));
+        }
+
+        match String::from_utf8(body) {
+            Ok(body) => Ok(declare_utf8(body)),
+            Err(error) => {
+                let offset = error.utf8_error().valid_up_to();
+                let body = error.into_bytes();
+                try_with_context!(
+                    Err(InvalidUtf8::InvalidUtf8(
+                        offset,
+                        content_type.map(String::from),
+                        hex_dump(&body[..body.len().min(UTF8_ERROR_DUMP_SIZE)]),
+                    )),
+                    format!("the response from <{}> is not valid UTF-8", feed_url)
+                )
+            }
+        }
+    }
+
+    /// Fails if a body of `content_length` bytes is larger than the maximum body size,
+    /// so we don't even start downloading a body that we know is too large.
+    fn check_body_size(&self, content_length: u64, feed_url: &str) -> Result<(), ErrorWithContext> {
+        if content_length > self.max_body_size {
+            try_with_context!(
+                Err(ResponseTooLarge::ResponseTooLarge(self.max_body_size)),
+                format!("the response from <{}> is too large", feed_url)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the character encoding of a feed's body,
+/// and where it comes from.
+///
+/// The feed's `charset` wins, for servers that get everything wrong.
+/// Then a byte order mark wins, since it can't be mistaken,
+/// then the charset of the Content-Type header,
+/// as the HTTP header takes precedence over the XML declaration
+/// (see RFC 7303, section 3),
+/// then the encoding of the XML declaration.
+/// Feeds without any of those are UTF-8.
+fn body_encoding(
+    charset: Option<&str>,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<(&'static Encoding, &'static str), UnknownCharset> {
+    if let Some(charset) = charset {
+        return match Encoding::for_label(charset.as_bytes()) {
+            Some(encoding) => Ok((encoding, "the feed's charset")),
+            None => Err(UnknownCharset::UnknownCharset(charset.into())),
+        };
+    }
+
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return Ok((encoding, "the byte order mark"));
+    }
+
+    // Servers and feeds sometimes give made-up names; ignore those.
+    let header_charset = content_type.and_then(|content_type| {
+        content_type
+            .split(';')
+            .skip(1)
+            .filter_map(|parameter| parameter.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+    });
+    if let Some(encoding) = header_charset {
+        return Ok((encoding, "the Content-Type header"));
+    }
+
+    let declared = declared_encoding(body).and_then(|range| Encoding::for_label(&body[range]));
+    if let Some(encoding) = declared {
+        return Ok((encoding, "the XML declaration"));
+    }
+
+    Ok((UTF_8, "the default"))
+}
+
+/// Returns the position of the value of the encoding in the XML declaration,
+/// if the body starts with an XML declaration that has one.
+fn declared_encoding(body: &[u8]) -> Option<Range<usize>> {
+    let start = if body.starts_with(b"\xef\xbb\xbf") {
+        3
+    } else {
+        0
+    };
+    let start = start
+        + body[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_whitespace())
+            .count();
+    if !body[start..].starts_with(b"<?xml") {
+        return None;
+    }
+
+    let end = start + body[start..].windows(2).position(|x| x == b"?>")?;
+    let declaration = &body[start..end];
+    let name = declaration
+        .windows(b"encoding".len())
+        .position(|x| x == b"encoding")?;
+    let mut i = name + b"encoding".len();
+    let skip_whitespace = |i: &mut usize| {
+        while declaration.get(*i).is_some_and(u8::is_ascii_whitespace) {
+            *i += 1;
+        }
+    };
+    skip_whitespace(&mut i);
+    if declaration.get(i) != Some(&b'=') {
+        return None;
+    }
+    i += 1;
+    skip_whitespace(&mut i);
+    let quote = *declaration.get(i).filter(|&&c| c == b'"' || c == b'\'')?;
+    let value_start = i + 1;
+    let value_len = declaration[value_start..]
+        .iter()
+        .position(|&c| c == quote)?;
+    Some(start + value_start..start + value_start + value_len)
+}
+
+/// Replaces the encoding of the XML declaration with UTF-8,
+/// since the body has been decoded
+/// and the feed parsers would otherwise decode it again.
+fn declare_utf8(mut body: String) -> String {
+    if let Some(range) = declared_encoding(body.as_bytes()) {
+        if !body[range.clone()].eq_ignore_ascii_case("utf-8") {
+            body.replace_range(range, "UTF-8");
+        }
+    }
+    body
+}
+
+/// Reads a response body,
+/// stopping after the first chunk that takes it over `limit` bytes.
+///
+/// The result can therefore be larger than `limit`,
+/// which `read_limited` will then report.
+async fn read_response(mut response: Response, limit: u64) -> Result<Vec<u8>, reqwest::Error> {
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() as u64 > limit {
+            break;
+        }
+    }
+
+    Ok(body)
+}
+
+/// Returns true if an error from a feed download is likely to go away
+/// if the request is sent again, e.g. a connection that was refused or reset,
+/// a timeout or a DNS failure.
+/// Errors reported by the server, such as error statuses, aren't transient.
+fn is_transient(error: &ErrorWithContext) -> bool {
+    iter::successors(Some(error as &(dyn Error + 'static)), |&source| {
+        source.source()
+    })
+    .any(|source| {
+        if let Some(error) = source.downcast_ref::<reqwest::Error>() {
+            error.is_connect() || error.is_timeout() || error.is_body() || error.is_decode()
+        } else {
+            matches!(
+                source.downcast_ref::<ResolveError>(),
+                Some(ResolveError::Failed(..))
+            )
+        }
+    })
+}
+
+/// Formats bytes as space-separated hexadecimal numbers.
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the headers for a conditional request
+/// using the validators from the last response.
+///
+/// ETags are sent as is, including the `W/` prefix of weak ETags.
+/// If-Modified-Since is only sent when there's no ETag,
+/// unless `always_send_if_modified_since` is set,
+/// as some servers ignore If-None-Match when If-Modified-Since is present.
+fn conditional_headers(feed: &FeedConfiguration) -> Result<HeaderMap, ErrorWithContext> {
+    let mut headers = HeaderMap::new();
+
+    // Add an If-None-Match header if we have an ETag.
+    if let Some(ref e_tag) = feed.last_e_tag {
+        headers.insert(
+            header::IF_NONE_MATCH,
+            try_with_context!(HeaderValue::from_str(e_tag), "invalid last_e_tag value"),
+        );
+    }
+
+    // Add an If-Modified-Since header if we have a Last-Modified date.
+    if let Some(ref last_modified) = feed.last_modified {
+        if feed.last_e_tag.is_none() || feed.always_send_if_modified_since {
+            headers.insert(
+                header::IF_MODIFIED_SINCE,
+                try_with_context!(
+                    HeaderValue::from_str(last_modified),
+                    "invalid last_modified value"
+                ),
+            );
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Computes until when a response is fresh
+/// from its Cache-Control, Date and Age headers.
+/// `now` is the time the response was received.
+fn fresh_until(headers: &HeaderMap, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let header_str = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let mut max_age = None;
+    for directive in header_str(header::CACHE_CONTROL)?.split(',') {
+        let directive = directive.trim().to_ascii_lowercase();
+        if directive == "no-cache" || directive == "no-store" {
+            return None;
+        }
+        if let Some(value) = directive.strip_prefix("max-age=") {
+            max_age = value.trim_matches('"').parse::<i64>().ok();
+        }
+    }
+
+    // The response may already have spent some time in caches.
+    let age = header_str(header::AGE)
+        .and_then(|age| age.parse::<i64>().ok())
+        .unwrap_or(0);
+    let date_age = header_str(header::DATE)
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map_or(0, |date| (now - date.with_timezone(&Utc)).num_seconds());
+    let current_age = age.max(date_age).max(0);
+
+    let remaining = max_age? - current_age;
+    if remaining > 0 {
+        Some(now + chrono::Duration::seconds(remaining))
+    } else {
+        None
+    }
+}
+
+/// Reads everything from `reader`,
+/// failing if there are more than `limit` bytes.
+fn read_limited(reader: &mut dyn Read, limit: u64) -> Result<Vec<u8>, BoxError> {
+    // Read one byte more than the limit
+    // so we can tell if the data exceeds the limit.
+    let mut data = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(ResponseTooLarge::ResponseTooLarge(limit).into());
+    }
+
+    Ok(data)
+}
+
+/// The per-feed options that require a dedicated `Client`.
+#[derive(Default, PartialEq, Eq, Hash)]
+struct ClientOptions {
+    tls_ca_file: Option<PathBuf>,
+    danger_accept_invalid_certs: bool,
+    ip_preference: IpPreference,
+    resolve: Option<ResolveOverride>,
+}
+
+impl ClientOptions {
+    /// Returns the options of the client shared by the feeds
+    /// that don't need specific client options.
+    fn shared(prefer_ipv4: bool, force_ipv4: bool) -> ClientOptions {
+        ClientOptions {
+            ip_preference: IpPreference::new(prefer_ipv4, force_ipv4),
+            ..ClientOptions::default()
+        }
+    }
+
+    /// Returns the client options for a feed,
+    /// given the IP version options from the configuration.
+    fn of(feed: &FeedConfiguration, prefer_ipv4: bool, force_ipv4: bool) -> ClientOptions {
+        ClientOptions {
+            tls_ca_file: feed.tls_ca_file.clone(),
+            danger_accept_invalid_certs: feed.danger_accept_invalid_certs,
+            ip_preference: IpPreference::new(
+                feed.prefer_ipv4.unwrap_or(prefer_ipv4),
+                feed.force_ipv4.unwrap_or(force_ipv4),
+            ),
+            resolve: feed.resolve.clone(),
+        }
+    }
+
+    fn build_client(&self, max_redirects: usize) -> Result<Client, BoxError> {
+        let mut builder = proxy::configure(Client::builder())
+            .redirect(redirect_policy(max_redirects))
+            .dns_resolver(Arc::new(Resolver(self.ip_preference)));
+
+        if let Some(tls_ca_file) = &self.tls_ca_file {
+            let certificate = fs::read(tls_ca_file).map_err(|e| {
+                ErrorWithContext::new(
+                    e.into(),
+                    format!("failed to read file {}", tls_ca_file.display()),
+                )
+            })?;
+            let certificate = Certificate::from_pem(&certificate)
+                .or_else(|_| Certificate::from_der(&certificate))
+                .map_err(|e| {
+                    ErrorWithContext::new(
+                        e.into(),
+                        format!("failed to load certificate {}", tls_ca_file.display()),
+                    )
+                })?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        if self.danger_accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        if let Some(resolve) = &self.resolve {
+            // The URL keeps the original host,
+            // which is used for the Host header and for SNI.
+            builder = builder.resolve(&resolve.host, resolve.addr);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Connects to a specific address for a host instead of resolving it,
+/// like curl's `--resolve` option.
+///
+/// It's written as `HOST:PORT:ADDRESS`, e.g. `feeds.example.com:443:192.0.2.10`.
+/// IPv6 addresses may be enclosed in brackets.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ResolveOverride {
+    host: String,
+    addr: SocketAddr,
+}
+
+impl FromStr for ResolveOverride {
+    type Err = InvalidResolveOverride;
+
+    fn from_str(value: &str) -> Result<ResolveOverride, InvalidResolveOverride> {
+        let invalid = || InvalidResolveOverride::InvalidResolveOverride(value.into());
+        let mut parts = value.splitn(3, ':');
+        let host = parts
+            .next()
+            .filter(|host| !host.is_empty())
+            .ok_or_else(invalid)?;
+        let port = parts
+            .next()
+            .and_then(|port| port.parse().ok())
+            .ok_or_else(invalid)?;
+        let ip = parts
+            .next()
+            .map(|ip| ip.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+            .ok_or_else(invalid)?;
+        Ok(ResolveOverride {
+            host: host.to_ascii_lowercase(),
+            addr: SocketAddr::new(ip, port),
+        })
+    }
+}
+
+impl TryFrom<String> for ResolveOverride {
+    type Error = InvalidResolveOverride;
+
+    fn try_from(value: String) -> Result<ResolveOverride, InvalidResolveOverride> {
+        value.parse()
+    }
+}
+
+impl From<ResolveOverride> for String {
+    fn from(resolve: ResolveOverride) -> String {
+        resolve.to_string()
+    }
+}
+
+impl Display for ResolveOverride {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.addr.ip() {
+            IpAddr::V4(ip) => write!(fmt, "{}:{}:{}", self.host, self.addr.port(), ip),
+            IpAddr::V6(ip) => write!(fmt, "{}:{}:[{}]", self.host, self.addr.port(), ip),
+        }
+    }
+}
+
+/// Which IP versions to connect with.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+enum IpPreference {
+    #[default]
+    Any,
+    /// Try IPv4 addresses first, then IPv6 addresses.
+    PreferIpv4,
+    /// Only connect to IPv4 addresses.
+    ForceIpv4,
+}
+
+impl IpPreference {
+    fn new(prefer_ipv4: bool, force_ipv4: bool) -> IpPreference {
+        if force_ipv4 {
+            IpPreference::ForceIpv4
+        } else if prefer_ipv4 {
+            IpPreference::PreferIpv4
+        } else {
+            IpPreference::Any
+        }
+    }
+
+    /// Orders or filters resolved addresses according to this preference.
+    ///
+    /// The HTTP client tries the addresses of the same family as the first address
+    /// before falling back to the other family,
+    /// so putting the IPv4 addresses first makes it prefer IPv4.
+    fn apply(
+        self,
+        host: &str,
+        mut addrs: Vec<SocketAddr>,
+    ) -> Result<Vec<SocketAddr>, ResolveError> {
+        match self {
+            IpPreference::Any => {}
+            IpPreference::PreferIpv4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+            IpPreference::ForceIpv4 => {
+                addrs.retain(SocketAddr::is_ipv4);
+                if addrs.is_empty() {
+                    return Err(ResolveError::NoIpv4Address(host.into()));
+                }
+            }
+        }
+
+        Ok(addrs)
+    }
+}
+
+/// Resolves host names with the system's resolver,
+/// applying an `IpPreference` to the addresses.
+///
+/// This also lets us report resolution failures as `ResolveError`s,
+/// which can be told apart from connection failures.
+struct Resolver(IpPreference);
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let ip_preference = self.0;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs = tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|e| ResolveError::Failed(host.into(), e.to_string()))?;
+            let addrs = ip_preference.apply(host, addrs.collect())?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Follows up to `max_redirects` redirects,
+/// reporting the redirect chain if there are too many redirects
+/// or if the redirects loop.
+fn redirect_policy(max_redirects: usize) -> redirect::Policy {
+    redirect::Policy::custom(move |attempt| {
+        let is_loop = attempt.previous().contains(attempt.url());
+        let redirects = attempt.previous().len();
+        if is_loop || redirects > max_redirects {
+            let chain = attempt
+                .previous()
+                .iter()
+                .chain(iter::once(attempt.url()))
+                .cloned()
+                .collect();
+            if is_loop {
+                attempt.error(RedirectError::Loop(chain))
+            } else {
+                attempt.error(RedirectError::TooManyRedirects(max_redirects, chain))
+            }
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Waits for a child process to exit,
+/// killing it if it runs for longer than `timeout`.
+pub fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus, BoxError> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if start.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CommandError::TimedOut(timeout).into());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Reads at most `limit + 1` bytes from a child process's pipe
+/// and discards the rest,
+/// so that the process doesn't block on a full pipe.
+fn read_pipe(pipe: &mut dyn Read, limit: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    (&mut *pipe).take(limit + 1).read_to_end(&mut data)?;
+    io::copy(pipe, &mut io::sink())?;
+    Ok(data)
+}
+
+/// Enforces a minimum delay between two requests to the same host.
+struct HostThrottle {
+    delay: Duration,
+    /// Longer delays for some hosts, from their robots.txt files.
+    crawl_delays: HashMap<String, Duration>,
+    last_requests: HashMap<String, Instant>,
+}
+
+impl HostThrottle {
+    fn new(delay: Duration) -> HostThrottle {
+        HostThrottle {
+            delay,
+            crawl_delays: HashMap::new(),
+            last_requests: HashMap::new(),
+        }
+    }
+
+    /// Makes requests to `host` wait for at least `delay`.
+    fn set_crawl_delay(&mut self, host: &str, delay: Duration) {
+        self.crawl_delays.insert(host.to_ascii_lowercase(), delay);
+    }
+
+    /// Sleeps until `delay` (or the host's crawl delay, if it's longer)
+    /// has elapsed since the last request to the host of `url`,
+    /// then records a request to that host.
+    fn wait(&mut self, url: &Url) {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return,
+        };
+
+        let delay = match self.crawl_delays.get(&host) {
+            Some(&crawl_delay) => crawl_delay.max(self.delay),
+            None => self.delay,
+        };
+        if let Some(last_request) = self.last_requests.get(&host) {
+            let elapsed = last_request.elapsed();
+            if elapsed < delay {
+                thread::sleep(delay - elapsed);
+            }
+        }
+
+        self.last_requests.insert(host, Instant::now());
+    }
+}
+
+/// Enters a Tokio runtime shared by the tests,
+/// so that they can create a `Fetcher` or a `Pocket`.
+#[cfg(test)]
+pub fn enter_test_runtime() -> tokio::runtime::EnterGuard<'static> {
+    use std::sync::OnceLock;
+
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| tokio::runtime::Runtime::new().unwrap())
+        .enter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Feed;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn feed_source_http() {
+        assert!(matches!(
+            FeedSource::of("https://example.com/feed.xml"),
+            FeedSource::Http
+        ));
+        assert!(matches!(
+            FeedSource::of("http://example.com/feed.xml"),
+            FeedSource::Http
+        ));
+    }
+
+    #[test]
+    fn feed_source_file_url() {
+        match FeedSource::of("file:///var/feeds/feed.xml") {
+            FeedSource::File(path) => assert_eq!(path, Path::new("/var/feeds/feed.xml")),
+            _ => panic!("expected a file"),
+        }
+    }
+
+    #[test]
+    fn feed_source_path() {
+        match FeedSource::of("/var/feeds/feed.xml") {
+            FeedSource::File(path) => assert_eq!(path, Path::new("/var/feeds/feed.xml")),
+            _ => panic!("expected a file"),
+        }
+        match FeedSource::of("feeds/feed.xml") {
+            FeedSource::File(path) => assert_eq!(path, Path::new("feeds/feed.xml")),
+            _ => panic!("expected a file"),
+        }
+        match FeedSource::of(r"C:\feeds\feed.xml") {
+            FeedSource::File(path) => assert_eq!(path, Path::new(r"C:\feeds\feed.xml")),
+            _ => panic!("expected a file"),
+        }
+    }
+
+    #[test]
+    fn feed_source_exec() {
+        match FeedSource::of("exec:/usr/local/bin/make-feed.sh --all") {
+            FeedSource::Exec(command) => {
+                assert_eq!(command, ["/usr/local/bin/make-feed.sh", "--all"])
+            }
+            _ => panic!("expected a command"),
+        }
+        match FeedSource::of("exec:///usr/local/bin/make-feed.sh") {
+            FeedSource::Exec(command) => assert_eq!(command, ["/usr/local/bin/make-feed.sh"]),
+            _ => panic!("expected a command"),
+        }
+    }
+
+    #[test]
+    fn feed_source_unsupported_scheme() {
+        match FeedSource::of("gemini://example.com/feed.xml") {
+            FeedSource::Unsupported(scheme) => assert_eq!(scheme, "gemini"),
+            _ => panic!("expected an unsupported scheme"),
+        }
+    }
+
+    #[test]
+    fn fetch_unsupported_scheme() {
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let feed = FeedConfiguration {
+            url: "gemini://example.com/feed.xml".into(),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "unable to read feed gemini://example.com/feed.xml:\n  \
+            unsupported URL scheme `gemini:`; \
+            feeds must be http, https, file or exec URLs or local paths"
+        );
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_body_with(
+        data: &[u8],
+        content_type: Option<&str>,
+        charset: Option<&str>,
+    ) -> Result<String, ErrorWithContext> {
+        let _runtime = enter_test_runtime();
+        let fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let feed = FeedConfiguration {
+            url: "https://example.com/feed.xml".into(),
+            charset: charset.map(String::from),
+            ..FeedConfiguration::default()
+        };
+        fetcher.read_body(&mut &data[..], None, content_type, &feed)
+    }
+
+    fn read_body(data: &[u8]) -> Result<String, ErrorWithContext> {
+        read_body_with(data, Some("application/rss+xml"), None)
+    }
+
+    #[test]
+    fn read_body_plain() {
+        assert_eq!(read_body(b"<rss/>").unwrap(), "<rss/>");
+    }
+
+    #[test]
+    fn read_body_gzip() {
+        assert_eq!(read_body(&gzip(b"<rss/>")).unwrap(), "<rss/>");
+    }
+
+    #[test]
+    fn read_body_double_gzip() {
+        assert_eq!(read_body(&gzip(&gzip(b"<rss/>"))).unwrap(), "<rss/>");
+    }
+
+    #[test]
+    fn read_body_too_many_gzip_layers() {
+        assert!(read_body(&gzip(&gzip(&gzip(b"<rss/>")))).is_err());
+    }
+
+    #[test]
+    fn read_body_invalid_utf8() {
+        let error = read_body(b"<rss>caf\xe9</rss>").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the response from <https://example.com/feed.xml> is not valid UTF-8:\n  \
+             invalid UTF-8 sequence at byte 8 \
+             (Content-Type: application/rss+xml); \
+             the body starts with: 3c 72 73 73 3e 63 61 66 e9 3c 2f 72 73 73 3e"
+        );
+    }
+
+    const LATIN_1_RSS: &[u8] =
+        b"<rss version=\"2.0\"><channel><title>Caf\xe9</title></channel></rss>";
+    const LATIN_1_CONTENT_TYPE: &str = "application/rss+xml; charset=ISO-8859-1";
+
+    fn with_declaration(encoding: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = format!("<?xml version=\"1.0\" encoding=\"{}\"?>", encoding).into_bytes();
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn read_body_header_charset() {
+        let body = read_body_with(LATIN_1_RSS, Some(LATIN_1_CONTENT_TYPE), None).unwrap();
+        assert_eq!(
+            body,
+            "<rss version=\"2.0\"><channel><title>Café</title></channel></rss>"
+        );
+
+        // Quoted and in another case.
+        let content_type = "application/rss+xml; Charset=\"iso-8859-1\"";
+        let body = read_body_with(LATIN_1_RSS, Some(content_type), None).unwrap();
+        assert_eq!(
+            body,
+            "<rss version=\"2.0\"><channel><title>Café</title></channel></rss>"
+        );
+    }
+
+    #[test]
+    fn read_body_declared_encoding() {
+        let data = with_declaration("ISO-8859-1", LATIN_1_RSS);
+        let body = read_body_with(&data, Some("application/rss+xml"), None).unwrap();
+        // The declaration now matches the decoded body.
+        assert_eq!(
+            body,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <rss version=\"2.0\"><channel><title>Café</title></channel></rss>"
+        );
+        let feed = body.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Café"));
+    }
+
+    #[test]
+    fn read_body_header_and_declaration_agree() {
+        let data = with_declaration("iso-8859-1", LATIN_1_RSS);
+        let body = read_body_with(&data, Some(LATIN_1_CONTENT_TYPE), None).unwrap();
+        let feed = body.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Café"));
+    }
+
+    #[test]
+    fn read_body_header_charset_wins() {
+        // The feed says it's UTF-8, but the server knows better.
+        let data = with_declaration("UTF-8", LATIN_1_RSS);
+        let body = read_body_with(&data, Some(LATIN_1_CONTENT_TYPE), None).unwrap();
+        let feed = body.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Café"));
+
+        // The other way around.
+        let data = with_declaration(
+            "ISO-8859-1",
+            "<rss version=\"2.0\"><channel><title>Café</title></channel></rss>".as_bytes(),
+        );
+        let content_type = "application/rss+xml; charset=utf-8";
+        let body = read_body_with(&data, Some(content_type), None).unwrap();
+        let feed = body.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Café"));
+    }
+
+    #[test]
+    fn read_body_charset_override() {
+        let data = with_declaration("UTF-8", LATIN_1_RSS);
+        let content_type = "application/rss+xml; charset=utf-8";
+        let body = read_body_with(&data, Some(content_type), Some("windows-1252")).unwrap();
+        let feed = body.parse::<Feed>().unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Café"));
+
+        let error = read_body_with(&data, Some(content_type), Some("klingon")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "failed to decode the response from <https://example.com/feed.xml>:\n  \
+             unknown charset: klingon"
+        );
+    }
+
+    #[test]
+    fn conditional_headers_prefer_e_tag() {
+        let mut feed = FeedConfiguration {
+            url: "https://example.com/feed.xml".into(),
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".into()),
+            last_e_tag: Some("W/\"abc\"".into()),
+            ..FeedConfiguration::default()
+        };
+        let headers = conditional_headers(&feed).unwrap();
+        assert_eq!(headers[header::IF_NONE_MATCH], "W/\"abc\"");
+        assert!(!headers.contains_key(header::IF_MODIFIED_SINCE));
+
+        feed.always_send_if_modified_since = true;
+        let headers = conditional_headers(&feed).unwrap();
+        assert_eq!(headers[header::IF_NONE_MATCH], "W/\"abc\"");
+        assert_eq!(
+            headers[header::IF_MODIFIED_SINCE],
+            "Mon, 01 Jan 2024 00:00:00 GMT"
+        );
+
+        feed.last_e_tag = None;
+        feed.always_send_if_modified_since = false;
+        let headers = conditional_headers(&feed).unwrap();
+        assert!(!headers.contains_key(header::IF_NONE_MATCH));
+        assert_eq!(
+            headers[header::IF_MODIFIED_SINCE],
+            "Mon, 01 Jan 2024 00:00:00 GMT"
+        );
+    }
+
+    fn headers(headers: &[(header::HeaderName, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn fresh_until_max_age() {
+        let now = Utc::now();
+        let headers = headers(&[(header::CACHE_CONTROL, "public, max-age=3600")]);
+        assert_eq!(
+            fresh_until(&headers, now),
+            Some(now + chrono::Duration::seconds(3600))
+        );
+    }
+
+    #[test]
+    fn fresh_until_subtracts_age() {
+        let now = Utc::now();
+        let aged = headers(&[
+            (header::CACHE_CONTROL, "max-age=3600"),
+            (header::AGE, "600"),
+        ]);
+        assert_eq!(
+            fresh_until(&aged, now),
+            Some(now + chrono::Duration::seconds(3000))
+        );
+
+        let now = DateTime::parse_from_rfc3339("2024-01-01T01:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let dated = headers(&[
+            (header::CACHE_CONTROL, "max-age=7200"),
+            (header::DATE, "Mon, 01 Jan 2024 00:00:00 GMT"),
+        ]);
+        assert_eq!(
+            fresh_until(&dated, now),
+            Some(now + chrono::Duration::seconds(3600))
+        );
+    }
+
+    #[test]
+    fn fresh_until_not_cacheable() {
+        let now = Utc::now();
+        assert_eq!(fresh_until(&headers(&[]), now), None);
+        let no_cache = headers(&[(header::CACHE_CONTROL, "no-cache, max-age=3600")]);
+        assert_eq!(fresh_until(&no_cache, now), None);
+        let stale = headers(&[(header::CACHE_CONTROL, "max-age=60"), (header::AGE, "120")]);
+        assert_eq!(fresh_until(&stale, now), None);
+    }
+
+    #[test]
+    fn read_limited_rejects_large_data() {
+        assert_eq!(read_limited(&mut &b"abc"[..], 3).unwrap(), b"abc");
+        assert!(read_limited(&mut &b"abcd"[..], 3).is_err());
+    }
+
+    fn fetch(server: &MockServer, feed: FeedConfiguration) -> FeedResponse {
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..feed
+        };
+        match fetcher.fetch(&feed) {
+            Ok(response) => response,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn fetch_returns_body_and_validators() {
+        let server = MockServer::start(vec![MockResponse::new("200 OK", "<rss/>")
+            .header("Content-Type", "application/rss+xml")
+            .header("Last-Modified", "Mon, 01 Jan 2024 00:00:00 GMT")
+            .header("ETag", "\"abc\"")]);
+        match fetch(&server, FeedConfiguration::default()) {
+            FeedResponse::Success {
+                body,
+                content_type,
+                last_modified,
+                e_tag,
+                fresh_until,
+                details,
+            } => {
+                assert_eq!(body, "<rss/>");
+                assert_eq!(content_type.as_deref(), Some("application/rss+xml"));
+                assert_eq!(
+                    last_modified.as_deref(),
+                    Some("Mon, 01 Jan 2024 00:00:00 GMT")
+                );
+                assert_eq!(e_tag.as_deref(), Some("\"abc\""));
+                assert_eq!(fresh_until, None);
+                assert_eq!(details.final_url, Some(server.url("/feed.xml")));
+                assert_eq!(details.status, Some(StatusCode::OK));
+                assert_eq!(details.size, 6);
+            }
+            FeedResponse::NotModified { .. } => panic!("expected a success"),
+        }
+
+        let request = server.request();
+        assert_eq!(request.request_line, "GET /feed.xml HTTP/1.1");
+        assert_eq!(request.header("Accept"), Some(ACCEPT));
+        assert_eq!(request.header("User-Agent"), Some(DEFAULT_USER_AGENT));
+        assert_eq!(request.header("If-None-Match"), None);
+        assert_eq!(request.header("If-Modified-Since"), None);
+    }
+
+    #[test]
+    fn fetch_sends_conditional_headers() {
+        let server = MockServer::start(vec![
+            MockResponse::new("304 Not Modified", "").header("ETag", "\"def\"")
+        ]);
+        let feed = FeedConfiguration {
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".into()),
+            last_e_tag: Some("\"abc\"".into()),
+            ..FeedConfiguration::default()
+        };
+        match fetch(&server, feed) {
+            FeedResponse::NotModified { e_tag, .. } => {
+                assert_eq!(e_tag.as_deref(), Some("\"def\""));
+            }
+            FeedResponse::Success { .. } => panic!("expected a 304 response"),
+        }
+
+        let request = server.request();
+        assert_eq!(request.header("If-None-Match"), Some("\"abc\""));
+        assert_eq!(request.header("If-Modified-Since"), None);
+    }
+
+    #[test]
+    fn fetch_sends_last_modified_without_e_tag() {
+        let server = MockServer::start(vec![MockResponse::new("304 Not Modified", "")]);
+        let feed = FeedConfiguration {
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".into()),
+            user_agent: Some("Custom/1.0".into()),
+            ..FeedConfiguration::default()
+        };
+        match fetch(&server, feed) {
+            FeedResponse::NotModified { e_tag, .. } => assert_eq!(e_tag, None),
+            FeedResponse::Success { .. } => panic!("expected a 304 response"),
+        }
+
+        let request = server.request();
+        assert_eq!(request.header("User-Agent"), Some("Custom/1.0"));
+        assert_eq!(request.header("If-None-Match"), None);
+        assert_eq!(
+            request.header("If-Modified-Since"),
+            Some("Mon, 01 Jan 2024 00:00:00 GMT")
+        );
+    }
+
+    #[test]
+    fn fetch_reports_truncated_body() {
+        let server = MockServer::start(vec![MockResponse::new("200 OK", "<rss/>").truncated()]);
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            fetch_retries: Some(0),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().expect("expected an error");
+        assert!(is_transient(&error), "{}", error);
+    }
+
+    #[test]
+    fn fetch_rejects_error_status() {
+        let server = MockServer::start(vec![MockResponse::new("404 Not Found", "")]);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().expect("expected an error");
+        assert!(error.to_string().contains("404 Not Found"));
+        // Error statuses aren't retried.
+        assert!(!error.to_string().contains("attempts"));
+    }
+
+    #[test]
+    fn fetch_retries_connection_errors() {
+        // Nothing listens on this port once the listener is dropped.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            host_delay_ms: Some(0),
+            fetch_retries: Some(2),
+            fetch_retry_delay_ms: Some(1),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        let feed = FeedConfiguration {
+            url: format!("http://127.0.0.1:{}/feed.xml", port),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().expect("expected an error");
+        assert!(is_transient(&error));
+        assert!(error
+            .to_string()
+            .starts_with(&format!("giving up on <{}> after 3 attempts", feed.url)));
+        assert!(error.to_string().contains("failed to connect"));
+    }
+
+    fn dead_link_status(responses: Vec<MockResponse>) -> (MockServer, Option<StatusCode>) {
+        let server = MockServer::start(responses);
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            host_delay_ms: Some(0),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        let url = Url::parse(&server.url("/posts/1")).unwrap();
+        let status = fetcher.dead_link_status(&FeedConfiguration::default(), &url);
+        (server, status)
+    }
+
+    #[test]
+    fn dead_link_status_detects_missing_pages() {
+        let (server, status) = dead_link_status(vec![MockResponse::new("410 Gone", "")]);
+        assert_eq!(status, Some(StatusCode::GONE));
+        assert_eq!(server.request().request_line, "HEAD /posts/1 HTTP/1.1");
+
+        let (_, status) = dead_link_status(vec![MockResponse::new("200 OK", "")]);
+        assert_eq!(status, None);
+
+        // The page may come back.
+        let (_, status) = dead_link_status(vec![MockResponse::new("503 Service Unavailable", "")]);
+        assert_eq!(status, None);
+    }
+
+    #[test]
+    fn dead_link_status_falls_back_to_get() {
+        let (server, status) = dead_link_status(vec![
+            MockResponse::new("405 Method Not Allowed", ""),
+            MockResponse::new("404 Not Found", ""),
+        ]);
+        assert_eq!(status, Some(StatusCode::NOT_FOUND));
+        assert_eq!(server.request().request_line, "HEAD /posts/1 HTTP/1.1");
+        let request = server.request();
+        assert_eq!(request.request_line, "GET /posts/1 HTTP/1.1");
+        assert_eq!(request.header("Range"), Some("bytes=0-0"));
+    }
+
+    #[test]
+    fn fetch_rejects_large_body() {
+        let server = MockServer::start(vec![MockResponse::new("200 OK", "<rss></rss>")]);
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            max_body_size: Some(5),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().expect("expected an error");
+        assert!(error.to_string().contains("too large"));
+    }
+
+    #[test]
+    fn ip_preference_orders_addresses() {
+        let v4: SocketAddr = "192.0.2.1:0".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:0".parse().unwrap();
+        let addrs = vec![v6, v4];
+        assert_eq!(
+            IpPreference::Any.apply("a", addrs.clone()).unwrap(),
+            [v6, v4]
+        );
+        assert_eq!(
+            IpPreference::PreferIpv4.apply("a", addrs.clone()).unwrap(),
+            [v4, v6]
+        );
+        assert_eq!(IpPreference::ForceIpv4.apply("a", addrs).unwrap(), [v4]);
+        assert!(IpPreference::ForceIpv4.apply("a", vec![v6]).is_err());
+    }
+
+    #[test]
+    fn feed_ip_options_override_configuration() {
+        let mut feed = FeedConfiguration::default();
+        assert_eq!(
+            ClientOptions::of(&feed, true, false).ip_preference,
+            IpPreference::PreferIpv4
+        );
+        feed.prefer_ipv4 = Some(false);
+        assert_eq!(
+            ClientOptions::of(&feed, true, false).ip_preference,
+            IpPreference::Any
+        );
+        feed.force_ipv4 = Some(true);
+        assert_eq!(
+            ClientOptions::of(&feed, true, false).ip_preference,
+            IpPreference::ForceIpv4
+        );
+    }
+
+    #[test]
+    fn resolve_override_parse() {
+        let resolve: ResolveOverride = "Feeds.example.com:443:192.0.2.10".parse().unwrap();
+        assert_eq!(resolve.host, "feeds.example.com");
+        assert_eq!(resolve.addr, "192.0.2.10:443".parse().unwrap());
+        assert_eq!(resolve.to_string(), "feeds.example.com:443:192.0.2.10");
+
+        let resolve: ResolveOverride = "feeds.example.com:8443:[2001:db8::1]".parse().unwrap();
+        assert_eq!(resolve.addr, "[2001:db8::1]:8443".parse().unwrap());
+        assert_eq!(resolve.to_string(), "feeds.example.com:8443:[2001:db8::1]");
+        let resolve: ResolveOverride = "feeds.example.com:80:2001:db8::1".parse().unwrap();
+        assert_eq!(resolve.addr, "[2001:db8::1]:80".parse().unwrap());
+
+        assert!("feeds.example.com:443".parse::<ResolveOverride>().is_err());
+        assert!("feeds.example.com:https:192.0.2.10"
+            .parse::<ResolveOverride>()
+            .is_err());
+        assert!(":443:192.0.2.10".parse::<ResolveOverride>().is_err());
+        assert!("feeds.example.com:443:example.net"
+            .parse::<ResolveOverride>()
+            .is_err());
+    }
+
+    #[test]
+    fn fetch_uses_resolve_override() {
+        let server = MockServer::start(vec![MockResponse::new("200 OK", "<rss/>")]);
+        let port = server.port();
+        let feed = FeedConfiguration {
+            url: format!("http://feeds.example.com:{}/feed.xml", port),
+            resolve: Some(
+                format!("feeds.example.com:{}:127.0.0.1", port)
+                    .parse()
+                    .unwrap(),
+            ),
+            ..FeedConfiguration::default()
+        };
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        assert!(matches!(
+            fetcher.fetch(&feed),
+            Ok(FeedResponse::Success { body, .. }) if body == "<rss/>"
+        ));
+
+        let request = server.request();
+        assert_eq!(
+            request.header("Host"),
+            Some(&*format!("feeds.example.com:{}", port))
+        );
+    }
+
+    fn robots_txt_fetcher() -> Fetcher {
+        let config = Configuration {
+            respect_robots_txt: true,
+            host_delay_ms: Some(0),
+            ..Configuration::default()
+        };
+        Fetcher::new(&config).unwrap()
+    }
+
+    #[test]
+    fn robots_txt_disallows_feed() {
+        let server = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            "User-agent: *\nDisallow: /feed.xml\n",
+        )]);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = robots_txt_fetcher();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..FeedConfiguration::default()
+        };
+        assert!(!fetcher.is_allowed_by_robots_txt(&feed));
+        assert_eq!(server.request().request_line, "GET /robots.txt HTTP/1.1");
+
+        // robots.txt is only downloaded once.
+        assert!(!fetcher.is_allowed_by_robots_txt(&feed));
+    }
+
+    #[test]
+    fn missing_robots_txt_allows_feed() {
+        let server = MockServer::start(vec![MockResponse::new("404 Not Found", "")]);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = robots_txt_fetcher();
+        let feed = FeedConfiguration {
+            url: server.url("/feed.xml"),
+            ..FeedConfiguration::default()
+        };
+        assert!(fetcher.is_allowed_by_robots_txt(&feed));
+    }
+
+    #[test]
+    fn fetch_reports_resolution_errors() {
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            fetch_retries: Some(0),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        let feed = FeedConfiguration {
+            url: "http://feeds-to-pocket.invalid/feed.xml".into(),
+            ..FeedConfiguration::default()
+        };
+        let error = fetcher.fetch(&feed).err().expect("expected an error");
+        assert!(error
+            .to_string()
+            .starts_with("failed to resolve the host of <http://feeds-to-pocket.invalid/feed.xml>:\n  failed to resolve feeds-to-pocket.invalid: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fetch_exec_reports_large_output_without_waiting_for_the_timeout() {
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            max_body_size: Some(10),
+            command_timeout_secs: Some(10),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        // More than the capacity of a pipe.
+        let feed: FeedConfiguration =
+            serde_yaml::from_str("url: exec:head -c 1000000 /dev/zero").unwrap();
+        let start = Instant::now();
+        let error = fetcher.fetch(&feed).err().unwrap();
+        assert!(
+            error.to_string().contains("response exceeded 10 bytes"),
+            "{}",
+            error
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wait_with_timeout_kills_hung_command() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let start = Instant::now();
+        let result = wait_with_timeout(&mut child, Duration::from_millis(100));
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
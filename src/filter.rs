@@ -0,0 +1,291 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Filter commands, which decide whether new entries are sent to Pocket.
+//!
+//! A filter command is run once for each entry,
+//! with the entry's URL, title and feed URL as arguments
+//! and the same fields as a JSON object on its standard input.
+//! It exits with status 0 to push the entry or 1 to skip it;
+//! any other status is an error.
+//!
+//! In batch mode, the command is run once for all the entries of a feed,
+//! with one JSON object per line on its standard input,
+//! and prints `push` or `skip` on a line for each entry, in the same order.
+
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::error::{BoxError, CommandError, FilterError};
+use crate::fetch::wait_with_timeout;
+
+/// An entry, as given to a filter command.
+#[derive(Serialize)]
+pub struct FilterEntry<'a> {
+    pub url: &'a str,
+    pub title: Option<&'a str>,
+    pub feed_url: &'a str,
+}
+
+/// What to do with an entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Push,
+    Skip,
+}
+
+/// Runs a filter command for one entry.
+pub fn filter_entry(
+    command: &str,
+    entry: &FilterEntry,
+    timeout: Duration,
+) -> Result<Verdict, BoxError> {
+    let mut input = serde_json::to_vec(entry)?;
+    input.push(b'\n');
+    let args = [entry.url, entry.title.unwrap_or_default(), entry.feed_url];
+    let output = run(command, &args, input, timeout)?;
+    match output.status.code() {
+        Some(0) => Ok(Verdict::Push),
+        Some(1) => Ok(Verdict::Skip),
+        _ => Err(output.failure().into()),
+    }
+}
+
+/// Runs a filter command once for several entries.
+pub fn filter_entries(
+    command: &str,
+    entries: &[FilterEntry],
+    timeout: Duration,
+) -> Result<Vec<Verdict>, BoxError> {
+    let mut input = vec![];
+    for entry in entries {
+        serde_json::to_writer(&mut input, entry)?;
+        input.push(b'\n');
+    }
+    let output = run(command, &[], input, timeout)?;
+    if !output.status.success() {
+        return Err(output.failure().into());
+    }
+
+    let verdicts = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line {
+            "push" => Ok(Verdict::Push),
+            "skip" => Ok(Verdict::Skip),
+            _ => Err(FilterError::UnknownVerdict(line.into())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if verdicts.len() != entries.len() {
+        return Err(FilterError::VerdictCount(entries.len(), verdicts.len()).into());
+    }
+    Ok(verdicts)
+}
+
+struct Output {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Output {
+    fn failure(&self) -> CommandError {
+        CommandError::Failed(
+            self.status,
+            String::from_utf8_lossy(&self.stderr).trim().into(),
+        )
+    }
+}
+
+/// Runs `command` (split on whitespace, like `exec:` feeds)
+/// with `args` appended, writing `input` on its standard input.
+fn run(
+    command: &str,
+    args: &[&str],
+    input: Vec<u8>,
+    timeout: Duration,
+) -> Result<Output, BoxError> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or(FilterError::MissingCommand)?;
+    let mut child = Command::new(program)
+        .args(words)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Write and read on separate threads
+    // so that the child doesn't block on a full pipe
+    // while we wait for it to exit.
+    // Commands that decide from their arguments may exit
+    // without reading their input, so write errors are ignored.
+    let mut stdin = child.stdin.take().unwrap();
+    let stdin_writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let mut stdout = child.stdout.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut output = vec![];
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_reader = thread::spawn(move || {
+        let mut output = vec![];
+        let _ = stderr.read_to_end(&mut output);
+        output
+    });
+
+    let status = wait_with_timeout(&mut child, timeout)?;
+    stdin_writer.join().expect("stdin writer thread panicked");
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().expect("stdout reader thread panicked"),
+        stderr: stderr_reader.join().expect("stderr reader thread panicked"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Writes a shell script to a temporary file and returns its path.
+    /// The scripts are run with `sh` rather than executed directly,
+    /// as executing a file that another thread's child process
+    /// still has open for writing fails.
+    fn script(name: &str, body: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "feeds-to-pocket-filter-{}-{}.sh",
+            name,
+            process::id()
+        ));
+        fs::write(&path, format!("{}\n", body)).unwrap();
+        path
+    }
+
+    fn entry<'a>(url: &'a str, title: Option<&'a str>) -> FilterEntry<'a> {
+        FilterEntry {
+            url,
+            title,
+            feed_url: "https://example.com/feed.xml",
+        }
+    }
+
+    #[test]
+    fn filter_entry_uses_the_exit_status() {
+        let path = script(
+            "status",
+            r#"case "$1" in *skip*) exit 1;; *error*) echo oops >&2; exit 2;; esac"#,
+        );
+        let command = &format!("sh {}", path.display());
+
+        let verdict = filter_entry(command, &entry("https://example.com/1", None), TIMEOUT);
+        assert_eq!(verdict.unwrap(), Verdict::Push);
+        let verdict = filter_entry(command, &entry("https://example.com/skip", None), TIMEOUT);
+        assert_eq!(verdict.unwrap(), Verdict::Skip);
+        let error =
+            filter_entry(command, &entry("https://example.com/error", None), TIMEOUT).unwrap_err();
+        assert!(error.to_string().contains("standard error:\noops"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn filter_entry_passes_the_title_and_feed_url() {
+        let path = script(
+            "arguments",
+            r#"[ "$2" = "Hello" ] && [ "$3" = "https://example.com/feed.xml" ] && grep -q '"title":"Hello"'"#,
+        );
+        let command = &format!("sh {}", path.display());
+
+        let verdict = filter_entry(
+            command,
+            &entry("https://example.com/1", Some("Hello")),
+            TIMEOUT,
+        );
+        assert_eq!(verdict.unwrap(), Verdict::Push);
+        let verdict = filter_entry(
+            command,
+            &entry("https://example.com/1", Some("Bye")),
+            TIMEOUT,
+        );
+        assert_eq!(verdict.unwrap(), Verdict::Skip);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn filter_entry_kills_slow_commands() {
+        let path = script("slow", "sleep 5");
+        let error = filter_entry(
+            &format!("sh {}", path.display()),
+            &entry("https://example.com/1", None),
+            Duration::from_millis(100),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CommandError>(),
+            Some(CommandError::TimedOut(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn filter_entries_reads_one_verdict_per_entry() {
+        let path = script(
+            "batch",
+            r#"while read -r line; do case "$line" in *skip*) echo skip;; *) echo push;; esac; done"#,
+        );
+        let command = &format!("sh {}", path.display());
+        let entries = [
+            entry("https://example.com/1", None),
+            entry("https://example.com/skip", None),
+            entry("https://example.com/3", Some("Three")),
+        ];
+
+        let verdicts = filter_entries(command, &entries, TIMEOUT).unwrap();
+        assert_eq!(verdicts, [Verdict::Push, Verdict::Skip, Verdict::Push]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn filter_entries_rejects_bad_output() {
+        let path = script("short", "echo push");
+        let entries = [
+            entry("https://example.com/1", None),
+            entry("https://example.com/2", None),
+        ];
+        let error =
+            filter_entries(&format!("sh {}", path.display()), &entries, TIMEOUT).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FilterError>(),
+            Some(FilterError::VerdictCount(2, 1))
+        ));
+        fs::remove_file(path).unwrap();
+
+        let path = script("unknown", "echo yes; echo no");
+        let error =
+            filter_entries(&format!("sh {}", path.display()), &entries, TIMEOUT).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FilterError>(),
+            Some(FilterError::UnknownVerdict(line)) if line == "yes"
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The history file, a log of the entries added to Pocket
+//! with one JSON object per line.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// An entry that was added to Pocket.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct HistoryRecord {
+    pub timestamp: DateTime<Utc>,
+    pub feed_url: String,
+    pub entry_url: String,
+    pub title: Option<String>,
+    pub tags: Option<String>,
+}
+
+/// A history file opened for appending.
+/// Records can be written from several threads.
+pub struct History {
+    file: Mutex<File>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> io::Result<History> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(History {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends a record to the file and flushes it,
+    /// so that the record isn't lost if the process is killed.
+    pub fn record(&self, record: &HistoryRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.flush()
+    }
+}
+
+/// Reads the last `count` records of a history file, oldest first.
+/// Lines that aren't valid records are skipped.
+pub fn tail(path: &Path, count: usize) -> io::Result<Vec<HistoryRecord>> {
+    let contents = fs::read_to_string(path)?;
+    let mut records: Vec<HistoryRecord> = contents
+        .lines()
+        .rev()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                debug!("skipping invalid history record {:?}: {}", line, e);
+                None
+            }
+        })
+        .take(count)
+        .collect();
+    records.reverse();
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    fn record(entry_url: &str) -> HistoryRecord {
+        HistoryRecord {
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            feed_url: "https://example.com/atom.xml".into(),
+            entry_url: entry_url.into(),
+            title: Some("A post".into()),
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn record_and_tail() {
+        let path = env::temp_dir().join(format!("feeds-to-pocket-history-{}.jsonl", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let history = History::open(&path).unwrap();
+        for i in 1..=3 {
+            history
+                .record(&record(&format!("https://example.com/posts/{}", i)))
+                .unwrap();
+        }
+        drop(history);
+
+        // Appending keeps the previous records.
+        let history = History::open(&path).unwrap();
+        history
+            .record(&record("https://example.com/posts/4"))
+            .unwrap();
+
+        let records = tail(&path, 2).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            records,
+            [
+                record("https://example.com/posts/3"),
+                record("https://example.com/posts/4")
+            ]
+        );
+    }
+}
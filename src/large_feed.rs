@@ -0,0 +1,402 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Parsing of large Atom and RSS feeds.
+//!
+//! feed-rs builds the whole feed in memory,
+//! including the content of every entry,
+//! which takes several hundred megabytes for archive feeds of tens of megabytes.
+//! This parser reads the feed as a stream of XML events
+//! and only keeps the parts of the entries that we use.
+//! It follows feed-rs in how it reads those parts,
+//! except that entries without an ID get their first link as ID
+//! and dates are only read in the RFC 3339 and RFC 2822 formats.
+
+use chrono::{DateTime, Utc};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Decoder, Reader, XmlVersion};
+use url::Url;
+
+use crate::error::LargeFeedError;
+use crate::feed::{Entry, Feed, Link};
+
+/// The root elements of Atom, RSS 2.0 and RSS 1.0 feeds.
+const ROOT_ELEMENTS: [&str; 3] = ["feed", "rss", "RDF"];
+
+/// Parses a feed, resolving relative URLs against `base_url`
+/// and the `xml:base` attributes.
+pub fn parse(s: &str, base_url: Option<&str>) -> Result<Feed, LargeFeedError> {
+    let mut parser = Parser {
+        feed: Feed {
+            title: None,
+            links: vec![],
+            entries: vec![],
+        },
+        path: vec![],
+        bases: base_url
+            .and_then(|base_url| Url::parse(base_url).ok())
+            .map(|base_url| (0, base_url))
+            .into_iter()
+            .collect(),
+        entry: None,
+        text: None,
+    };
+
+    let mut reader = Reader::from_str(s);
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => parser.start(&element, reader.decoder())?,
+            Event::Empty(element) => {
+                parser.start(&element, reader.decoder())?;
+                parser.end();
+            }
+            Event::End(_) => parser.end(),
+            Event::Text(text) => parser.push_text(&text.decode()?),
+            Event::CData(text) => parser.push_text(&text.decode()?),
+            Event::GeneralRef(reference) => {
+                if let Some(c) = reference.resolve_char_ref()? {
+                    parser.push_text(c.encode_utf8(&mut [0; 4]));
+                } else {
+                    let name = reference.decode()?;
+                    match resolve_predefined_entity(&name) {
+                        Some(value) => parser.push_text(value),
+                        None => parser.push_text(&format!("&{};", name)),
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(parser.feed)
+}
+
+struct Parser {
+    feed: Feed,
+    /// The local names of the open elements.
+    path: Vec<String>,
+    /// The `xml:base` URLs in scope,
+    /// with the depth of the element that set them.
+    bases: Vec<(usize, Url)>,
+    /// The entry being read, with the depth of its element.
+    entry: Option<(usize, PartialEntry)>,
+    /// The text of the element being read.
+    text: Option<Text>,
+}
+
+struct PartialEntry {
+    entry: Entry,
+    updated: Option<DateTime<Utc>>,
+}
+
+struct Text {
+    field: Field,
+    /// The depth of the element.
+    depth: usize,
+    content: String,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Title,
+    Link,
+    Id,
+    Published,
+    Updated,
+    Category,
+}
+
+/// The attributes of an element that we use.
+#[derive(Default)]
+struct Attributes {
+    base: Option<String>,
+    href: Option<String>,
+    rel: Option<String>,
+    media_type: Option<String>,
+    term: Option<String>,
+}
+
+impl Attributes {
+    fn of(element: &BytesStart, decoder: Decoder) -> Result<Attributes, LargeFeedError> {
+        let mut attributes = Attributes::default();
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(quick_xml::Error::from)?;
+            let field = match attribute.key.as_ref() {
+                b"xml:base" => &mut attributes.base,
+                b"href" => &mut attributes.href,
+                b"rel" => &mut attributes.rel,
+                b"type" => &mut attributes.media_type,
+                b"term" => &mut attributes.term,
+                _ => continue,
+            };
+            *field = Some(
+                attribute
+                    .decoded_and_normalized_value(XmlVersion::Implicit1_0, decoder)?
+                    .into_owned(),
+            );
+        }
+        Ok(attributes)
+    }
+}
+
+impl Parser {
+    fn start(&mut self, element: &BytesStart, decoder: Decoder) -> Result<(), LargeFeedError> {
+        let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+        let depth = self.path.len() + 1;
+        if depth == 1 && !ROOT_ELEMENTS.contains(&name.as_str()) {
+            return Err(LargeFeedError::UnknownRoot(name));
+        }
+        let parent = self.path.last().map(String::as_str);
+        let in_feed = self.entry.is_none() && matches!(parent, Some("feed" | "channel"));
+        let in_entry = matches!(self.entry, Some((entry_depth, _)) if entry_depth + 1 == depth);
+        self.path.push(name);
+
+        let attributes = Attributes::of(element, decoder)?;
+        if let Some(base) = &attributes.base {
+            let url = match self.bases.last() {
+                Some((_, base_url)) => base_url.join(base),
+                None => Url::parse(base),
+            };
+            if let Ok(url) = url {
+                self.bases.push((depth, url));
+            }
+        }
+
+        // Markup inside a field is part of its text.
+        if self.text.is_some() {
+            return Ok(());
+        }
+
+        let field = match self.path[depth - 1].as_str() {
+            "entry" | "item" if self.entry.is_none() => {
+                let entry = Entry {
+                    id: String::new(),
+                    title: None,
+                    links: vec![],
+                    published: None,
+                    categories: vec![],
+                };
+                self.entry = Some((
+                    depth,
+                    PartialEntry {
+                        entry,
+                        updated: None,
+                    },
+                ));
+                None
+            }
+            _ if !in_feed && !in_entry => None,
+            // Atom links, including `atom:link` in RSS feeds.
+            "link" if attributes.href.is_some() => {
+                let link = Link {
+                    href: self.resolve(attributes.href.as_deref().unwrap_or_default()),
+                    rel: Some(attributes.rel.unwrap_or_else(|| "alternate".into())),
+                    media_type: attributes.media_type,
+                };
+                self.links().push(link);
+                None
+            }
+            "link" => Some(Field::Link),
+            "title" => Some(Field::Title),
+            _ if !in_entry => None,
+            "id" | "guid" => Some(Field::Id),
+            "published" | "pubDate" | "date" => Some(Field::Published),
+            "updated" => Some(Field::Updated),
+            "category" => match attributes.term {
+                Some(term) => {
+                    self.entry_mut().categories.push(term);
+                    None
+                }
+                None => Some(Field::Category),
+            },
+            _ => None,
+        };
+        self.text = field.map(|field| Text {
+            field,
+            depth,
+            content: String::new(),
+        });
+        Ok(())
+    }
+
+    fn end(&mut self) {
+        let depth = self.path.len();
+        if let Some(text) = self.text.take() {
+            if text.depth == depth {
+                self.set_field(text.field, text.content.trim());
+            } else {
+                self.text = Some(text);
+            }
+        }
+
+        if let Some((entry_depth, _)) = self.entry {
+            if entry_depth == depth {
+                let (_, PartialEntry { mut entry, updated }) = self.entry.take().unwrap();
+                // Atom entries only require an updated date.
+                entry.published = entry.published.or(updated);
+                if entry.id.is_empty() {
+                    if let Some(link) = entry.links.first() {
+                        entry.id = link.href.clone();
+                    }
+                }
+                self.feed.entries.push(entry);
+            }
+        }
+
+        while self
+            .bases
+            .last()
+            .is_some_and(|&(base_depth, _)| base_depth >= depth)
+        {
+            self.bases.pop();
+        }
+        self.path.pop();
+    }
+
+    fn push_text(&mut self, s: &str) {
+        if let Some(text) = &mut self.text {
+            text.content.push_str(s);
+        }
+    }
+
+    fn set_field(&mut self, field: Field, value: &str) {
+        match field {
+            Field::Title => {
+                let title = Some(value.to_owned());
+                match &mut self.entry {
+                    Some((_, partial)) => partial.entry.title = title,
+                    None => self.feed.title = title,
+                }
+            }
+            Field::Link => {
+                if !value.is_empty() {
+                    let link = Link {
+                        href: self.resolve(value),
+                        rel: None,
+                        media_type: None,
+                    };
+                    self.links().push(link);
+                }
+            }
+            Field::Id => self.entry_mut().id = value.to_owned(),
+            Field::Published => self.entry_mut().published = parse_date(value),
+            Field::Updated => {
+                if let Some((_, partial)) = &mut self.entry {
+                    partial.updated = parse_date(value);
+                }
+            }
+            Field::Category => {
+                if !value.is_empty() {
+                    self.entry_mut().categories.push(value.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Resolves a URL against the `xml:base` in scope, like feed-rs.
+    fn resolve(&self, url: &str) -> String {
+        let resolved = match (Url::parse(url), self.bases.last()) {
+            (Ok(url), _) => Some(url),
+            (Err(url::ParseError::RelativeUrlWithoutBase), Some((_, base_url))) => {
+                base_url.join(url).ok()
+            }
+            (Err(_), _) => None,
+        };
+        resolved
+            .map_or_else(|| url.to_owned(), String::from)
+            .trim()
+            .to_owned()
+    }
+
+    /// Returns the links of the current entry, or of the feed.
+    fn links(&mut self) -> &mut Vec<Link> {
+        match &mut self.entry {
+            Some((_, partial)) => &mut partial.entry.links,
+            None => &mut self.feed.links,
+        }
+    }
+
+    /// Returns the current entry.
+    /// Only called for fields that are read inside entries.
+    fn entry_mut(&mut self) -> &mut Entry {
+        &mut self.entry.as_mut().unwrap().1.entry
+    }
+}
+
+fn parse_date(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_rfc2822(s))
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type LinkSummary = (String, Option<String>, Option<String>);
+
+    fn links(links: &[Link]) -> Vec<LinkSummary> {
+        links
+            .iter()
+            .map(|link| (link.href.clone(), link.rel.clone(), link.media_type.clone()))
+            .collect()
+    }
+
+    /// Checks that both parsers read the same parts of a feed.
+    /// IDs are only compared if the feed has them,
+    /// since feed-rs generates IDs for entries that don't.
+    fn assert_same_as_feed_rs(s: &str, url: &str, compare_ids: bool) {
+        let expected = Feed::parse_at(s, url, false).unwrap();
+        let actual = Feed::parse_at(s, url, true).unwrap();
+        assert_eq!(actual.title, expected.title);
+        assert_eq!(links(&actual.links), links(&expected.links));
+        assert_eq!(actual.entries.len(), expected.entries.len());
+        for (actual, expected) in actual.entries.iter().zip(&expected.entries) {
+            if compare_ids {
+                assert_eq!(actual.id, expected.id);
+            }
+            assert_eq!(actual.title, expected.title);
+            assert_eq!(links(&actual.links), links(&expected.links));
+            assert_eq!(actual.published, expected.published);
+            assert_eq!(actual.categories, expected.categories);
+        }
+    }
+
+    #[test]
+    fn parse_like_feed_rs() {
+        let fixtures = [
+            (include_str!("../tests/fixtures/atom.xml"), true),
+            (include_str!("../tests/fixtures/rss.xml"), true),
+            (include_str!("../tests/fixtures/rss-1.0.xml"), false),
+            (include_str!("../tests/fixtures/malformed-rss.xml"), false),
+            (include_str!("../tests/fixtures/idn.xml"), true),
+            (include_str!("../tests/fixtures/atom-xml-base.xml"), true),
+            (
+                include_str!("../tests/fixtures/rss-with-leading-garbage.xml"),
+                true,
+            ),
+        ];
+        for (s, compare_ids) in fixtures {
+            assert_same_as_feed_rs(s, "https://example.com/feeds/feed.xml", compare_ids);
+        }
+    }
+
+    #[test]
+    fn use_links_as_ids() {
+        let feed = parse(include_str!("../tests/fixtures/malformed-rss.xml"), None).unwrap();
+        assert_eq!(feed.entries[0].id, "https://example.com/posts/3");
+    }
+
+    #[test]
+    fn reject_other_documents() {
+        let result = parse("<html><body>Not found</body></html>", None);
+        assert!(matches!(result, Err(LargeFeedError::UnknownRoot(name)) if name == "html"));
+    }
+}
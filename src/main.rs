@@ -6,77 +6,496 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+/// Prints a message on the standard error
+/// if the `-v` flag was passed on the command line.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::verbosity() > 0 {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Prints a message on the standard error
+/// if the `-v` flag was passed at least twice on the command line.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::verbosity() > 1 {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+mod diff;
+mod digest;
+#[macro_use]
+mod error;
+mod feed;
+mod fetch;
+mod filter;
+mod history;
+mod large_feed;
+#[cfg(test)]
+mod mock_server;
 mod pocket;
+mod proxy;
+mod push;
+mod push_window;
+mod robots;
+mod sites;
+mod strict;
+mod template;
 
-use std::error::Error;
-use std::fmt::{self, Display};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::process;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, SubsecRound, Utc};
 use clap::Parser;
-use quick_error::quick_error;
-use reqwest::header::{self, HeaderValue};
-use reqwest::{blocking::Client, StatusCode};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use regex::Regex;
+use reqwest::header::HeaderValue;
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::runtime::Runtime;
 use url::Url;
 
-use crate::pocket::Pocket;
+use crate::digest::DigestDay;
+use crate::error::{
+    AmbiguousTags, BoxError, BudgetExceeded, ErrorChain, ErrorKind, ErrorWithContext, Errors,
+    FeedFailures, FeedNotFound, Indented, InvalidFeedName, InvalidInclude, InvalidSavedConfig,
+    PocketSetupError, ReadOnly, Republished, StaleSync, UnknownField, UnsupportedScheme, YamlError,
+};
+use crate::feed::{looks_like_html, snippet, EntryRef, Feed, NotAFeed};
+use crate::fetch::{FeedResponse, FeedSource, Fetcher, ResolveOverride};
+use crate::filter::{filter_entries, filter_entry, FilterEntry, Verdict};
+use crate::history::History;
+use crate::pocket::{
+    Pocket, PocketError, PocketResult, DEFAULT_POCKET_TIMEOUT, DEFAULT_REDIRECT_URI,
+};
+use crate::push::{join_tags, DryRun, Offline, PushPool, PushRequest, DEFAULT_PUSH_WORKERS};
+use crate::push_window::PushWindow;
+use crate::sites::{GitHubFeed, SiteFeed};
+use crate::template::{TitleTemplate, TitleValues};
+
+/// How much detail to include in the output,
+/// set from the number of `-v` flags passed on the command line.
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the `--read-only` flag was passed on the command line.
+fn read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
 
 fn main() {
     let args = Args::parse();
+    VERBOSITY.store(args.verbose, Ordering::Relaxed);
+    READ_ONLY.store(args.read_only, Ordering::Relaxed);
+    if args.no_proxy {
+        proxy::disable();
+    }
+
+    // The HTTP requests run on this runtime.
+    // `Fetcher` and `Pocket` block until their requests complete,
+    // so the rest of the program doesn't need to be async.
+    let runtime = Runtime::new().unwrap_or_else(|e| {
+        let _ = writeln!(io::stderr(), "failed to start the async runtime: {}", e);
+        process::exit(1);
+    });
+    let _runtime = runtime.enter();
+
     run(&args).unwrap_or_else(|e| {
         let _ = writeln!(io::stderr(), "{}", e);
-        process::exit(1);
+        if args.show_config_errors_full && e.kind() == ErrorKind::Config {
+            let _ = write!(io::stderr(), "\nerror chain:\n{}", ErrorChain(&e));
+        }
+        // Let scripts tell configuration problems, authentication problems,
+        // failing feeds and truncated syncs apart.
+        process::exit(e.kind().exit_status());
     })
 }
 
 fn run(args: &Args) -> Result<(), ErrorWithContext> {
     match &args.command {
         Some(Command::Init) => init(&args.config),
-        Some(Command::SetConsumerKey { key }) => args.with_config(|config| {
-            set_consumer_key(config, key);
+        Some(Command::SetConsumerKey { key, account }) => args.with_config(|config| {
+            set_consumer_key(config, account, key);
             Ok(())
         }),
-        Some(Command::Login) => args.with_config(login),
+        Some(Command::Login(cmd)) => args.with_config(|config| login(config, cmd)),
+        Some(Command::FinishLogin { account }) => {
+            args.with_config(|config| finish_login(config, account))
+        }
+        Some(Command::Add(cmd)) if cmd.preview => preview(&args.load_config()?, cmd),
         Some(Command::Add(cmd)) => args.with_config(|config| add(config, cmd)),
         Some(Command::Remove { feed_url }) => args.with_config(|config| remove(config, feed_url)),
-        None => args.with_config(sync),
+        Some(Command::ImportPocketState(cmd)) => {
+            args.with_config(|config| import_pocket_state(config, cmd))
+        }
+        Some(Command::MarkRead { feed_url }) => {
+            args.with_config(|config| mark_read(config, feed_url))
+        }
+        Some(Command::ReprocessInvalid { feed_url }) => {
+            args.with_config(|config| reprocess_invalid(config, feed_url))
+        }
+        Some(Command::List {
+            long,
+            by_last_synced,
+        }) => list(&args.load_config()?, *long, *by_last_synced),
+        Some(Command::ExportState { feed_url }) => export_state(&args.load_config()?, feed_url),
+        Some(Command::ImportState {
+            feed_url,
+            state_file,
+        }) => args.with_config(|config| import_state(config, feed_url, state_file)),
+        Some(Command::Merge(cmd)) => args.with_config(|config| merge(config, cmd)),
+        Some(Command::Validate(cmd)) => validate(&args.load_config()?, cmd),
+        Some(Command::Search(cmd)) => search(&args.load_config()?, cmd),
+        Some(Command::Pending(cmd)) => pending(&args.load_config()?, cmd),
+        Some(Command::Disable(cmd)) => args.with_config(|config| set_disabled(config, cmd, true)),
+        Some(Command::Enable(cmd)) => args.with_config(|config| set_disabled(config, cmd, false)),
+        Some(Command::Stats(cmd)) => match &cmd.reset {
+            Some(feed_url) => args.with_config(|config| reset_stats(config, feed_url)),
+            None => stats(&args.load_config()?, cmd),
+        },
+        Some(Command::History(cmd)) => history(&args.load_config()?, cmd),
+        Some(Command::Status(cmd)) => status(&args.config, &args.load_config()?, cmd),
+        Some(Command::CompactState(cmd)) if cmd.dry_run => {
+            compact_state(&mut args.load_config()?, cmd)
+        }
+        Some(Command::CompactState(cmd)) => args.with_config(|config| compact_state(config, cmd)),
+        Some(Command::Sync(cmd)) => run_sync(args, cmd),
+        None => run_sync(args, &SyncCommand::default()),
     }
 }
 
-macro_rules! try_with_context {
-    ($expr:expr, $context:expr) => {
-        match $expr {
-            ::std::result::Result::Ok(val) => val,
-            ::std::result::Result::Err(err) => {
-                return ::std::result::Result::Err($crate::ErrorWithContext::new(
-                    ::std::convert::From::from(err),
-                    $context,
-                ))
-            }
+fn run_sync(args: &Args, cmd: &SyncCommand) -> Result<(), ErrorWithContext> {
+    if read_only() && !cmd.dry_run {
+        eprintln!(
+            "note: --read-only doesn't save what was sent to Pocket, \
+            so the next sync will send the same entries again"
+        );
+    }
+    let (failures, budget_exceeded) = if cmd.dry_run || read_only() {
+        let mut config = args.load_config()?;
+        let result = sync(&mut config, cmd)?;
+        if cmd.diff {
+            print!("{}", config_diff(&config, &args.config)?);
         }
+        result
+    } else {
+        args.with_config(|config| sync(config, cmd))?
     };
+    if let Some(budget_exceeded) = budget_exceeded {
+        if !failures.is_empty() {
+            let _ = writeln!(io::stderr(), "{}", failures);
+        }
+        try_with_context!(Err(budget_exceeded), "the sync was stopped early");
+    }
+    if !failures.is_empty() {
+        try_with_context!(Err(failures), "some feeds could not be synced successfully");
+    }
+
+    Ok(())
 }
 
-fn load_config(config_file_name: &Path) -> Result<Configuration, ErrorWithContext> {
-    let config_file = try_with_context!(
-        File::open(config_file_name),
-        format!("failed to open file {}", config_file_name.to_string_lossy())
-    );
-    let config = try_with_context!(
-        serde_yaml::from_reader(config_file),
-        format!(
-            "failed to load configuration from {}",
-            config_file_name.to_string_lossy()
-        )
+/// Returns the changes that saving `config` would make
+/// to the configuration files, as a unified diff.
+fn config_diff(
+    config: &Configuration,
+    config_file_name: &Path,
+) -> Result<String, ErrorWithContext> {
+    let mut diff = String::new();
+    for file in config_files(config, config_file_name)? {
+        let current = try_with_context!(
+            fs::read_to_string(&file.path),
+            format!("failed to open file {}", file.path.display())
+        );
+        let name = file.path.display().to_string();
+        diff += &diff::unified_diff(&current, &file.contents, &name, &name);
+    }
+    Ok(diff)
+}
+
+/// Loads a configuration file, with the feeds of the files it includes.
+/// If `strict` is true, fields that would be ignored
+/// (e.g. because they're misspelled) are errors.
+fn load_config(config_file_name: &Path, strict: bool) -> Result<Configuration, ErrorWithContext> {
+    let mut config: Configuration =
+        parse_config_file(config_file_name, strict, strict::unknown_fields)?;
+    let include = config.include.clone();
+    let mut including = vec![canonical_path(config_file_name)?];
+    load_includes(
+        &mut config,
+        config_file_name,
+        &include,
+        strict,
+        &mut including,
+    )?;
+    check_included_feeds(&config, config_file_name)?;
+    try_with_context!(
+        config.validate(),
+        format!("invalid configuration in {}", config_file_name.display())
     );
+    // External tools and older versions may have added entries twice.
+    // The configuration is only saved if the command changes it anyway.
+    let duplicates: usize = config
+        .feeds
+        .iter_mut()
+        .map(FeedConfiguration::remove_duplicates)
+        .sum();
+    if duplicates > 0 {
+        debug!(
+            "Removed {} duplicate entries from {}",
+            duplicates,
+            config_file_name.display()
+        );
+    }
+    // Older versions stored the tags as they were typed,
+    // and Pocket keeps the spaces around tags.
+    for feed in &mut config.feeds {
+        feed.tags = join_tags([feed.tags.as_str()]).unwrap_or_default();
+    }
+    config.tag_all = config.tag_all.as_deref().and_then(|tags| join_tags([tags]));
     Ok(config)
 }
 
+/// Reads and parses a configuration file or a file it includes.
+/// If `strict` is true, the fields that `unknown_fields` finds are errors.
+fn parse_config_file<T: serde::de::DeserializeOwned>(
+    file_name: &Path,
+    strict: bool,
+    unknown_fields: fn(&serde_yaml::Value) -> Vec<UnknownField>,
+) -> Result<T, ErrorWithContext> {
+    // Read the whole file to show the lines around syntax errors.
+    let contents = try_with_context!(
+        fs::read_to_string(file_name),
+        format!("failed to open file {}", file_name.display())
+    );
+    let load_error = || format!("failed to load configuration from {}", file_name.display());
+    if !strict {
+        return Ok(try_with_context!(parse_yaml(&contents), load_error()));
+    }
+
+    let value: serde_yaml::Value = try_with_context!(parse_yaml(&contents), load_error());
+    let unknown_fields: Vec<BoxError> = unknown_fields(&value)
+        .into_iter()
+        .map(|e| Box::new(e) as BoxError)
+        .collect();
+    if !unknown_fields.is_empty() {
+        try_with_context!(
+            Err(Errors::new(unknown_fields)),
+            format!("unknown fields in {}", file_name.display())
+        );
+    }
+    match serde_yaml::from_value(value) {
+        Ok(parsed) => Ok(parsed),
+        // Errors from a `Value` have no location,
+        // so parse the file again to find it.
+        Err(error) => Ok(try_with_context!(
+            parse_yaml::<T>(&contents).and(Err(YamlError::new(error, &contents))),
+            load_error()
+        )),
+    }
+}
+
+/// Returns the absolute path of a file, to tell if two paths are the same file.
+fn canonical_path(file_name: &Path) -> Result<PathBuf, ErrorWithContext> {
+    Ok(try_with_context!(
+        fs::canonicalize(file_name),
+        format!("failed to open file {}", file_name.display())
+    ))
+}
+
+/// Adds the feeds of the files in `include` to `config`,
+/// then the feeds of the files they include.
+/// `include` is relative to the directory of `file_name`,
+/// the file that includes them;
+/// `including` has the absolute paths of `file_name`
+/// and of the files that include it, to reject cycles.
+fn load_includes(
+    config: &mut Configuration,
+    file_name: &Path,
+    include: &[PathBuf],
+    strict: bool,
+    including: &mut Vec<PathBuf>,
+) -> Result<(), ErrorWithContext> {
+    let directory = file_name.parent().unwrap_or(Path::new(""));
+    for included_file_name in include {
+        let included_file_name = directory.join(included_file_name);
+        let canonical = canonical_path(&included_file_name)?;
+        if including.contains(&canonical) {
+            try_with_context!(
+                Err(InvalidInclude::Cycle(
+                    included_file_name.display().to_string()
+                )),
+                format!("failed to include files from {}", file_name.display())
+            );
+        }
+
+        let included: IncludeFile =
+            parse_config_file(&included_file_name, strict, strict::unknown_include_fields)?;
+        let index = config.included_files.len();
+        config.included_files.push(IncludedFile {
+            path: included_file_name.clone(),
+            include: included.include.clone(),
+        });
+        config
+            .feeds
+            .extend(included.feeds.into_iter().map(|feed| FeedConfiguration {
+                source_file: Some(index),
+                ..feed
+            }));
+
+        including.push(canonical);
+        load_includes(
+            config,
+            &included_file_name,
+            &included.include,
+            strict,
+            including,
+        )?;
+        including.pop();
+    }
+    Ok(())
+}
+
+/// Checks that no feed is in more than one of the configuration files,
+/// since each feed's state is saved back to the file it came from.
+fn check_included_feeds(
+    config: &Configuration,
+    config_file_name: &Path,
+) -> Result<(), ErrorWithContext> {
+    let mut sources: HashMap<&str, Option<usize>> = HashMap::new();
+    for feed in &config.feeds {
+        match sources.insert(&feed.url, feed.source_file) {
+            Some(source_file) if source_file != feed.source_file => {
+                let file_name = |source_file: Option<usize>| match source_file {
+                    Some(i) => config.included_files[i].path.display().to_string(),
+                    None => config_file_name.display().to_string(),
+                };
+                try_with_context!(
+                    Err(InvalidInclude::DuplicateFeed(
+                        feed.url.clone(),
+                        file_name(source_file),
+                        file_name(feed.source_file)
+                    )),
+                    format!("invalid configuration in {}", config_file_name.display())
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parses a YAML document,
+/// including the lines around the error's location in errors.
+fn parse_yaml<T: serde::de::DeserializeOwned>(contents: &str) -> Result<T, YamlError> {
+    serde_yaml::from_str(contents).map_err(|error| YamlError::new(error, contents))
+}
+
+/// The contents of one of the files of a configuration.
+struct ConfigFile {
+    path: PathBuf,
+    contents: String,
+    /// The number of feeds in the file.
+    feeds: usize,
+    /// False for the files loaded through `include`.
+    main: bool,
+}
+
+/// Serializes a configuration,
+/// splitting its feeds between the main configuration file
+/// and the files they were included from.
+fn config_files(
+    config: &Configuration,
+    config_file_name: &Path,
+) -> Result<Vec<ConfigFile>, ErrorWithContext> {
+    let context = "failed to serialize the configuration";
+    let mut main = try_with_context!(serde_yaml::to_value(config), context);
+    let mut feeds: Vec<Vec<serde_yaml::Value>> = vec![vec![]; config.included_files.len() + 1];
+    if let Some(serde_yaml::Value::Sequence(values)) = main
+        .as_mapping_mut()
+        .and_then(|main| main.shift_remove("feeds"))
+    {
+        for (feed, value) in config.feeds.iter().zip(values) {
+            feeds[feed.source_file.map_or(0, |i| i + 1)].push(value);
+        }
+    }
+
+    let mut feeds = feeds.into_iter();
+    let main_feeds = feeds.next().unwrap();
+    let mut files = vec![];
+    let main_feed_count = main_feeds.len();
+    if !main_feeds.is_empty() {
+        main.as_mapping_mut()
+            .unwrap()
+            .insert("feeds".into(), serde_yaml::Value::Sequence(main_feeds));
+    }
+    files.push(ConfigFile {
+        path: config_file_name.to_owned(),
+        contents: try_with_context!(serde_yaml::to_string(&main), context),
+        feeds: main_feed_count,
+        main: true,
+    });
+
+    for (included_file, feeds) in config.included_files.iter().zip(feeds) {
+        let mut file = serde_yaml::Mapping::new();
+        if !included_file.include.is_empty() {
+            file.insert(
+                "include".into(),
+                try_with_context!(serde_yaml::to_value(&included_file.include), context),
+            );
+        }
+        let feed_count = feeds.len();
+        if !feeds.is_empty() {
+            file.insert("feeds".into(), serde_yaml::Value::Sequence(feeds));
+        }
+        files.push(ConfigFile {
+            path: included_file.path.clone(),
+            contents: try_with_context!(serde_yaml::to_string(&file), context),
+            feeds: feed_count,
+            main: false,
+        });
+    }
+    Ok(files)
+}
+
+/// Saves a configuration,
+/// writing each feed back to the file it was loaded from.
 fn save_config(config: &Configuration, config_file_name: &Path) -> Result<(), ErrorWithContext> {
+    if read_only() {
+        try_with_context!(
+            Err(ReadOnly::ReadOnly),
+            format!(
+                "failed to save configuration to {}",
+                config_file_name.display()
+            )
+        );
+    }
+    for file in config_files(config, config_file_name)? {
+        save_config_file(&file)?;
+    }
+    Ok(())
+}
+
+fn save_config_file(file: &ConfigFile) -> Result<(), ErrorWithContext> {
+    let config_file_name = &file.path;
     // Append ".new" to the config file name.
     // We'll write the updated configuration in this file,
     // then rename the original and the new files
@@ -84,7 +503,7 @@ fn save_config(config: &Configuration, config_file_name: &Path) -> Result<(), Er
     let new_config_file_name = &{
         let mut file_name = config_file_name.as_os_str().to_os_string();
         file_name.push(".new");
-        file_name
+        PathBuf::from(file_name)
     };
 
     // Append ".old" to the config file name.
@@ -92,7 +511,7 @@ fn save_config(config: &Configuration, config_file_name: &Path) -> Result<(), Er
     let old_config_file_name = &{
         let mut file_name = config_file_name.as_os_str().to_os_string();
         file_name.push(".old");
-        file_name
+        PathBuf::from(file_name)
     };
 
     // Copy the configuration file, to preserve permissions.
@@ -100,58 +519,68 @@ fn save_config(config: &Configuration, config_file_name: &Path) -> Result<(), Er
         fs::copy(config_file_name, new_config_file_name),
         format!(
             "failed to copy {} to {}",
-            config_file_name.to_string_lossy(),
-            new_config_file_name.to_string_lossy()
+            config_file_name.display(),
+            new_config_file_name.display()
         )
     );
 
     // Write the updated configuration to the new configuration file.
+    // Errors while writing (e.g. when the disk is full)
+    // can go unnoticed until the file is flushed and synced,
+    // so do both before checking the file.
     {
-        let mut config_file = try_with_context!(
+        let config_file = try_with_context!(
             File::create(new_config_file_name),
-            format!(
-                "failed to create file {}",
-                new_config_file_name.to_string_lossy()
-            )
+            format!("failed to create file {}", new_config_file_name.display())
         );
-        try_with_context!(
-            serde_yaml::to_writer(&mut config_file, config),
+        let context = || {
             format!(
                 "failed to save configuration to {}",
-                new_config_file_name.to_string_lossy()
+                new_config_file_name.display()
             )
+        };
+        let mut writer = BufWriter::new(config_file);
+        try_with_context!(writer.write_all(file.contents.as_bytes()), context());
+        let config_file = try_with_context!(
+            writer.into_inner().map_err(io::IntoInnerError::into_error),
+            context()
         );
+        try_with_context!(config_file.sync_all(), context());
     }
 
-    fn rename<P: AsRef<Path> + Copy, Q: AsRef<Path> + Copy>(
-        from: P,
-        to: Q,
-    ) -> Result<(), ErrorWithContext> {
-        Ok(try_with_context!(
-            fs::rename(from, to),
+    // Don't replace the configuration file with a truncated file.
+    if let Err(e) = check_saved_config(file, new_config_file_name) {
+        let _ = fs::remove_file(new_config_file_name);
+        try_with_context!(
+            Err(e),
             format!(
-                "failed to rename {} to {}",
-                from.as_ref().to_string_lossy(),
-                to.as_ref().to_string_lossy()
+                "failed to save configuration; {} was left unchanged",
+                config_file_name.display()
             )
-        ))
+        );
+    }
+
+    fn rename(from: &Path, to: &Path) -> Result<(), ErrorWithContext> {
+        try_with_context!(
+            retry(RENAME_ATTEMPTS, RENAME_RETRY_DELAY, is_file_locked, || {
+                fs::rename(from, to)
+            }),
+            format!("failed to rename {} to {}", from.display(), to.display())
+        );
+        Ok(())
     }
 
     // Rename the original configuration file.
     rename(config_file_name, old_config_file_name)?;
 
     // Rename the new configuration file.
-    let rename_new_result = rename(new_config_file_name, config_file_name);
-    if rename_new_result.is_err() {
+    if let Err(rename_new_error) = rename(new_config_file_name, config_file_name) {
         // Rename the original configuration file back to its original name.
         let rollback_rename_old_result = rename(old_config_file_name, config_file_name);
         match rollback_rename_old_result {
-            Ok(_) => return rename_new_result,
+            Ok(_) => return Err(rename_new_error),
             Err(e) => try_with_context!(
-                Err(Errors::new(vec![
-                    Box::new(rename_new_result.unwrap_err()),
-                    Box::new(e)
-                ])),
+                Err(Errors::new(vec![Box::new(rename_new_error), Box::new(e)])),
                 "failed to save configuration"
             ),
         }
@@ -160,26 +589,100 @@ fn save_config(config: &Configuration, config_file_name: &Path) -> Result<(), Er
     // Delete the renamed original configuration file.
     try_with_context!(
         fs::remove_file(old_config_file_name),
+        format!("failed to remove file {}", old_config_file_name.display())
+    );
+
+    Ok(())
+}
+
+/// Reads back a configuration file that was just written
+/// and checks that it has all the feeds of `file`.
+fn check_saved_config(file: &ConfigFile, file_name: &Path) -> Result<(), ErrorWithContext> {
+    let saved = try_with_context!(
+        fs::read_to_string(file_name),
+        format!("failed to read {} back", file_name.display())
+    );
+    let saved_feeds = if file.main {
+        serde_yaml::from_str::<Configuration>(&saved).map(|saved| saved.feeds.len())
+    } else {
+        serde_yaml::from_str::<IncludeFile>(&saved).map(|saved| saved.feeds.len())
+    };
+    let saved_feeds = try_with_context!(
+        saved_feeds,
         format!(
-            "failed to remove file {}",
-            old_config_file_name.to_string_lossy()
+            "the configuration written to {} is invalid",
+            file_name.display()
         )
     );
+    if saved_feeds != file.feeds {
+        try_with_context!(
+            Err(InvalidSavedConfig::FeedCount(file.feeds, saved_feeds)),
+            format!(
+                "the configuration written to {} is invalid",
+                file_name.display()
+            )
+        );
+    }
 
     Ok(())
 }
 
+/// How many times to try each rename when saving the configuration.
+/// On Windows, antivirus software and search indexers
+/// briefly lock the files they scan,
+/// so a rename right after writing a file can fail.
+#[cfg(windows)]
+const RENAME_ATTEMPTS: u32 = 6;
+#[cfg(not(windows))]
+const RENAME_ATTEMPTS: u32 = 1;
+
+/// The delay between two attempts to rename a file.
+const RENAME_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Runs `operation` up to `attempts` times,
+/// waiting `delay` after each attempt that fails
+/// with an error for which `is_transient` returns true.
+fn retry<T>(
+    attempts: u32,
+    delay: Duration,
+    is_transient: impl Fn(&io::Error) -> bool,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Err(error) if attempt < attempts && is_transient(&error) => {
+                debug!("attempt {} failed ({}), retrying", attempt, error);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns whether a file operation failed
+/// because another process has the file open.
+fn is_file_locked(error: &io::Error) -> bool {
+    // ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION.
+    cfg!(windows) && matches!(error.raw_os_error(), Some(5 | 32 | 33))
+}
+
 fn init(config_file_name: &Path) -> Result<(), ErrorWithContext> {
+    if read_only() {
+        try_with_context!(
+            Err(ReadOnly::ReadOnly),
+            format!("failed to create file {}", config_file_name.display())
+        );
+    }
+
     // Only write a configuration file if it doesn't exist yet.
     let mut config_file = try_with_context!(
         OpenOptions::new()
             .write(true)
             .create_new(true)
             .open(config_file_name),
-        format!(
-            "failed to create file {}",
-            config_file_name.to_string_lossy()
-        )
+        format!("failed to create file {}", config_file_name.display())
     );
 
     let config = Configuration::default();
@@ -187,52 +690,108 @@ fn init(config_file_name: &Path) -> Result<(), ErrorWithContext> {
         serde_yaml::to_writer(&mut config_file, &config),
         format!(
             "failed to save configuration to {}",
-            config_file_name.to_string_lossy()
+            config_file_name.display()
         )
     );
 
     Ok(())
 }
 
-fn set_consumer_key(config: &mut Configuration, key: &str) {
-    config.consumer_key = Some(key.to_string());
+fn set_consumer_key(config: &mut Configuration, account: &str, key: &str) {
+    config.account_mut(account).consumer_key = Some(key.to_string());
 }
 
-fn login(config: &mut Configuration) -> Result<(), ErrorWithContext> {
-    let client = Client::new();
+fn login(config: &mut Configuration, args: &LoginCommand) -> Result<(), ErrorWithContext> {
+    let account = &*args.account;
+    let client = pocket_client(config)?;
     let mut pocket = try_with_context!(
-        get_pocket(config, client),
+        config.account(account).and_then(|account| get_pocket(
+            account,
+            client,
+            config.pocket_api_url.as_deref()
+        )),
         "unable to perform authorization"
     );
 
-    if config.access_token.is_some() {
-        println!(
+    if config
+        .account(account)
+        .is_ok_and(|account| account.access_token.is_some())
+    {
+        eprintln!(
             "note: There's already an access token in the configuration file. \
             Proceeding will overwrite this access token."
         );
     }
 
+    let redirect_uri = args
+        .redirect_uri
+        .as_ref()
+        .or(config.redirect_uri.as_ref())
+        .cloned()
+        .unwrap_or_else(|| Url::parse(DEFAULT_REDIRECT_URI).unwrap());
     let auth_url = try_with_context!(
-        pocket.get_auth_url(),
+        pocket.get_auth_url(&redirect_uri),
         "unable to get authorization URL for Pocket"
     );
-    println!("Go to the following webpage to login: {}", auth_url);
-    println!("Then, press Enter to continue.");
+    // Only the URL goes to the standard output, for scripts.
+    eprintln!("Go to the following webpage to login:");
+    println!("{}", auth_url);
+    if args.qr {
+        let width = terminal_size::terminal_size().map(|(width, _)| usize::from(width.0));
+        match qr_code(auth_url.as_str(), width) {
+            Ok(qr_code) => eprintln!("{}", qr_code),
+            Err(reason) => eprintln!("note: Unable to show a QR code: {}", reason),
+        }
+    }
+
+    if args.no_wait {
+        config.account_mut(account).pending_login = Some(PendingLogin {
+            code: pocket.code().unwrap().into(),
+            requested_at: Utc::now(),
+        });
+        eprintln!(
+            "Then, run `feeds-to-pocket <config> finish-login --account {}`.",
+            account
+        );
+        return Ok(());
+    }
+
+    eprintln!(
+        "Then, press Enter to continue \
+        (or paste the URL of the page Pocket sent you to, then press Enter)."
+    );
     loop {
         // Let the user authorize access to the application before proceeding.
-        let mut _input = String::new();
+        let mut input = String::new();
         try_with_context!(
-            std::io::stdin().read_line(&mut _input),
+            std::io::stdin().read_line(&mut input),
             "unable to read from standard input"
         );
 
+        // If the user pasted the URL they were redirected to,
+        // make sure it belongs to this login attempt.
+        let input = input.trim();
+        if !input.is_empty() {
+            let redirect_url = try_with_context!(
+                Url::parse(input),
+                format!("invalid redirect URL {:?}", input)
+            );
+            try_with_context!(
+                pocket.verify_state(&redirect_url),
+                "unable to verify the redirect URL; please login again"
+            );
+        }
+
         match pocket.authorize() {
-            Ok(_) => {
-                config.access_token = Some(String::from(pocket.access_token().unwrap()));
+            Ok(username) => {
+                config.account_mut(account).logged_in(&pocket, username);
+                if args.verify_permissions {
+                    verify_permissions(&mut pocket)?;
+                }
                 return Ok(());
             }
             Err(e) => {
-                println!(
+                eprintln!(
                     "Authorization failed: {}\n\
                     Make sure you authorized your application at the webpage linked above.\n\
                     Press Enter to try again, or press Ctrl+C to exit.",
@@ -243,538 +802,6664 @@ fn login(config: &mut Configuration) -> Result<(), ErrorWithContext> {
     }
 }
 
-fn sync(config: &mut Configuration) -> Result<(), ErrorWithContext> {
-    let client = Client::new();
-    let mut pocket = try_with_context!(
-        get_authenticated_pocket(config, client.clone()),
-        "unable to sync"
+/// Checks that the consumer key can add entries to Pocket
+/// by adding `PERMISSION_CHECK_URL`.
+/// Errors other than a missing permission are only reported,
+/// so that the access token is still saved.
+fn verify_permissions(pocket: &mut Pocket) -> Result<(), ErrorWithContext> {
+    eprintln!(
+        "Checking the consumer key's permissions by adding {} to your Pocket list.",
+        PERMISSION_CHECK_URL
     );
-
-    for feed in &mut config.feeds {
-        process_feed(feed, Some(&mut pocket), &client).unwrap_or_else(|e| {
-            let _ = writeln!(io::stderr(), "{}", e);
-        });
+    let url = Url::parse(PERMISSION_CHECK_URL).unwrap();
+    match pocket.add(&url, None, None, None) {
+        Ok(()) => eprintln!("The consumer key has the \"Add\" permission."),
+        Err(error @ PocketError::MissingPermission(..)) => {
+            try_with_context!(
+                Err(error),
+                "the consumer key can't be used to add entries to Pocket"
+            );
+        }
+        Err(error) => eprintln!(
+            "WARNING: unable to check the consumer key's permissions:\n  {}",
+            Indented(&error)
+        ),
     }
-
     Ok(())
 }
 
-fn add(config: &mut Configuration, args: &AddCommand) -> Result<(), ErrorWithContext> {
-    fn apply_tags(feed: &mut FeedConfiguration, args: &AddCommand) {
-        if let Some(tags) = &args.tags {
-            feed.tags = tags.to_owned();
-        }
-    }
+/// Exchanges the request token saved by `login --no-wait` for an access token.
+fn finish_login(config: &mut Configuration, account: &str) -> Result<(), ErrorWithContext> {
+    let client = pocket_client(config)?;
+    let (pocket, pending_login) = try_with_context!(
+        config.account(account).and_then(|a| {
+            let pending_login = a
+                .pending_login
+                .as_ref()
+                .ok_or_else(|| PocketSetupError::NoPendingLogin(account.into()))?;
+            if Utc::now() - pending_login.requested_at > PENDING_LOGIN_LIFETIME {
+                return Err(PocketSetupError::PendingLoginExpired(account.into()));
+            }
+            Ok((
+                get_pocket(a, client, config.pocket_api_url.as_deref())?,
+                pending_login,
+            ))
+        }),
+        "unable to finish the login"
+    );
 
-    let client = Client::new();
+    let mut pocket = pocket.with_code(&pending_login.code);
+    let username = try_with_context!(
+        pocket.authorize(),
+        format!(
+            "authorization failed; make sure you authorized your application \
+            at the webpage printed by `login --no-wait`, \
+            or run `login --no-wait --account {}` again",
+            account
+        )
+    );
 
-    let feed_url = &args.feed_url;
-    if let Some(feed) = config.feeds.iter_mut().find(|feed| &feed.url == feed_url) {
-        apply_tags(feed, args);
-        return Ok(());
+    config.account_mut(account).logged_in(&pocket, username);
+    Ok(())
+}
+
+/// Makes the state saved in the configuration smaller
+/// and reports by how much.
+fn compact_state(
+    config: &mut Configuration,
+    args: &CompactStateCommand,
+) -> Result<(), ErrorWithContext> {
+    fn size(config: &Configuration) -> Result<usize, ErrorWithContext> {
+        Ok(try_with_context!(
+            serde_yaml::to_string(config),
+            "failed to serialize the configuration"
+        )
+        .len())
     }
 
-    let send_to_pocket = args.unread;
-    let mut pocket = if send_to_pocket {
-        Some(try_with_context!(
-            get_authenticated_pocket(config, client.clone()),
-            "unable to add feed"
-        ))
+    let size_before = size(config)?;
+    let mut fetcher = if args.fetch {
+        Some(Fetcher::new(config)?)
     } else {
         None
     };
 
-    let mut feed = FeedConfiguration {
-        url: String::from(feed_url),
-        tags: String::new(),
-        processed_entries: vec![],
-        last_modified: None,
-        last_e_tag: None,
-    };
-    apply_tags(&mut feed, args);
-    config.feeds.push(feed);
-
-    let feed = config.feeds.last_mut().unwrap();
-
-    process_feed(feed, pocket.as_mut(), &client)
-}
+    let mut unseen = 0;
+    let mut trimmed = 0;
+    let mut hashed = 0;
+    let mut failures = FeedFailures::new();
+    for feed in &mut config.feeds {
+        if let Some(fetcher) = fetcher.as_mut().filter(|_| !feed.disabled) {
+            match fetch_current_feed(fetcher, feed) {
+                Ok(parsed_feed) => unseen += feed.forget_unseen_entries(&parsed_feed),
+                Err((_, e)) => failures.push(&feed.url, e),
+            }
+        }
+        if let Some(max_processed_entries) = config.max_processed_entries {
+            trimmed += feed.trim_processed_entries(max_processed_entries);
+        }
+        hashed += feed.hash_processed_entries();
+        feed.drop_empty_fields();
+    }
 
-fn remove(config: &mut Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
-    let len_before = config.feeds.len();
-    config.feeds.retain(|feed| feed.url != feed_url);
-    let len_after = config.feeds.len();
-    if len_before == len_after {
-        try_with_context!(
-            Err(FeedNotFound::FeedNotFound(feed_url.into())),
-            "failed to remove feed"
+    if !failures.is_empty() {
+        eprintln!(
+            "The following feeds couldn't be downloaded, so they kept all their entries:\n{}",
+            failures
         );
     }
-
+    if args.fetch {
+        println!(
+            "Removed {} processed entries that are no longer in their feeds.",
+            unseen
+        );
+    }
+    println!(
+        "Removed {} processed entries beyond max_processed_entries.",
+        trimmed
+    );
+    println!("Replaced {} processed entries with their hashes.", hashed);
+    let verb = if args.dry_run { "would go" } else { "went" };
+    println!(
+        "The configuration {} from {} to {} bytes.",
+        verb,
+        size_before,
+        size(config)?
+    );
     Ok(())
 }
 
-fn get_pocket(config: &Configuration, client: Client) -> Result<Pocket, PocketSetupError> {
-    match config.consumer_key {
-        Some(ref consumer_key) => Ok(Pocket::new(
-            consumer_key,
-            config.access_token.as_ref().map(|x| x.as_ref()),
-            client,
+/// Renders `data` as a QR code made of Unicode blocks,
+/// or returns why it can't be shown
+/// in a terminal that is `terminal_width` columns wide.
+fn qr_code(data: &str, terminal_width: Option<usize>) -> Result<String, String> {
+    let code = QrCode::new(data).map_err(|e| e.to_string())?;
+    // The colors are inverted for terminals with a dark background;
+    // the quiet zone keeps the code scannable on light backgrounds too.
+    let rendered = code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build();
+
+    let width = rendered.lines().map(|line| line.chars().count()).max();
+    match (width, terminal_width) {
+        (Some(width), Some(terminal_width)) if width > terminal_width => Err(format!(
+            "the terminal is too narrow ({} columns, {} needed)",
+            terminal_width, width
         )),
-        None => Err(PocketSetupError::MissingConsumerKey),
+        _ => Ok(rendered),
     }
 }
 
-fn get_authenticated_pocket(
-    config: &Configuration,
-    client: Client,
-) -> Result<Pocket, PocketSetupError> {
-    get_pocket(config, client).and_then(|pocket| match config.access_token {
-        Some(_) => Ok(pocket),
-        None => Err(PocketSetupError::MissingAccessToken),
-    })
-}
-
-fn process_feed(
-    feed: &mut FeedConfiguration,
-    mut pocket: Option<&mut Pocket>,
-    client: &Client,
-) -> Result<(), ErrorWithContext> {
-    println!("downloading {}", feed.url);
-    let feed_response = try_with_context!(
-        fetch(feed, client),
-        format!("failed to download feed at {url}", url = feed.url)
-    );
-
-    // Do nothing if we received a 304 Not Modified response.
-    if let FeedResponse::Success {
-        body,
-        last_modified,
-        e_tag,
-    } = feed_response
-    {
-        let parsed_feed = try_with_context!(
-            body.parse::<Feed>(),
-            format!(
-                "failed to parse feed at {url} as either RSS or Atom",
-                url = feed.url
-            )
-        );
+/// Processes all the feeds in the configuration.
+///
+/// Errors that occur while processing a feed don't stop the sync;
+/// they are collected and returned at the end
+/// so they can be reported together,
+/// along with the feeds that were skipped because the run exceeded its budget.
+fn sync(
+    config: &mut Configuration,
+    args: &SyncCommand,
+) -> Result<(FeedFailures, Option<BudgetExceeded>), ErrorWithContext> {
+    // Set up a Pocket client for every account the feeds are sent to.
+    let client = pocket_client(config)?;
+    let push_workers = config.push_workers.unwrap_or(DEFAULT_PUSH_WORKERS);
+    let is_selected = |feed: &FeedConfiguration| {
+        !feed.disabled
+            && args
+                .group
+                .as_ref()
+                .is_none_or(|group| feed.group_name() == group)
+    };
+    for feed_url in &args.accept_republished {
+        try_with_context!(find_feed(config, feed_url), "unable to sync");
+    }
 
-        let (mut rss_entries, mut atom_entries);
-        let entries: &mut dyn Iterator<Item = &str> = match parsed_feed {
-            Feed::Rss(ref rss) => {
-                rss_entries = rss.items().iter().rev().flat_map(|item| item.link());
-                &mut rss_entries
-            }
-            Feed::Atom(ref atom) => {
-                atom_entries = atom
-                    .entries()
-                    .iter()
-                    .rev()
-                    .flat_map(|entry| entry.links())
-                    .filter_map(|link| {
-                        match link.rel() {
-                            // Only push links with an "alternate" relation type.
-                            "alternate" | "http://www.iana.org/assignments/relation/alternate" => {
-                                Some(link.href())
-                            }
-                            _ => None,
-                        }
-                    });
-                &mut atom_entries
-            }
-        };
-
-        let mut all_processed_successfully = true;
-        for entry_url in entries {
-            // The rss and atom_syndication libraries
-            // don't trim the values extracted from the XML files.
-            let entry_url = entry_url.trim();
-
-            // Ignore entries we've processed previously.
-            if !feed.processed_entries.iter().rev().any(|x| x == entry_url) {
-                let is_processed = if let Some(ref mut pocket) = pocket {
-                    match Url::parse(entry_url) {
-                        Ok(parsed_entry_url) => {
-                            // Push the entry to Pocket.
-                            // Only consider the entry processed if the push succeeded.
-                            // That means that if it failed, we'll try again next time.
-                            println!("pushing {} to Pocket", entry_url);
-                            let tags = if feed.tags.is_empty() {
-                                None
-                            } else {
-                                Some(&*feed.tags)
-                            };
-                            let push_result = pocket.add(&parsed_entry_url, None, tags, None);
-                            match push_result {
-                                Ok(_) => true,
-                                Err(error) => {
-                                    println!(
-                                        "error while adding URL {url} to Pocket:\n  {error}",
-                                        url = entry_url,
-                                        error = Indented(&error)
-                                    );
-                                    false
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            println!("'{}' is not a valid URL ({}). ignoring.", entry_url, e);
-
-                            // Mark the entry as processed,
-                            // to avoid noise in subsequent runs.
-                            true
-                        }
-                    }
-                } else {
-                    // If `pocket` is None,
-                    // then we just want to mark the current feed entries as processed,
-                    // on the assumption that the user has read them already.
-                    true
-                };
-
-                if is_processed {
-                    // Remember that we've processed this entry
-                    // so we don't try to send it to Pocket next time.
-                    feed.processed_entries.push(entry_url.into());
-                } else {
-                    all_processed_successfully = false;
-                }
+    // A dry run doesn't contact Pocket or record anything in the history.
+    let history = if args.dry_run {
+        None
+    } else {
+        open_history(config)?
+    };
+    let mut pools = HashMap::new();
+    // The accounts that can't be used (e.g. because nobody logged in yet)
+    // only stop the feeds that are sent to them.
+    let mut account_errors = BTreeMap::new();
+    for feed in config.feeds.iter().filter(|feed| is_selected(feed)) {
+        for account in feed.account_names() {
+            if pools.contains_key(account) || account_errors.contains_key(account) {
+                continue;
             }
+            if args.dry_run {
+                pools.insert(
+                    account.to_owned(),
+                    PushPool::new(DryRun, 1, None, config.tag_all.clone()),
+                );
+                continue;
+            }
+            // Offline, the entries are queued for the next sync.
+            if args.offline {
+                pools.insert(
+                    account.to_owned(),
+                    PushPool::new(Offline, 1, None, config.tag_all.clone()),
+                );
+                continue;
+            }
+            let pocket = match config.account(account).and_then(|account| {
+                get_authenticated_pocket(account, client.clone(), config.pocket_api_url.as_deref())
+            }) {
+                Ok(pocket) => pocket,
+                Err(e) => {
+                    account_errors.insert(account.to_owned(), (e, vec![]));
+                    continue;
+                }
+            };
+            pools.insert(
+                account.to_owned(),
+                PushPool::new(
+                    pocket,
+                    push_workers,
+                    history.clone(),
+                    config.tag_all.clone(),
+                ),
+            );
         }
+    }
 
-        // Don't update the last modified and last ETag
-        // if any push to Pocket failed
-        // so we can try again next time.
-        if all_processed_successfully {
-            feed.last_modified = last_modified.and_then(|v| v.to_str().ok().map(|s| s.into()));
-            feed.last_e_tag = e_tag.and_then(|v| v.to_str().ok().map(|s| s.into()));
+    // Send the entries that couldn't be sent by previous syncs
+    // before adding new ones, to keep them in order.
+    let max_push_attempts = config
+        .max_push_attempts
+        .unwrap_or(DEFAULT_MAX_PUSH_ATTEMPTS);
+    for feed in &mut config.feeds {
+        if is_selected(feed) && !feed.push_queue.is_empty() && !args.offline {
+            let push_delay = feed.push_delay(config.push_delay_ms);
+            send_push_queue(feed, &pools, push_delay, max_push_attempts);
         }
     }
 
-    Ok(())
-}
+    let mut fetcher = Fetcher::new(config)?;
+    fetcher.set_force(args.force);
+    fetcher.set_offline(args.offline);
+    let feed_urls: HashSet<String> = config.feeds.iter().map(|feed| feed.url.clone()).collect();
+    let feed_urls = Some(&feed_urls).filter(|_| args.update_feed_urls);
+    let start = Instant::now();
+    let mut failures = FeedFailures::new();
+    let mut exceeded_budget = None;
+    let mut skipped = vec![];
+    let mut offline_skipped = 0;
+    let mut feed_failed = false;
+    // Only ask when someone can answer,
+    // and when something will actually be sent.
+    let mut confirmation =
+        (!args.yes && !args.dry_run && io::stdin().is_terminal() && io::stderr().is_terminal())
+            .then(|| {
+                PushConfirmation::new(
+                    config
+                        .confirm_pushes_over
+                        .unwrap_or(DEFAULT_CONFIRM_PUSHES_OVER),
+                    Box::new(io::stdin().lock()),
+                )
+            });
+    for i in sync_order(&config.feeds) {
+        let feed = &mut config.feeds[i];
+        if !is_selected(feed) {
+            continue;
+        }
 
-fn fetch(feed: &FeedConfiguration, client: &Client) -> Result<FeedResponse, ErrorWithContext> {
-    let mut request = client.get(&feed.url);
-    request = request.header(
-        header::USER_AGENT,
-        HeaderValue::from_static(concat!("feeds-to-pocket/", env!("CARGO_PKG_VERSION"))),
-    );
+        // Finish the current feed, but don't start another one
+        // once the budget is exceeded.
+        if exceeded_budget.is_none() {
+            exceeded_budget = args.exceeded_budget(start, &fetcher);
+        }
+        if exceeded_budget.is_some() {
+            skipped.push(feed.url.clone());
+            continue;
+        }
 
-    // Add an If-Modified-Since header if we have a Last-Modified date.
-    if let Some(ref last_modified) = feed.last_modified {
-        request = request.header(
-            header::IF_MODIFIED_SINCE,
-            HeaderValue::from_str(last_modified)
-                .expect("Failed to convert last_modified to HeaderValue"),
-        );
-    }
+        // Skipping a feed because of --offline isn't a failure.
+        if args.offline && matches!(FeedSource::of(&feed.url), FeedSource::Http) {
+            eprintln!("skipped {} (offline)", feed.label());
+            offline_skipped += 1;
+            continue;
+        }
 
-    // Add an If-None-Match header if we have an ETag.
-    if let Some(ref e_tag) = feed.last_e_tag {
-        request = request.header(
-            header::IF_NONE_MATCH,
-            HeaderValue::from_str(e_tag).expect("Failed to convert last_e_tag to HeaderValue"),
-        );
+        // The feed is synced once all of its accounts can be used,
+        // so that no account misses its entries.
+        let unusable_accounts: Vec<&str> = feed
+            .account_names()
+            .into_iter()
+            .filter(|account| account_errors.contains_key(*account))
+            .collect();
+        if !unusable_accounts.is_empty() {
+            for account in unusable_accounts {
+                account_errors
+                    .get_mut(account)
+                    .unwrap()
+                    .1
+                    .push(feed.label());
+            }
+            feed_failed = true;
+            continue;
+        }
+
+        feed.last_synced = Some(Utc::now());
+        let targets: Vec<(&str, &PushPool)> = feed
+            .account_names()
+            .into_iter()
+            .filter_map(|account| pools.get_key_value(account))
+            .map(|(account, pool)| (account.as_str(), pool))
+            .collect();
+        let push_delay = feed.push_delay(config.push_delay_ms);
+        let limits = PushLimits {
+            max_pushes: Some(feed.max_pushes_per_run(config.max_pushes_per_run))
+                .filter(|_| !args.no_limit),
+            accept_republished: args
+                .accept_republished
+                .iter()
+                .any(|feed_url| feed.is_identified_by(feed_url)),
+            confirmation: confirmation.as_mut(),
+            flush_digests: args.flush_digests,
+            max_url_length: config.max_url_length,
+        };
+        match process_feed(feed, &targets, &mut fetcher, push_delay, limits, feed_urls) {
+            Ok(push_errors) => {
+                feed.last_error = None;
+                if !push_errors.is_empty() {
+                    let context = format!("failed to push {} entries to Pocket", push_errors.len());
+                    let errors = push_errors.into_iter().map(From::from).collect();
+                    failures.push(
+                        &feed.label(),
+                        ErrorWithContext::new(Box::new(Errors::new(errors)), context),
+                    );
+                }
+            }
+            Err(e) => {
+                feed.last_error = Some(e.to_string());
+                failures.push(&feed.label(), e);
+                feed_failed = true;
+            }
+        }
     }
 
-    let mut response = try_with_context!(request.send(), "failed to send request");
-    if response.status() == StatusCode::NOT_MODIFIED {
-        Ok(FeedResponse::NotModified)
-    } else {
-        if !response.status().is_success() {
-            try_with_context!(
-                Err(UnacceptableHttpStatus::UnacceptableHttpStatus(
-                    response.status()
-                )),
-                format!(
-                    "the HTTP request to <{}> didn't return a success status",
-                    feed.url
-                )
+    // Report each account once, rather than once per feed.
+    for (account, (error, feed_labels)) in account_errors {
+        if !feed_labels.is_empty() {
+            let context = format!("unable to send entries to account {}", account);
+            failures.push(
+                &feed_labels.join(", "),
+                ErrorWithContext::new(Box::new(error), context),
             );
         }
+    }
 
-        let last_modified = response.headers().get(header::LAST_MODIFIED).cloned();
-        let e_tag = response.headers().get(header::ETAG).cloned();
-
-        let mut body = String::new();
-        try_with_context!(
-            response.read_to_string(&mut body),
-            "failed to read response"
+    if offline_skipped > 0 {
+        eprintln!(
+            "skipped (offline): {} feeds that need the network",
+            offline_skipped
         );
+    }
 
-        Ok(FeedResponse::Success {
-            body,
-            last_modified,
-            e_tag,
-        })
+    let now = Utc::now();
+    config.last_sync = Some(now);
+    // An offline sync doesn't sync every feed.
+    if !feed_failed && offline_skipped == 0 {
+        config.last_successful_sync = Some(now);
     }
+
+    let budget_exceeded =
+        exceeded_budget.map(|reason| BudgetExceeded::BudgetExceeded(reason, skipped));
+    Ok((failures, budget_exceeded))
 }
 
-/// Simple program to greet a person
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None, display_name = "Feeds to Pocket")]
-struct Args {
-    /// A YAML file containing your feeds configuration.
-    //#[clap(short, long, value_parser)]
-    #[clap(index = 1)]
-    config: PathBuf,
+/// Returns the indices of the feeds in the order `sync` processes them:
+/// least recently synced first (feeds that were never synced come first),
+/// so that the feeds skipped by an interrupted sync are synced first next time.
+/// Feeds synced at the same time keep their order in the configuration.
+fn sync_order(feeds: &[FeedConfiguration]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..feeds.len()).collect();
+    order.sort_by_key(|&i| feeds[i].last_synced);
+    order
+}
 
-    #[clap(subcommand)]
-    command: Option<Command>,
+/// Returns the URL of the feed of a page of a well-known site
+/// (e.g. a YouTube channel or a subreddit),
+/// or the URL itself if it isn't such a page.
+/// If the page must be downloaded to find its feed and that fails,
+/// a warning is printed and the URL itself is returned.
+fn resolve_known_sites(fetcher: &mut Fetcher, url: &str, github_feed: GitHubFeed) -> String {
+    let feed_url = match sites::site_feed(url, github_feed) {
+        None => return url.to_owned(),
+        Some(SiteFeed::Feed(feed_url)) => feed_url,
+        Some(SiteFeed::YouTubeChannelPage) => match fetch_youtube_channel_id(fetcher, url) {
+            Ok(channel_id) => sites::youtube_channel_feed_url(&channel_id),
+            Err(message) => {
+                eprintln!(
+                    "WARNING: unable to find the feed of {}: {}; adding the URL as is",
+                    url, message
+                );
+                return url.to_owned();
+            }
+        },
+    };
+    eprintln!("using the feed of {}: {}", url, feed_url);
+    feed_url
 }
 
-impl Args {
-    fn with_config(
-        &self,
-        mut callback: impl FnMut(&mut Configuration) -> Result<(), ErrorWithContext>,
-    ) -> Result<(), ErrorWithContext> {
-        let mut config = load_config(&self.config)?;
+/// Downloads a YouTube page to find the ID of its channel.
+fn fetch_youtube_channel_id(fetcher: &mut Fetcher, url: &str) -> Result<String, String> {
+    let page = FeedConfiguration {
+        url: url.to_owned(),
+        ..FeedConfiguration::default()
+    };
+    match fetcher.fetch(&page).map_err(|e| e.to_string())? {
+        FeedResponse::Success { body, .. } => {
+            sites::youtube_channel_id(&body).ok_or_else(|| "no channel ID on the page".to_owned())
+        }
+        // Some servers answer 304 Not Modified to any request.
+        FeedResponse::NotModified { .. } => {
+            Err("the server answered 304 Not Modified to an unconditional request".to_owned())
+        }
+    }
+}
+
+fn add(config: &mut Configuration, args: &AddCommand) -> Result<(), ErrorWithContext> {
+    fn apply_tags(feed: &mut FeedConfiguration, args: &AddCommand) {
+        if let Some(tags) = args.tags.as_ref().or(args.tags_replace.as_ref()) {
+            feed.tags = join_tags([tags.as_str()]).unwrap_or_default();
+        }
+        if let Some(tags) = &args.tags_append {
+            feed.tags = join_tags([feed.tags.as_str(), tags]).unwrap_or_default();
+        }
+        if let Some(account) = &args.account {
+            feed.account = Some(account.to_owned()).filter(|x| x != DEFAULT_ACCOUNT);
+        }
+        if let Some(name) = &args.name {
+            feed.name = Some(name.to_owned());
+        }
+        if let Some(group) = &args.group {
+            feed.group = Some(group.to_owned()).filter(|x| x != DEFAULT_GROUP);
+        }
+    }
 
-        callback(&mut config)?;
+    let client = pocket_client(config)?;
+    let mut fetcher = Fetcher::new(config)?;
 
-        save_config(&config, &self.config)
+    let resolved_url;
+    let feed_id = if config
+        .feeds
+        .iter()
+        .any(|feed| feed.is_identified_by(&args.feed_url))
+    {
+        &args.feed_url
+    } else {
+        resolved_url = args.resolve_feed_url(&mut fetcher);
+        &resolved_url
+    };
+    let feed_url = match config
+        .feeds
+        .iter()
+        .find(|feed| feed.is_identified_by(feed_id))
+    {
+        Some(feed) => &feed.url,
+        None => feed_id,
+    };
+    if let Some(name) = &args.name {
+        try_with_context!(config.check_feed_name(name, feed_url), "unable to add feed");
     }
-}
 
-#[derive(Parser, Debug)]
-enum Command {
-    /// Creates an empty configuration file (if it doesn't already exist).
-    Init,
+    if let Some(feed) = config
+        .feeds
+        .iter_mut()
+        .find(|feed| feed.is_identified_by(feed_id))
+    {
+        // Replacing a curated list of tags by mistake is easy,
+        // so ask which one was meant.
+        if args.tags.is_some() {
+            try_with_context!(
+                Err(AmbiguousTags::FeedExists(feed.url.clone())),
+                "unable to update feed"
+            );
+        }
+        apply_tags(feed, args);
+        return Ok(());
+    }
 
-    /// Sets the consumer key in the configuration file.
-    SetConsumerKey {
-        /// A consumer key obtained from Pocket's website.
-        /// You must create your own application
-        /// at https://getpocket.com/developer/apps/new
-        /// to obtain a consumer key;
-        /// I don't want you kicking me out of my own application! :)
-        /// Make sure your application has at least the "Add" permission.
-        key: String,
-    },
+    if let FeedSource::Unsupported(scheme) = FeedSource::of(feed_id) {
+        try_with_context!(
+            Err(UnsupportedScheme::UnsupportedScheme(scheme)),
+            "unable to add feed"
+        );
+    }
 
-    /// Obtains and saves an access token from Pocket.
-    /// This will print a URL on the standard output,
-    /// which you must open in a web browser
-    /// in order to grant your application access to your Pocket account.
-    /// Once authorization has been obtained,
-    /// an access token is saved in the configuration file,
-    /// which will be used to queue up entries in your Pocket list.
-    Login,
+    let mut feed = FeedConfiguration {
+        url: String::from(feed_id),
+        ..FeedConfiguration::default()
+    };
+    apply_tags(&mut feed, args);
 
-    /// Adds a feed to your feeds configuration
-    /// or updates an existing feed in your feeds configuration.
-    Add(AddCommand),
+    let send_to_pocket = args.unread;
+    let pocket = if send_to_pocket {
+        Some(try_with_context!(
+            config
+                .account(feed.account_name())
+                .and_then(|account| get_authenticated_pocket(
+                    account,
+                    client.clone(),
+                    config.pocket_api_url.as_deref()
+                )),
+            "unable to add feed"
+        ))
+    } else {
+        None
+    };
 
-    /// Removes a feed from your feeds configuration.
-    Remove {
-        /// The URL of the feed to remove.
-        feed_url: String,
-    },
-}
+    if let Some(pocket) = pocket.as_ref().filter(|_| args.skip_existing) {
+        let mut pocket = pocket.clone();
+        let (saved_urls, _) = try_with_context!(
+            pocket.get_all_urls(None),
+            "failed to retrieve your Pocket list"
+        );
+        let parsed_feed = fetch_current_feed(&mut fetcher, &feed).map_err(|(_, e)| e)?;
+        let count = record_saved_urls(&mut feed, &parsed_feed, &saved_urls);
+        eprintln!("{} entries are already in Pocket", count);
+    }
 
-#[derive(Parser, Debug)]
-struct AddCommand {
-    /// Consider all the entries in the feed to be unread.
-    /// All entries will be sent to Pocket immediately.
-    /// By default, all the entries present when the feed is added
-    /// are considered read and are not sent to Pocket.
-    #[clap(long)]
-    unread: bool,
+    let history = open_history(config)?;
+    config.feeds.push(feed);
 
-    /// A comma-separated list of tags to attach to the URLs sent to Pocket.
-    #[clap(long)]
-    tags: Option<String>,
+    let feed = config.feeds.last_mut().unwrap();
 
-    /// The URL of the feed to add.
-    feed_url: String,
+    // Errors that occur while pushing entries have already been reported,
+    // and the failed entries will be retried on the next sync.
+    let push_delay = feed.push_delay(config.push_delay_ms);
+    let pool = pocket.map(|pocket| PushPool::new(pocket, 1, history, config.tag_all.clone()));
+    let account = feed.account_name().to_owned();
+    let targets: Vec<(&str, &PushPool)> = pool.iter().map(|pool| (&*account, pool)).collect();
+    process_feed(
+        feed,
+        &targets,
+        &mut fetcher,
+        push_delay,
+        PushLimits {
+            max_url_length: config.max_url_length,
+            ..PushLimits::default()
+        },
+        None,
+    )
+    .map(|_| ())
 }
 
-#[derive(Default, Deserialize, Serialize)]
-struct Configuration {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    consumer_key: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    access_token: Option<String>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    #[serde(default)]
-    feeds: Vec<FeedConfiguration>,
-}
+/// Shows what `add` would do with a feed's entries.
+fn preview(config: &Configuration, args: &AddCommand) -> Result<(), ErrorWithContext> {
+    let mut fetcher = Fetcher::new(config)?;
 
-#[derive(Deserialize, Serialize)]
-struct FeedConfiguration {
-    url: String,
-    #[serde(skip_serializing_if = "str::is_empty")]
-    #[serde(default)]
-    tags: String,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    #[serde(default)]
-    processed_entries: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    last_modified: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    last_e_tag: Option<String>,
-}
+    let feed_id = if config
+        .feeds
+        .iter()
+        .any(|feed| feed.is_identified_by(&args.feed_url))
+    {
+        args.feed_url.clone()
+    } else {
+        args.resolve_feed_url(&mut fetcher)
+    };
+    let existing_feed = config
+        .feeds
+        .iter()
+        .find(|feed| feed.is_identified_by(&feed_id));
+    let feed = existing_feed.cloned().unwrap_or_else(|| FeedConfiguration {
+        url: feed_id,
+        ..FeedConfiguration::default()
+    });
+    let parsed_feed = fetch_current_feed(&mut fetcher, &feed).map_err(|(_, e)| e)?;
+    let new_entries = new_entries(&feed, &parsed_feed);
 
-enum FeedResponse {
-    Success {
-        body: String,
-        last_modified: Option<HeaderValue>,
-        e_tag: Option<HeaderValue>,
-    },
-    NotModified,
-}
+    println!("{}", feed.url);
+    println!(
+        "  title: {}",
+        parsed_feed.title.as_deref().unwrap_or("(none)")
+    );
+    println!("  entries: {}", parsed_feed.entries.len());
+    if existing_feed.is_some() {
+        println!(
+            "  this feed was already added; the next sync would send these entries to Pocket:"
+        );
+    } else if args.unread {
+        println!("  these entries would be sent to Pocket:");
+    } else {
+        println!("  these entries would be marked as read:");
+    }
 
-enum Feed {
-    Atom(Box<atom_syndication::Feed>),
-    Rss(Box<rss::Channel>),
+    for entry in new_entries.iter().take(args.limit) {
+        match entry.title {
+            Some(title) => println!("    {} ({})", entry.url, title),
+            None => println!("    {}", entry.url),
+        }
+    }
+    if new_entries.len() > args.limit {
+        println!("    ... and {} more", new_entries.len() - args.limit);
+    } else if new_entries.is_empty() {
+        println!("    (none)");
+    }
+
+    Ok(())
 }
 
-impl FromStr for Feed {
-    type Err = FeedError;
+/// Marks the entries of feeds that are already in the user's Pocket list
+/// as processed.
+fn import_pocket_state(
+    config: &mut Configuration,
+    args: &ImportPocketStateCommand,
+) -> Result<(), ErrorWithContext> {
+    let client = pocket_client(config)?;
+    let mut pocket = try_with_context!(
+        config
+            .account(&args.account)
+            .and_then(|account| get_authenticated_pocket(
+                account,
+                client,
+                config.pocket_api_url.as_deref()
+            )),
+        "unable to import Pocket state"
+    );
+
+    let (saved_urls, since) = try_with_context!(
+        pocket.get_all_urls(args.since),
+        "failed to retrieve your Pocket list"
+    );
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.parse::<atom_syndication::Feed>() {
-            Ok(feed) => Ok(Feed::Atom(Box::new(feed))),
-            Err(atom_error) => match s.parse::<rss::Channel>() {
-                Ok(channel) => Ok(Feed::Rss(Box::new(channel))),
-                Err(rss_error) => Err(FeedError {
-                    atom_error,
-                    rss_error,
-                }),
-            },
+    let mut fetcher = Fetcher::new(config)?;
+    let mut failures = FeedFailures::new();
+    let feeds = config
+        .feeds
+        .iter_mut()
+        .filter(|feed| feed.account_name() == args.account)
+        .filter(|feed| {
+            args.feed_urls.is_empty() || args.feed_urls.iter().any(|id| feed.is_identified_by(id))
+        });
+    for feed in feeds {
+        match fetch_current_feed(&mut fetcher, feed) {
+            Ok(parsed_feed) => {
+                let count = record_saved_urls(feed, &parsed_feed, &saved_urls);
+                println!("{}: {} entries marked as processed", feed.url, count);
+            }
+            Err((_, e)) => failures.push(&feed.url, e),
         }
     }
-}
 
-#[derive(Debug)]
-struct FeedError {
-    atom_error: atom_syndication::Error,
-    rss_error: rss::Error,
-}
+    if let Some(since) = since {
+        eprintln!(
+            "To only import the items saved to Pocket from now on next time, pass --since {}",
+            since
+        );
+    }
 
-impl Display for FeedError {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "could not parse input as either Atom or RSS:\n  parsing as Atom failed with:\n    {}\n  parsing as RSS failed with:\n    {}",
-            Indented(Indented(&self.atom_error)), Indented(Indented(&self.rss_error)))
+    if !failures.is_empty() {
+        try_with_context!(Err(failures), "some feeds could not be imported");
     }
+
+    Ok(())
 }
 
-impl Error for FeedError {
-    fn description(&self) -> &str {
-        "could not parse input as either Atom or RSS"
+/// Adds the URLs saved to Pocket
+/// that are on the same host as one of the feed's entries
+/// to the feed's processed entries.
+///
+/// Returns the number of entries that were added.
+fn record_saved_urls(
+    feed: &mut FeedConfiguration,
+    parsed_feed: &Feed,
+    saved_urls: &[String],
+) -> usize {
+    fn host(url: &str) -> Option<String> {
+        Url::parse(url).ok()?.host_str().map(String::from)
     }
-}
 
-#[derive(Debug)]
-struct ErrorWithContext {
-    error: Box<dyn Error>,
-    context: String,
-}
+    let hosts: HashSet<_> = parsed_feed
+        .entries
+        .iter()
+        .flat_map(|entry| entry.alternate_links())
+        .filter_map(host)
+        .collect();
 
-impl ErrorWithContext {
-    fn new<S: Into<String>>(error: Box<dyn Error>, context: S) -> ErrorWithContext {
-        ErrorWithContext {
-            error,
-            context: context.into(),
+    let mut processed = feed.processed_keys();
+    let mut count = 0;
+    for url in saved_urls {
+        if host(url).is_some_and(|host| hosts.contains(&host)) && processed.insert(url) {
+            feed.mark_processed(url, EntryStatus::MarkedRead);
+            count += 1;
         }
     }
+
+    count
 }
 
-impl Display for ErrorWithContext {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}:\n  {}", self.context, Indented(&self.error))
-    }
+fn export_state(config: &Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
+    let feed = try_with_context!(find_feed(config, feed_url), "failed to export feed state");
+    try_with_context!(
+        serde_yaml::to_writer(io::stdout(), &FeedState::of(feed)),
+        "failed to export feed state"
+    );
+    Ok(())
 }
 
-impl Error for ErrorWithContext {
-    fn description(&self) -> &str {
-        &self.context
-    }
+fn import_state(
+    config: &mut Configuration,
+    feed_url: &str,
+    state_file_name: &Path,
+) -> Result<(), ErrorWithContext> {
+    let state_file = try_with_context!(
+        fs::read_to_string(state_file_name),
+        format!("failed to open file {}", state_file_name.display())
+    );
+    let state: FeedState = try_with_context!(
+        parse_yaml(&state_file),
+        format!(
+            "failed to load feed state from {}",
+            state_file_name.display()
+        )
+    );
 
-    fn cause(&self) -> Option<&dyn Error> {
-        Some(&*self.error)
-    }
+    let feed = try_with_context!(
+        config
+            .feeds
+            .iter_mut()
+            .find(|feed| feed.is_identified_by(feed_url))
+            .ok_or_else(|| FeedNotFound::FeedNotFound(feed_url.into())),
+        "failed to import feed state"
+    );
+    feed.merge_state(state);
+    Ok(())
 }
 
-quick_error! {
-    #[derive(Debug)]
-    enum PocketSetupError {
-        MissingConsumerKey {
-            display("The consumer key is not set in the configuration file. Run `feeds-to-pocket help set-consumer-key` for help and instructions.")
-        }
-        MissingAccessToken {
-            display("The access token is not set in the configuration file. Run `feeds-to-pocket help login` for help and instructions.")
-        }
-    }
-}
+/// Marks the entries currently in a feed as processed,
+/// e.g. after the feed republished its archive by mistake.
+fn mark_read(config: &mut Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
+    let mut fetcher = Fetcher::new(config)?;
+    let feed = try_with_context!(
+        config
+            .feeds
+            .iter_mut()
+            .find(|feed| feed.is_identified_by(feed_url))
+            .ok_or_else(|| FeedNotFound::FeedNotFound(feed_url.into())),
+        "failed to mark entries as read"
+    );
 
-quick_error! {
-    #[derive(Debug)]
-    enum UnacceptableHttpStatus {
-        UnacceptableHttpStatus(status: StatusCode) {
-            display("{}", status)
-        }
+    let parsed_feed = fetch_current_feed(&mut fetcher, feed).map_err(|(_, e)| e)?;
+    let entry_urls: Vec<String> = new_entries(feed, &parsed_feed)
+        .into_iter()
+        .map(|entry| entry.url.to_owned())
+        .collect();
+    for entry_url in &entry_urls {
+        feed.mark_processed(entry_url, EntryStatus::MarkedRead);
     }
+
+    println!("{} entries marked as read", entry_urls.len());
+    Ok(())
 }
 
-quick_error! {
-    #[derive(Debug)]
-    enum Errors {
-        Errors(errors: Vec<Box<dyn Error>>) {
-            display("{}", errors.iter().map(|error| format!("- {}", Indented(error))).collect::<Vec<_>>().join("\n"))
-        }
-    }
+/// Makes the next sync try again the entries of a feed
+/// that were skipped because their URL was invalid.
+fn reprocess_invalid(config: &mut Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
+    let feed = try_with_context!(
+        config
+            .feeds
+            .iter_mut()
+            .find(|feed| feed.is_identified_by(feed_url))
+            .ok_or_else(|| FeedNotFound::FeedNotFound(feed_url.into())),
+        "failed to reprocess invalid entries"
+    );
+
+    let count = feed.reprocess_invalid_entries();
+    println!("{} entries will be tried again on the next sync", count);
+    Ok(())
 }
 
-quick_error! {
-    #[derive(Debug)]
-    enum FeedNotFound {
-        FeedNotFound(url: String) {
-            display("No feed with URL {} was found.", url)
+/// Merges the feeds from another configuration file into this configuration.
+fn merge(config: &mut Configuration, args: &MergeCommand) -> Result<(), ErrorWithContext> {
+    let other = load_config(&args.other_config, false)?;
+
+    let mut added = 0;
+    let mut merged = 0;
+    for other_feed in other.feeds {
+        match config
+            .feeds
+            .iter_mut()
+            .find(|feed| feed.url == other_feed.url)
+        {
+            Some(feed) => {
+                println!("merging {}", feed.url);
+                if !other_feed.tags.is_empty() && (args.prefer_other || feed.tags.is_empty()) {
+                    feed.tags = other_feed.tags.clone();
+                }
+                feed.merge_state(FeedState::of(&other_feed));
+                merged += 1;
+            }
+            None => {
+                println!("adding {}", other_feed.url);
+                // The feed is saved to this configuration file,
+                // even if the other configuration included it from another file.
+                config.feeds.push(FeedConfiguration {
+                    source_file: None,
+                    ..other_feed
+                });
+                added += 1;
+            }
         }
     }
-}
 
-impl Errors {
-    fn new(errors: Vec<Box<dyn Error>>) -> Errors {
-        Errors::Errors(errors)
-    }
+    println!("{} feeds added, {} feeds merged", added, merged);
+    Ok(())
 }
 
-/// Wraps a type implementing Display
-/// and adds two spaces after each line feed in its display output.
-struct Indented<D: Display>(D);
+/// Shows the entries that the next sync would send to Pocket
+/// for one feed or for all feeds.
+fn pending(config: &Configuration, args: &PendingCommand) -> Result<(), ErrorWithContext> {
+    let feeds = match &args.feed_url {
+        Some(feed_url) => vec![try_with_context!(
+            find_feed(config, feed_url),
+            "failed to show pending entries"
+        )],
+        None => config.feeds.iter().collect(),
+    };
 
-impl<D: Display> Display for Indented<D> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        use std::fmt::Write;
-        write!(IndentedWrite(fmt), "{}", self.0)
-    }
-}
+    let mut fetcher = Fetcher::new(config)?;
+    let mut failures = FeedFailures::new();
+    for feed in feeds {
+        // Download the feed even if it wasn't modified,
+        // since we don't keep the entries from the last download.
+        let parsed_feed = match fetch_current_feed(&mut fetcher, feed) {
+            Ok(parsed_feed) => parsed_feed,
+            Err((kind, e)) => {
+                eprintln!("{} {}", kind, feed.url);
+                failures.push(&feed.url, e);
+                continue;
+            }
+        };
 
-/// Intercepts writes to a `std::fmt::Formatter`
-/// and adds two spaces after each line feed written to it.
-struct IndentedWrite<'a: 'f, 'f>(&'f mut fmt::Formatter<'a>);
+        let mut entries = new_entries(feed, &parsed_feed);
 
-// The documentation recommends implementing std::io::Write,
-// but that trait operates on a stream of bytes,
-// whereas std::fmt::Write operates on string slices.
-// Additionally, we call Formatter::write_str(),
-// which returns a Result<(), std::fmt::Error>,
-// which matches the signature of std::fmt::Write::write_str().
-impl<'a: 'f, 'f> fmt::Write for IndentedWrite<'a, 'f> {
-    fn write_str(&mut self, s: &str) -> Result<(), fmt::Error> {
-        let mut lines = s.split('\n');
-        if let Some(line) = lines.next() {
-            self.0.write_str(line)?;
-            for line in lines {
-                self.0.write_str("\n  ")?;
-                self.0.write_str(line)?;
+        // The entries that failed to be pushed or were deferred
+        // are sent by the next sync even if they're no longer in the feed.
+        for entry_url in feed.pending_entries.iter().chain(&feed.deferred_entries) {
+            if !entries.iter().any(|entry| entry.url == entry_url) {
+                entries.push(EntryRef {
+                    url: entry_url,
+                    title: None,
+                });
             }
         }
 
-        Ok(())
+        println!("{}", feed.url);
+        for entry in &entries {
+            match entry.title {
+                Some(title) => println!("  {} ({})", entry.url, title),
+                None => println!("  {}", entry.url),
+            }
+        }
+        if entries.is_empty() {
+            println!("  (none)");
+        }
+    }
+
+    if !failures.is_empty() {
+        try_with_context!(Err(failures), "some feeds could not be downloaded");
+    }
+
+    Ok(())
+}
+
+/// Disables or enables feeds.
+fn set_disabled(
+    config: &mut Configuration,
+    args: &ToggleCommand,
+    disabled: bool,
+) -> Result<(), ErrorWithContext> {
+    let context = if disabled {
+        "failed to disable feeds"
+    } else {
+        "failed to enable feeds"
+    };
+
+    let mut feeds: Vec<&mut FeedConfiguration> = match &args.group {
+        Some(group) => {
+            let feeds: Vec<_> = config
+                .feeds
+                .iter_mut()
+                .filter(|feed| feed.group_name() == group)
+                .collect();
+            if feeds.is_empty() {
+                try_with_context!(Err(FeedNotFound::EmptyGroup(group.clone())), context);
+            }
+            feeds
+        }
+        None => {
+            // Check all the feeds before changing any of them.
+            for feed_url in &args.feed_urls {
+                try_with_context!(find_feed(config, feed_url), context);
+            }
+            config
+                .feeds
+                .iter_mut()
+                .filter(|feed| args.feed_urls.iter().any(|id| feed.is_identified_by(id)))
+                .collect()
+        }
+    };
+
+    for feed in &mut feeds {
+        feed.disabled = disabled;
+        println!(
+            "{} {}",
+            if disabled { "disabled" } else { "enabled" },
+            feed.url
+        );
+    }
+
+    Ok(())
+}
+
+fn stats(config: &Configuration, args: &StatsCommand) -> Result<(), ErrorWithContext> {
+    // Most pushes first; feeds with as many pushes stay in configuration order.
+    let mut feeds: Vec<&FeedConfiguration> = config.feeds.iter().collect();
+    feeds.sort_by_key(|feed| std::cmp::Reverse(feed.entries_pushed_total));
+
+    let idle_since = Utc::now() - chrono::Duration::days(args.days);
+    println!(
+        "{:>7}  {:<10}  {:>7}  FEED",
+        "PUSHES", "LAST PUSH", "ERRORS"
+    );
+    for feed in feeds {
+        let last_push = feed.last_push_date.map_or_else(
+            || "never".into(),
+            |date| date.format("%Y-%m-%d").to_string(),
+        );
+        let idle = if feed.last_push_date.is_none_or(|date| date < idle_since) {
+            format!(" (no pushes in the last {} days)", args.days)
+        } else {
+            String::new()
+        };
+        println!(
+            "{:>7}  {:<10}  {:>7}  {}{}",
+            feed.entries_pushed_total, last_push, feed.fetch_error_count, feed.url, idle
+        );
+    }
+
+    Ok(())
+}
+
+fn reset_stats(config: &mut Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
+    let feed = try_with_context!(
+        config
+            .feeds
+            .iter_mut()
+            .find(|feed| feed.is_identified_by(feed_url))
+            .ok_or_else(|| FeedNotFound::FeedNotFound(feed_url.into())),
+        "failed to reset statistics"
+    );
+    feed.entries_pushed_total = 0;
+    feed.last_push_date = None;
+    feed.fetch_error_count = 0;
+    println!("reset the statistics of {}", feed.url);
+    Ok(())
+}
+
+/// Opens the history file, if one is configured.
+fn open_history(config: &Configuration) -> Result<Option<Arc<History>>, ErrorWithContext> {
+    let Some(path) = &config.history_file else {
+        return Ok(None);
+    };
+    if read_only() {
+        debug!(
+            "not recording the history in {} (--read-only)",
+            path.display()
+        );
+        return Ok(None);
+    }
+
+    let history = try_with_context!(
+        History::open(path),
+        format!("failed to open the history file {}", path.display())
+    );
+    Ok(Some(Arc::new(history)))
+}
+
+fn history(config: &Configuration, args: &HistoryCommand) -> Result<(), ErrorWithContext> {
+    let path = match &config.history_file {
+        Some(path) => path,
+        None => try_with_context!(
+            Err("history_file is not set in the configuration file"),
+            "failed to show the history"
+        ),
+    };
+
+    let records = try_with_context!(
+        history::tail(path, args.tail),
+        format!("failed to read the history file {}", path.display())
+    );
+    for record in records {
+        println!(
+            "{} {}",
+            record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            record.entry_url
+        );
+        if let Some(title) = &record.title {
+            println!("  title: {}", title);
+        }
+        println!("  feed: {}", record.feed_url);
+        if let Some(tags) = &record.tags {
+            println!("  tags: {}", tags);
+        }
+    }
+
+    Ok(())
+}
+
+fn find_feed<'a>(
+    config: &'a Configuration,
+    feed_url: &str,
+) -> Result<&'a FeedConfiguration, FeedNotFound> {
+    config
+        .feeds
+        .iter()
+        .find(|feed| feed.is_identified_by(feed_url))
+        .ok_or_else(|| FeedNotFound::FeedNotFound(feed_url.into()))
+}
+
+fn remove(config: &mut Configuration, feed_url: &str) -> Result<(), ErrorWithContext> {
+    let len_before = config.feeds.len();
+    config.feeds.retain(|feed| !feed.is_identified_by(feed_url));
+    let len_after = config.feeds.len();
+    if len_before == len_after {
+        try_with_context!(
+            Err(FeedNotFound::FeedNotFound(feed_url.into())),
+            "failed to remove feed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that feeds can be downloaded and parsed,
+/// without pushing anything to Pocket or updating the configuration.
+fn validate(config: &Configuration, args: &ValidateCommand) -> Result<(), ErrorWithContext> {
+    let mut fetcher = Fetcher::new(config)?;
+    let mut failures = FeedFailures::new();
+    let feeds = config
+        .feeds
+        .iter()
+        .filter(|feed| args.only.is_empty() || args.only.iter().any(|x| feed.url.contains(x)));
+    for feed in feeds {
+        let start = Instant::now();
+        let result = fetch_current_feed(&mut fetcher, feed);
+        let elapsed = start.elapsed().as_secs_f64();
+
+        match result {
+            Ok(parsed_feed) => println!(
+                "OK {} ({:.2}s, {} entries)",
+                feed.url,
+                elapsed,
+                parsed_feed.entries.len()
+            ),
+            Err((kind, e)) => {
+                println!("{} {} ({:.2}s)", kind, feed.url, elapsed);
+                failures.push(&feed.url, e);
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        try_with_context!(Err(failures), "some feeds failed validation");
+    }
+
+    Ok(())
+}
+
+/// Searches the entries of all feeds for URLs matching a query.
+fn search(config: &Configuration, args: &SearchCommand) -> Result<(), ErrorWithContext> {
+    let regex = if args.regex {
+        Some(try_with_context!(
+            Regex::new(&args.query),
+            "invalid regular expression"
+        ))
+    } else {
+        None
+    };
+    let matches = |entry_url: &str| match &regex {
+        Some(regex) => regex.is_match(entry_url),
+        None => entry_url.contains(&args.query),
+    };
+
+    let mut fetcher = Fetcher::new(config)?;
+    let mut failures = FeedFailures::new();
+    for feed in &config.feeds {
+        let entries: Vec<String> = if args.pending {
+            // Look for entries currently in the feed
+            // that haven't been processed yet.
+            match fetch_current_feed(&mut fetcher, feed) {
+                Ok(parsed_feed) => new_entries(feed, &parsed_feed)
+                    .into_iter()
+                    .map(|entry| entry.url)
+                    .filter(|entry_url| matches(entry_url))
+                    .map(String::from)
+                    .collect(),
+                Err((_, e)) => {
+                    failures.push(&feed.url, e);
+                    continue;
+                }
+            }
+        } else {
+            feed.processed_entries
+                .iter()
+                .filter(|entry| matches(&entry.url))
+                .map(|entry| match (entry.status, entry.timestamp) {
+                    (Some(status), Some(timestamp)) => format!(
+                        "{} ({} on {})",
+                        entry.url,
+                        status.description(),
+                        timestamp.to_rfc3339()
+                    ),
+                    (Some(status), None) => format!("{} ({})", entry.url, status.description()),
+                    (None, _) => entry.url.clone(),
+                })
+                .collect()
+        };
+
+        if !entries.is_empty() {
+            println!("{}", feed.url);
+            for entry_url in entries {
+                println!("  {}", entry_url);
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        try_with_context!(Err(failures), "some feeds could not be searched");
+    }
+
+    Ok(())
+}
+
+/// The summary shown by `status`.
+#[derive(Serialize)]
+struct Status<'a> {
+    config: &'a Path,
+    accounts: Vec<AccountStatus<'a>>,
+    feeds: usize,
+    enabled_feeds: usize,
+    disabled_feeds: usize,
+    processed_entries: usize,
+    last_sync: Option<DateTime<Utc>>,
+    last_successful_sync: Option<DateTime<Utc>>,
+    failing_feeds: Vec<FailingFeed<'a>>,
+}
+
+#[derive(Serialize)]
+struct AccountStatus<'a> {
+    name: &'a str,
+    consumer_key: bool,
+    access_token: bool,
+    username: Option<&'a str>,
+}
+
+/// A feed that failed to sync last time
+/// or whose entries failed to be sent to Pocket.
+#[derive(Serialize)]
+struct FailingFeed<'a> {
+    url: &'a str,
+    title: Option<String>,
+    error: Option<&'a str>,
+    pending_entries: usize,
+    queued_entries: usize,
+}
+
+impl<'a> Status<'a> {
+    fn of(config_path: &'a Path, config: &'a Configuration) -> Status<'a> {
+        let accounts = std::iter::once((DEFAULT_ACCOUNT, &config.default_account))
+            .chain(
+                config
+                    .accounts
+                    .iter()
+                    .map(|(name, account)| (&**name, account)),
+            )
+            .map(|(name, account)| AccountStatus {
+                name,
+                consumer_key: account.consumer_key.is_some(),
+                access_token: account.access_token.is_some(),
+                username: account.username.as_deref(),
+            })
+            .collect();
+        let disabled_feeds = config.feeds.iter().filter(|feed| feed.disabled).count();
+        let failing_feeds = config
+            .feeds
+            .iter()
+            .filter(|feed| {
+                feed.last_error.is_some()
+                    || !feed.pending_entries.is_empty()
+                    || !feed.push_queue.is_empty()
+            })
+            .map(|feed| FailingFeed {
+                url: &feed.url,
+                title: feed.display_title(),
+                error: feed.last_error.as_deref(),
+                pending_entries: feed.pending_entries.len(),
+                queued_entries: feed.push_queue.len(),
+            })
+            .collect();
+
+        Status {
+            config: config_path,
+            accounts,
+            feeds: config.feeds.len(),
+            enabled_feeds: config.feeds.len() - disabled_feeds,
+            disabled_feeds,
+            processed_entries: config
+                .feeds
+                .iter()
+                .map(|feed| feed.processed_entries.len())
+                .sum(),
+            last_sync: config.last_sync,
+            last_successful_sync: config.last_successful_sync,
+            failing_feeds,
+        }
+    }
+}
+
+/// Summarizes the configuration without contacting any server.
+fn status(
+    config_path: &Path,
+    config: &Configuration,
+    args: &StatusCommand,
+) -> Result<(), ErrorWithContext> {
+    let status = Status::of(config_path, config);
+    if args.json {
+        let json = try_with_context!(
+            serde_json::to_string_pretty(&status),
+            "failed to format the status"
+        );
+        println!("{}", json);
+    } else {
+        print_status(&status);
+    }
+
+    if let Some(max_staleness) = args.max_staleness {
+        try_with_context!(
+            check_staleness(config.last_successful_sync, max_staleness, Utc::now()),
+            "the feeds are not synced"
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that the last successful sync finished at most `max_staleness` before `now`.
+fn check_staleness(
+    last_successful_sync: Option<DateTime<Utc>>,
+    max_staleness: Duration,
+    now: DateTime<Utc>,
+) -> Result<(), StaleSync> {
+    let last_successful_sync = last_successful_sync.ok_or(StaleSync::Never)?;
+    let max_staleness_delta =
+        chrono::TimeDelta::from_std(max_staleness).unwrap_or(chrono::TimeDelta::MAX);
+    if now - last_successful_sync > max_staleness_delta {
+        return Err(StaleSync::TooOld(
+            last_successful_sync.to_rfc3339(),
+            format_duration(max_staleness),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Formats a duration with the largest unit `parse_duration` accepts
+/// that represents it exactly.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds > 0 && seconds.is_multiple_of(60 * 60) {
+        format!("{}h", seconds / (60 * 60))
+    } else if seconds > 0 && seconds.is_multiple_of(60) {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Prints the output of `status` for humans.
+fn print_status(status: &Status) {
+    fn is_set(value: bool) -> &'static str {
+        if value {
+            "set"
+        } else {
+            "not set"
+        }
+    }
+
+    println!("configuration: {}", status.config.display());
+    for account in &status.accounts {
+        let username = account.username.map_or_else(String::new, |username| {
+            format!(", logged in as {}", username)
+        });
+        println!(
+            "account {}: consumer key {}, access token {}{}",
+            account.name,
+            is_set(account.consumer_key),
+            is_set(account.access_token),
+            username
+        );
+    }
+    println!(
+        "feeds: {} ({} enabled, {} disabled)",
+        status.feeds, status.enabled_feeds, status.disabled_feeds
+    );
+    println!("processed entries: {}", status.processed_entries);
+    let or_never = |date: Option<DateTime<Utc>>| {
+        date.map_or_else(|| "never".to_owned(), |date| date.to_rfc3339())
+    };
+    println!("last sync: {}", or_never(status.last_sync));
+    println!(
+        "last successful sync: {}",
+        or_never(status.last_successful_sync)
+    );
+    if status.failing_feeds.is_empty() {
+        println!("failing feeds: none");
+    } else {
+        println!("failing feeds:");
+        for feed in &status.failing_feeds {
+            match &feed.title {
+                Some(title) => println!("  {} ({})", feed.url, title),
+                None => println!("  {}", feed.url),
+            }
+            if let Some(error) = feed.error {
+                println!("    {}", Indented(Indented(error)));
+            }
+            if feed.pending_entries > 0 {
+                println!(
+                    "    {} entries failed to be sent to Pocket",
+                    feed.pending_entries
+                );
+            }
+            if feed.queued_entries > 0 {
+                println!(
+                    "    {} entries are queued until Pocket is reachable",
+                    feed.queued_entries
+                );
+            }
+        }
+    }
+}
+
+fn list(config: &Configuration, long: bool, by_last_synced: bool) -> Result<(), ErrorWithContext> {
+    fn or_never(date: Option<DateTime<Utc>>) -> String {
+        date.map_or_else(|| "never".into(), |date| date.to_rfc3339())
+    }
+
+    let accounts = std::iter::once((DEFAULT_ACCOUNT, &config.default_account)).chain(
+        config
+            .accounts
+            .iter()
+            .map(|(name, account)| (&**name, account)),
+    );
+    for (name, account) in accounts {
+        if let Some(username) = &account.username {
+            eprintln!("Account {}: logged in as {}", name, username);
+        }
+    }
+
+    let mut feeds: Vec<&FeedConfiguration> = config.feeds.iter().collect();
+    if by_last_synced {
+        feeds.sort_by_key(|feed| feed.last_synced);
+    }
+
+    // Only show the groups if some feeds are in a group.
+    let mut groups: Vec<(&str, Vec<&FeedConfiguration>)> = vec![];
+    for feed in feeds {
+        match groups
+            .iter_mut()
+            .find(|(group, _)| *group == feed.group_name())
+        {
+            Some((_, feeds)) => feeds.push(feed),
+            None => groups.push((feed.group_name(), vec![feed])),
+        }
+    }
+    let show_groups = config.feeds.iter().any(|feed| feed.group.is_some());
+    let indent = if show_groups { "  " } else { "" };
+
+    for (group, feeds) in groups {
+        if show_groups {
+            println!("{}:", group);
+        }
+
+        for feed in feeds {
+            let name = feed
+                .name
+                .as_ref()
+                .map_or_else(String::new, |name| format!(" ({})", name));
+            let disabled = if feed.disabled { " [disabled]" } else { "" };
+            println!("{}{}{}{}", indent, feed.url, name, disabled);
+            if long {
+                if let Some(title) = &feed.title {
+                    println!("{}  title: {}", indent, title);
+                }
+                if !feed.tags.is_empty() {
+                    println!("{}  tags: {}", indent, feed.tags);
+                }
+                println!("{}  last synced: {}", indent, or_never(feed.last_synced));
+                println!("{}  last fetched: {}", indent, or_never(feed.last_fetched));
+                println!(
+                    "{}  last new entry: {}",
+                    indent,
+                    or_never(feed.last_new_entry)
+                );
+                println!(
+                    "{}  processed entries: {}",
+                    indent,
+                    feed.processed_entries.len()
+                );
+                if !feed.pending_entries.is_empty() {
+                    println!(
+                        "{}  pending entries: {}",
+                        indent,
+                        feed.pending_entries.len()
+                    );
+                }
+                if !feed.deferred_entries.is_empty() {
+                    println!(
+                        "{}  deferred entries: {}",
+                        indent,
+                        feed.deferred_entries.len()
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the HTTP client used to talk to Pocket.
+fn pocket_client(config: &Configuration) -> Result<Client, ErrorWithContext> {
+    let timeout = config
+        .pocket_timeout_secs
+        .map_or(DEFAULT_POCKET_TIMEOUT, Duration::from_secs);
+    Ok(try_with_context!(
+        proxy::configure(Client::builder().timeout(timeout)).build(),
+        "unable to set up the HTTP client for Pocket"
+    ))
+}
+
+fn get_pocket(
+    account: &Account,
+    client: Client,
+    api_url: Option<&str>,
+) -> Result<Pocket, PocketSetupError> {
+    match account.consumer_key {
+        Some(ref consumer_key) => {
+            let pocket = Pocket::new(
+                consumer_key,
+                account.access_token.as_ref().map(|x| x.as_ref()),
+                client,
+            );
+            Ok(match api_url {
+                Some(api_url) => pocket.with_api_url(api_url),
+                None => pocket,
+            })
+        }
+        None => Err(PocketSetupError::MissingConsumerKey),
+    }
+}
+
+fn get_authenticated_pocket(
+    account: &Account,
+    client: Client,
+    api_url: Option<&str>,
+) -> Result<Pocket, PocketSetupError> {
+    get_pocket(account, client, api_url).and_then(|pocket| match account.access_token {
+        Some(_) => Ok(pocket),
+        None => Err(PocketSetupError::MissingAccessToken),
+    })
+}
+
+/// Downloads a feed and pushes its new entries to Pocket.
+///
+/// Returns the errors that occurred while pushing individual entries.
+/// The entries that failed will be retried on the next sync.
+///
+/// If `feed_urls` (the URLs of all the configured feeds) is given,
+/// a feed whose self link points to another URL is moved to that URL,
+/// unless another feed already uses it.
+fn process_feed(
+    feed: &mut FeedConfiguration,
+    targets: &[(&str, &PushPool)],
+    fetcher: &mut Fetcher,
+    push_delay: Duration,
+    limits: PushLimits,
+    feed_urls: Option<&HashSet<String>>,
+) -> Result<Vec<ErrorWithContext>, ErrorWithContext> {
+    // Set if the feed was downloaded again without validators
+    // because the server kept answering 304 Not Modified.
+    let mut checked_validators = false;
+
+    // Don't send a request at all if the last response is still fresh.
+    let feed_response = if fetcher.is_fresh(feed) {
+        eprintln!(
+            "skipping {} (the last response is still fresh)",
+            feed.label()
+        );
+        None
+    } else if !fetcher.is_allowed_by_robots_txt(feed) {
+        eprintln!(
+            "WARNING: skipping {} (the host's robots.txt disallows it)",
+            feed.label()
+        );
+        None
+    } else {
+        eprintln!("downloading {}", feed.label());
+        let mut feed_response = fetcher.fetch(feed);
+        if let Ok(FeedResponse::NotModified { .. }) = feed_response {
+            if feed.not_modified_for_too_long(Utc::now()) {
+                debug!(
+                    "{} hasn't changed since {}; downloading it again without validators",
+                    feed.url,
+                    feed.not_modified_since.unwrap()
+                );
+                checked_validators = true;
+                feed_response = fetcher.fetch_unconditionally(feed);
+            }
+        }
+        let feed_response = match feed_response {
+            Ok(feed_response) => feed_response,
+            Err(e) => {
+                feed.fetch_error_count += 1;
+                try_with_context!(
+                    Err(e),
+                    format!("failed to download feed at {}", feed.label())
+                )
+            }
+        };
+        trace!(
+            "response for {}:\n{}",
+            feed.url,
+            fetch_summary(&feed_response)
+        );
+        Some(feed_response)
+    };
+    let fetched_at = Utc::now();
+
+    // Outside the feed's push window
+    // or until the feed's weekly digest is due,
+    // the new entries are only recorded as pending.
+    let outside_window = feed
+        .push_window
+        .as_ref()
+        .is_some_and(|window| !window.contains(fetched_at));
+    let digest_due = feed
+        .digest_day
+        .map(|day| limits.flush_digests || day.is_due(feed.last_digest, fetched_at));
+    let deferred = !targets.is_empty() && (outside_window || digest_due == Some(false));
+
+    // Relative entry URLs are resolved against the URL the feed was served from.
+    let base_url = feed_response
+        .as_ref()
+        .and_then(|feed_response| feed_response.details().final_url.as_deref())
+        .unwrap_or(&feed.url);
+    let base_url = Url::parse(base_url).ok();
+
+    // Take the entries that failed to be pushed on previous runs;
+    // entries that fail again will be added back by `push_entries`.
+    let mut pending_entries = mem::take(&mut feed.pending_entries);
+    let mut deferred_entries = mem::take(&mut feed.deferred_entries);
+    let mut entries_to_push = vec![];
+    let mut entry_dates = vec![];
+
+    // Only update the freshness once the response has been processed,
+    // so that a feed that fails to parse is retried on the next sync.
+    let fresh_until = match &feed_response {
+        Some(FeedResponse::Success { fresh_until, .. })
+        | Some(FeedResponse::NotModified { fresh_until, .. }) => Some(*fresh_until),
+        None => None,
+    };
+
+    match &feed_response {
+        Some(FeedResponse::NotModified { e_tag, .. }) => {
+            if let Some(e_tag) = e_tag {
+                feed.last_e_tag = Some(e_tag.clone());
+            }
+            if feed.not_modified_since.is_none() {
+                feed.not_modified_since = Some(fetched_at);
+            }
+        }
+        Some(FeedResponse::Success { .. }) => feed.not_modified_since = None,
+        None => {}
+    }
+
+    // The validators to save once the entries are sent,
+    // for feeds with the `strict` validator update.
+    let mut new_validators = None;
+
+    // Don't parse anything if we received a 304 Not Modified response.
+    if let Some(FeedResponse::Success {
+        body,
+        content_type,
+        last_modified,
+        e_tag,
+        ..
+    }) = feed_response
+    {
+        if feed.validator_update == ValidatorUpdate::Always {
+            feed.last_modified = last_modified.clone();
+            feed.last_e_tag = e_tag.clone();
+        }
+
+        let parsed_feed = match parse_feed(feed, &body, content_type) {
+            Ok(parsed_feed) => parsed_feed,
+            Err(e) => {
+                // Keep the entries that failed previously for the next sync.
+                feed.pending_entries = pending_entries;
+                feed.deferred_entries = deferred_entries;
+                return Err(e);
+            }
+        };
+        if parsed_feed.title.is_some() {
+            feed.title = parsed_feed.title.clone();
+        }
+
+        if let Some(self_url) = moved_url(feed, &parsed_feed) {
+            match feed_urls {
+                Some(feed_urls) if !feed_urls.contains(&self_url) => {
+                    eprintln!("updating the URL of {} to {}", feed.url, self_url);
+                    feed.url = self_url;
+                }
+                Some(_) => eprintln!(
+                    "WARNING: {} says its URL is {}, which is already configured as another feed",
+                    feed.label(),
+                    self_url
+                ),
+                None => eprintln!(
+                    "WARNING: {} says its URL is {} (use `sync --update-feed-urls` to update it)",
+                    feed.label(),
+                    self_url
+                ),
+            }
+        }
+
+        let new_entries = new_entries(feed, &parsed_feed);
+
+        if checked_validators && !new_entries.is_empty() {
+            eprintln!(
+                "WARNING: the server of {} answered 304 Not Modified, \
+                 but the feed has {} new entries; \
+                 the server doesn't update its ETag or Last-Modified header",
+                feed.label(),
+                new_entries.len()
+            );
+        }
+
+        if !limits.accept_republished {
+            if let Some(error) = republished(feed, &parsed_feed, &new_entries) {
+                // Leave the feed's state as it was, including the validators,
+                // so that the entries are checked again on the next sync.
+                feed.pending_entries = pending_entries;
+                feed.deferred_entries = deferred_entries;
+                let context = format!("skipped the new entries of {}", feed.label());
+                return Err(ErrorWithContext::new(Box::new(error), context));
+            }
+        }
+
+        // Ignore dates in the future,
+        // which would make every new entry look republished.
+        for entry in &parsed_feed.entries {
+            if let Some(published) = entry.published.filter(|&date| date <= fetched_at) {
+                for entry_url in entry.alternate_links() {
+                    entry_dates.push((entry_url.to_owned(), published));
+                }
+            }
+        }
+
+        if new_entries.is_empty() {
+            debug!("no new entries in {}", feed.url);
+        } else {
+            feed.last_new_entry = Some(fetched_at);
+        }
+
+        // A feed that suddenly has a lot of new entries
+        // probably republished its archive (e.g. after moving to another CMS),
+        // so only push the newest entries and leave the others for later.
+        // Deferred entries are all kept, since they aren't sent now.
+        let skipped = limits
+            .max_pushes
+            .filter(|_| !deferred)
+            .map_or(0, |max_pushes| new_entries.len().saturating_sub(max_pushes));
+        if skipped > 0 {
+            eprintln!(
+                "WARNING: {label} has {count} new entries; only the newest {pushed} are sent to Pocket\n  \
+                 The other entries will be sent by the next syncs.\n  \
+                 If the feed republished old entries by mistake, \
+                 run `mark-read {url}` to mark them as processed;\n  \
+                 to send them all now, run `sync --no-limit`.",
+                label = feed.label(),
+                url = feed.url,
+                count = new_entries.len(),
+                pushed = new_entries.len() - skipped,
+            );
+        }
+
+        if let Some(confirmation) = limits.confirmation.filter(|_| !deferred) {
+            if !confirmation.confirm(feed, &new_entries[skipped..]) {
+                // Leave the feed's state as it was, like for republished entries,
+                // so that the entries are found again on the next sync.
+                eprintln!(
+                    "not sending the new entries of {} to Pocket; \
+                     they will be sent by the next sync",
+                    feed.label()
+                );
+                feed.pending_entries = pending_entries;
+                feed.deferred_entries = deferred_entries;
+                return Ok(vec![]);
+            }
+        }
+
+        for entry in new_entries.into_iter().skip(skipped) {
+            // This entry will be pushed with the new entries.
+            let key = feed.entry_key(entry.url);
+            pending_entries.retain(|x| feed.entry_key(x) != key);
+            deferred_entries.retain(|x| feed.entry_key(x) != key);
+            entries_to_push.push((entry.url.to_owned(), entry.title.map(String::from)));
+        }
+
+        // The feed was downloaded and parsed successfully.
+        // By default, save the new validators now,
+        // even if some pushes to Pocket fail;
+        // the failed entries are remembered in `pending_entries`.
+        // `strict` only saves them once every entry was sent,
+        // and `always` already saved them above.
+        // If the server no longer sends a validator, forget it.
+        match feed.validator_update {
+            ValidatorUpdate::OnFetchSuccess => {
+                feed.last_modified = last_modified;
+                feed.last_e_tag = e_tag;
+            }
+            ValidatorUpdate::Strict => new_validators = Some((last_modified, e_tag)),
+            ValidatorUpdate::Always => {}
+        }
+    }
+
+    if let Some(fresh_until) = fresh_until {
+        feed.fresh_until = fresh_until;
+        feed.last_fetched = Some(fetched_at);
+    }
+
+    // Retry the entries that failed previously or that were deferred
+    // and that weren't seen in the feed this time
+    // (either because we received a 304 Not Modified response
+    // or because the entry is no longer in the feed).
+    let processed = feed.processed_keys();
+    let mut failed_before = HashSet::new();
+    for entry_url in pending_entries {
+        if !processed.contains(&entry_url) {
+            failed_before.insert(entry_url.clone());
+            entries_to_push.push((entry_url, None));
+        }
+    }
+    for entry_url in deferred_entries {
+        if !processed.contains(&entry_url) {
+            entries_to_push.push((entry_url, None));
+        }
+    }
+
+    let push_errors = if deferred {
+        if !entries_to_push.is_empty() {
+            let reason = match &feed.push_window {
+                Some(window) if outside_window => format!("outside its push window ({})", window),
+                _ => format!("until its weekly digest ({})", feed.digest_day.unwrap()),
+            };
+            eprintln!(
+                "not sending {} entries of {} to Pocket {}",
+                entries_to_push.len(),
+                feed.label(),
+                reason
+            );
+        }
+        for (entry_url, _) in entries_to_push {
+            let failed = failed_before.contains(&entry_url);
+            feed.defer_entry(entry_url, failed);
+        }
+        vec![]
+    } else {
+        if digest_due == Some(true) && !targets.is_empty() {
+            // A digest that's larger than the push limit
+            // is sent over several syncs.
+            let held_back = match limits.max_pushes {
+                Some(max_pushes) if entries_to_push.len() > max_pushes => {
+                    entries_to_push.split_off(max_pushes)
+                }
+                _ => vec![],
+            };
+            if held_back.is_empty() {
+                feed.last_digest = Some(fetched_at);
+            } else {
+                eprintln!(
+                    "sending {} of the {} entries of the digest of {}; \
+                     the others will be sent by the next syncs",
+                    entries_to_push.len(),
+                    entries_to_push.len() + held_back.len(),
+                    feed.label()
+                );
+                for (entry_url, _) in held_back {
+                    let failed = failed_before.contains(&entry_url);
+                    feed.defer_entry(entry_url, failed);
+                }
+            }
+        }
+
+        push_entries(
+            feed,
+            targets,
+            fetcher,
+            entries_to_push,
+            base_url.as_ref(),
+            push_delay,
+            limits.max_url_length.unwrap_or(DEFAULT_MAX_URL_LENGTH),
+        )
+    };
+
+    if let Some((last_modified, e_tag)) = new_validators.filter(|_| push_errors.is_empty()) {
+        feed.last_modified = last_modified;
+        feed.last_e_tag = e_tag;
+    }
+
+    let processed = feed.processed_keys();
+    let newest_processed = entry_dates
+        .into_iter()
+        .filter(|(entry_url, _)| processed.contains(entry_url))
+        .map(|(_, published)| published)
+        .max();
+    if newest_processed > feed.newest_entry_published {
+        feed.newest_entry_published = newest_processed;
+    }
+
+    Ok(push_errors)
+}
+
+/// Limits on the entries `process_feed` sends to Pocket.
+#[derive(Default)]
+struct PushLimits<'a> {
+    /// The maximum number of new entries to send (see `max_pushes_per_run`).
+    max_pushes: Option<usize>,
+    /// Send the new entries even if they look like republished entries.
+    accept_republished: bool,
+    /// Ask before sending many entries, if someone can answer.
+    confirmation: Option<&'a mut PushConfirmation>,
+    /// Send the entries kept for weekly digests even if they aren't due.
+    flush_digests: bool,
+    /// The length above which entry URLs are too long for Pocket
+    /// (see `max_url_length`).
+    max_url_length: Option<usize>,
+}
+
+/// Asks the user to confirm a sync that sends many entries to Pocket,
+/// in case a feed republished its archive.
+///
+/// The question is asked at most once per sync;
+/// once the user has answered,
+/// the answer applies to the following feeds too.
+struct PushConfirmation {
+    /// The number of entries that can be sent without asking.
+    threshold: usize,
+    /// The number of entries sent in this sync so far.
+    count: usize,
+    answer: Option<bool>,
+    input: Box<dyn BufRead>,
+}
+
+impl PushConfirmation {
+    fn new(threshold: usize, input: Box<dyn BufRead>) -> PushConfirmation {
+        PushConfirmation {
+            threshold,
+            count: 0,
+            answer: None,
+            input,
+        }
+    }
+
+    /// Returns whether to send `entries` of `feed` to Pocket,
+    /// asking the user if they take the sync over the threshold.
+    fn confirm(&mut self, feed: &FeedConfiguration, entries: &[EntryRef]) -> bool {
+        let total = self.count + entries.len();
+        if entries.is_empty() || total <= self.threshold {
+            self.count = total;
+            return true;
+        }
+
+        let answer = match self.answer {
+            Some(answer) => answer,
+            None => {
+                let answer = self.ask(feed, entries, total);
+                self.answer = Some(answer);
+                answer
+            }
+        };
+        if answer {
+            self.count = total;
+        }
+        answer
+    }
+
+    fn ask(&mut self, feed: &FeedConfiguration, entries: &[EntryRef], total: usize) -> bool {
+        loop {
+            eprint!(
+                "About to push {} entries to Pocket ({} from {}) \u{2014} continue? [y/N/show] ",
+                total,
+                entries.len(),
+                feed.label()
+            );
+            let mut input = String::new();
+            match self.input.read_line(&mut input) {
+                // Nobody is there to answer.
+                Ok(0) | Err(_) => {
+                    eprintln!();
+                    return false;
+                }
+                Ok(_) => {}
+            }
+            match input.trim().to_lowercase().as_str() {
+                "y" | "yes" => return true,
+                "show" | "s" => {
+                    for entry in entries {
+                        match entry.title {
+                            Some(title) => eprintln!("  {} ({})", entry.url, title),
+                            None => eprintln!("  {}", entry.url),
+                        }
+                    }
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Checks whether a feed's new entries look like old entries
+/// that were republished with different URLs
+/// (e.g. because the site changed its URL scheme):
+/// most of the feed's entries are new,
+/// but most of them were published before the newest processed entry.
+fn republished(
+    feed: &FeedConfiguration,
+    parsed_feed: &Feed,
+    new_entries: &[EntryRef],
+) -> Option<Republished> {
+    let newest_processed = feed.newest_entry_published?;
+    let total = parsed_feed.entries.len();
+    // A single entry can't be a mass republication.
+    if new_entries.len() < 2 || (new_entries.len() as f64) <= total as f64 * REPUBLISHED_FRACTION {
+        return None;
+    }
+
+    let new_urls: HashSet<&str> = new_entries.iter().map(|entry| entry.url).collect();
+    let older = parsed_feed
+        .entries
+        .iter()
+        .filter(|entry| entry.alternate_links().any(|x| new_urls.contains(x)))
+        .filter(|entry| entry.published.is_some_and(|date| date < newest_processed))
+        .count();
+    if older * 2 > new_entries.len() {
+        Some(Republished::Republished(
+            feed.url.clone(),
+            new_entries.len(),
+            total,
+            older,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Returns the URL in a feed's self link
+/// if it's not the URL the feed was downloaded from.
+///
+/// Differences in the case of the scheme and host,
+/// default ports and the order of the query parameters are ignored.
+fn moved_url(feed: &FeedConfiguration, parsed_feed: &Feed) -> Option<String> {
+    // Local and `exec:` feeds aren't published at their self link.
+    if !matches!(FeedSource::of(&feed.url), FeedSource::Http) {
+        return None;
+    }
+
+    let feed_url = Url::parse(&feed.url).ok()?;
+    let self_url = feed_url.join(parsed_feed.self_link()?).ok()?;
+    if normalized_url(&self_url) == normalized_url(&feed_url) {
+        None
+    } else {
+        Some(self_url.into())
+    }
+}
+
+/// Sorts the query parameters of a URL.
+/// Parsing the URL already normalized the case of its scheme and host.
+fn normalized_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    if !pairs.is_empty() {
+        pairs.sort();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url
+}
+
+/// Describes how a feed was downloaded:
+/// the final URL, the status, the validators, the size and the elapsed time.
+fn fetch_summary(feed_response: &FeedResponse) -> String {
+    let details = feed_response.details();
+    let mut summary = match details.status {
+        Some(status) => format!("  status: {}\n", status),
+        None => String::new(),
+    };
+    if let Some(final_url) = &details.final_url {
+        summary += &format!("  final URL: {}\n", final_url);
+    }
+
+    let (last_modified, e_tag) = match feed_response {
+        FeedResponse::Success {
+            last_modified,
+            e_tag,
+            ..
+        } => (last_modified.as_deref(), e_tag.as_deref()),
+        FeedResponse::NotModified { e_tag, .. } => (None, e_tag.as_deref()),
+    };
+    if let Some(last_modified) = last_modified {
+        summary += &format!("  Last-Modified: {}\n", last_modified);
+    }
+    if let Some(e_tag) = e_tag {
+        summary += &format!("  ETag: {}\n", e_tag);
+    }
+
+    summary += &format!(
+        "  {} bytes in {} ms",
+        details.size,
+        details.elapsed.as_millis()
+    );
+    summary
+}
+
+/// Returns the entries of a parsed feed that haven't been processed yet,
+/// in the order they are sent to Pocket (see `Feed::entry_refs`).
+fn new_entries<'a>(feed: &FeedConfiguration, parsed_feed: &'a Feed) -> Vec<EntryRef<'a>> {
+    // Adding the new entries' keys to the set
+    // also skips the entries that appear more than once in the feed.
+    let mut seen = feed.processed_keys();
+    parsed_feed
+        .entry_refs()
+        .into_iter()
+        .filter(|entry| seen.insert(entry.url))
+        .collect()
+}
+
+/// Downloads and parses a feed,
+/// ignoring the feed's validators so that the full feed is always received.
+///
+/// On failure, also returns a short description of the step that failed.
+fn fetch_current_feed(
+    fetcher: &mut Fetcher,
+    feed: &FeedConfiguration,
+) -> Result<Feed, (&'static str, ErrorWithContext)> {
+    let feed = FeedConfiguration {
+        last_modified: None,
+        last_e_tag: None,
+        fresh_until: None,
+        ..feed.clone()
+    };
+
+    let feed_response = fetcher.fetch(&feed).map_err(|e| {
+        let context = format!("failed to download feed at {}", feed.label());
+        (
+            "download error",
+            ErrorWithContext::new(Box::new(e), context),
+        )
+    })?;
+    match feed_response {
+        FeedResponse::Success {
+            body, content_type, ..
+        } => parse_feed(&feed, &body, content_type).map_err(|e| ("parse error", e)),
+        // Some servers answer 304 Not Modified to any request.
+        FeedResponse::NotModified { .. } => Err((
+            "download error",
+            ErrorWithContext::new(
+                "the server answered 304 Not Modified to an unconditional request".into(),
+                format!("failed to download feed at {}", feed.label()),
+            ),
+        )),
+    }
+}
+
+/// Parses a feed's body.
+fn parse_feed(
+    feed: &FeedConfiguration,
+    body: &str,
+    content_type: Option<String>,
+) -> Result<Feed, ErrorWithContext> {
+    let feed_url = &feed.url;
+    let feed_label = feed.label();
+    let large = feed.large_feed || body.len() > LARGE_FEED_SIZE;
+    if large {
+        debug!(
+            "parsing {} as a large feed ({} bytes)",
+            feed_url,
+            body.len()
+        );
+    }
+    match Feed::parse_at(body, feed_url, large) {
+        Ok(parsed_feed) => Ok(parsed_feed),
+        Err(feed_error) => {
+            // If the server sent us a web page (e.g. a login wall),
+            // say so instead of dumping the parser errors.
+            if looks_like_html(content_type.as_deref(), body) {
+                try_with_context!(
+                    Err(NotAFeed {
+                        content_type,
+                        snippet: snippet(body),
+                        feed_error: if verbosity() > 0 {
+                            Some(feed_error)
+                        } else {
+                            None
+                        },
+                    }),
+                    format!("failed to parse feed at {}", feed_label)
+                )
+            } else {
+                try_with_context!(
+                    Err(feed_error),
+                    format!("failed to parse feed at {}", feed_label)
+                )
+            }
+        }
+    }
+}
+
+/// Pushes entries to each of the Pocket accounts in `targets`
+/// (given by name, with their pool)
+/// and records them as processed or pending.
+/// An entry is only processed once every target has received it;
+/// if there are no targets, the entries are marked as read.
+///
+/// Returns the errors for the pushes that failed
+/// in a way that will be retried on the next sync.
+fn push_entries(
+    feed: &mut FeedConfiguration,
+    targets: &[(&str, &PushPool)],
+    fetcher: &mut Fetcher,
+    entries: Vec<(String, Option<String>)>,
+    base_url: Option<&Url>,
+    push_delay: Duration,
+    max_url_length: usize,
+) -> Vec<ErrorWithContext> {
+    if targets.is_empty() {
+        // If there are no targets,
+        // then we just want to mark the current feed entries as processed,
+        // on the assumption that the user has read them already.
+        for (entry_url, _) in entries {
+            feed.mark_processed(&entry_url, EntryStatus::MarkedRead);
+        }
+        return vec![];
+    }
+
+    let mut candidates = vec![];
+    // The canonical URLs of the entries whose page gave a different URL,
+    // which are also marked as processed.
+    let mut canonical_urls = HashMap::new();
+    let mut seen = feed.processed_keys();
+    for (entry_url, title) in entries {
+        match resolve_entry_url(&entry_url, base_url) {
+            Ok(mut parsed_entry_url) => {
+                if feed.verify_links {
+                    if let Some(status) = fetcher.dead_link_status(feed, &parsed_entry_url) {
+                        eprintln!("skipped dead link {} ({})", entry_url, status);
+                        feed.mark_processed(&entry_url, EntryStatus::DeadLink);
+                        continue;
+                    }
+                }
+
+                if feed.resolve_canonical {
+                    match fetcher.fetch_canonical_url(feed, &parsed_entry_url) {
+                        Ok(Some(canonical_url))
+                            if seen.key(canonical_url.as_str()) != seen.key(&entry_url) =>
+                        {
+                            if !seen.insert(canonical_url.as_str()) {
+                                eprintln!(
+                                    "skipped {} (its canonical URL {} was already processed)",
+                                    entry_url, canonical_url
+                                );
+                                feed.mark_processed(&entry_url, EntryStatus::Duplicate);
+                                continue;
+                            }
+                            parsed_entry_url = canonical_url.clone();
+                            canonical_urls.insert(entry_url.clone(), canonical_url);
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            // Send the URL from the feed instead.
+                            debug!("failed to fetch the canonical URL of {}: {}", entry_url, e);
+                        }
+                    }
+                }
+
+                // Pocket would reject the URL on every sync.
+                match fit_url_length(&parsed_entry_url, max_url_length, feed.shorten_long_urls) {
+                    Some(url) if url == parsed_entry_url => {}
+                    Some(url) => {
+                        eprintln!(
+                            "sending {} without its query string (URL too long for Pocket)",
+                            entry_url
+                        );
+                        parsed_entry_url = url;
+                    }
+                    None => {
+                        eprintln!(
+                            "skipped {} (URL too long for Pocket: {} characters, the limit is {})",
+                            entry_url,
+                            parsed_entry_url.as_str().len(),
+                            max_url_length
+                        );
+                        mark_resolved_processed(
+                            feed,
+                            &canonical_urls,
+                            &entry_url,
+                            EntryStatus::TooLong,
+                        );
+                        continue;
+                    }
+                }
+
+                candidates.push((entry_url, parsed_entry_url, title));
+            }
+            Err(e) => {
+                eprintln!(
+                    "'{}' is not a valid URL ({}). ignoring it; \
+                     it won't be retried unless you run `reprocess-invalid {}`.",
+                    entry_url, e, feed.url
+                );
+
+                // Mark the entry as processed,
+                // to avoid noise in subsequent runs.
+                feed.mark_processed(&entry_url, EntryStatus::SkippedInvalid);
+                if !feed.invalid_entries.contains(&entry_url) {
+                    feed.invalid_entries.push(entry_url);
+                }
+            }
+        }
+    }
+
+    let mut push_errors = vec![];
+    if let Some(command) = feed.filter_command.clone() {
+        let verdicts = run_filter(feed, fetcher, &command, &candidates, &mut push_errors);
+        let mut kept = vec![];
+        for (candidate, verdict) in candidates.into_iter().zip(verdicts) {
+            let entry_url = &candidate.0;
+            match verdict {
+                Some(Verdict::Push) => kept.push(candidate),
+                Some(Verdict::Skip) => {
+                    eprintln!("skipped {} (filter command)", entry_url);
+                    mark_resolved_processed(
+                        feed,
+                        &canonical_urls,
+                        entry_url,
+                        EntryStatus::Filtered,
+                    );
+                }
+                None => {
+                    // Leave the entry unprocessed
+                    // so that it's filtered again on the next sync.
+                    if !feed.pending_entries.contains(entry_url) {
+                        feed.pending_entries.push(entry_url.clone());
+                    }
+                }
+            }
+        }
+        candidates = kept;
+    }
+
+    let mut entry_urls = vec![];
+    let tags = join_tags([feed.tags.as_str()]);
+    for (entry_url, parsed_entry_url, title) in candidates {
+        let title = entry_title(feed, fetcher, &parsed_entry_url, title);
+        entry_urls.push((entry_url, parsed_entry_url, title));
+    }
+
+    // Send the entries to each target,
+    // except to the targets that already received them on a previous sync.
+    let mut results: Vec<Vec<(&str, PocketResult<()>)>> =
+        entry_urls.iter().map(|_| vec![]).collect();
+    for &(account, pool) in targets {
+        let mut indices = vec![];
+        let mut requests = vec![];
+        for (i, (entry_url, url, title)) in entry_urls.iter().enumerate() {
+            if !feed.was_partially_pushed_to(entry_url, account) {
+                indices.push(i);
+                requests.push(PushRequest {
+                    feed_url: feed.url.clone(),
+                    feed_label: feed.label(),
+                    url: url.clone(),
+                    title: title.clone(),
+                    tags: tags.clone(),
+                });
+            }
+        }
+        for (i, result) in indices.into_iter().zip(pool.push(requests, push_delay)) {
+            results[i].push((account, result));
+        }
+    }
+
+    // Only consider an entry processed if the pushes to all targets succeeded.
+    // That means that if one failed, we'll try again next time.
+    for ((entry_url, url, title), results) in entry_urls.into_iter().zip(results) {
+        let mut rejected = false;
+        let mut queued = false;
+        let mut failed = false;
+        for (account, result) in results {
+            match result {
+                Ok(()) => feed.record_partial_push(&entry_url, account),
+                Err(error) if error.is_permanent() => {
+                    // Pocket will reject this URL every time,
+                    // so mark the entry as processed
+                    // instead of retrying it on every sync.
+                    eprintln!(
+                        "URL {url} was rejected by Pocket and won't be retried:\n  {error}",
+                        error = Indented(&error)
+                    );
+                    rejected = true;
+                }
+                Err(error) if error.is_network_error() => {
+                    // Don't download the feed again just for this entry;
+                    // the queue is sent at the start of the next sync.
+                    eprintln!(
+                        "Pocket is unreachable, URL {url} will be sent on the next sync:\n  {error}",
+                        error = Indented(&error)
+                    );
+                    feed.record_partial_push(&entry_url, account);
+                    feed.push_queue.push(QueuedPush {
+                        url: url.to_string(),
+                        entry_url: Some(entry_url.clone()).filter(|x| x != url.as_str()),
+                        title: title.clone(),
+                        tags: tags.clone(),
+                        attempts: 0,
+                        account: Some(account.to_owned()).filter(|x| x != feed.account_name()),
+                    });
+                    queued = true;
+                }
+                Err(error) => {
+                    eprintln!(
+                        "error while adding URL {url} to Pocket:\n  {error}",
+                        error = Indented(&error)
+                    );
+                    push_errors.push(ErrorWithContext::new(
+                        Box::new(error),
+                        format!("failed to add URL {} to Pocket", url),
+                    ));
+                    failed = true;
+                }
+            }
+        }
+
+        if !rejected && failed {
+            // Remember that this entry failed
+            // so we can try again next time,
+            // even if the feed isn't modified by then.
+            if !feed.pending_entries.contains(&entry_url) {
+                feed.pending_entries.push(entry_url);
+            }
+            continue;
+        }
+
+        feed.partial_pushes.remove(&entry_url);
+        if rejected {
+            mark_resolved_processed(
+                feed,
+                &canonical_urls,
+                &entry_url,
+                EntryStatus::RejectedByPocket,
+            );
+        } else if queued {
+            mark_resolved_processed(feed, &canonical_urls, &entry_url, EntryStatus::Queued);
+            feed.pending_entries.retain(|x| *x != entry_url);
+        } else {
+            // Remember that we've processed this entry
+            // so we don't try to send it to Pocket next time.
+            mark_resolved_processed(feed, &canonical_urls, &entry_url, EntryStatus::Pushed);
+            feed.entries_pushed_total += 1;
+            feed.last_push_date = Some(Utc::now());
+        }
+    }
+
+    push_errors
+}
+
+/// Returns the URL to send to Pocket for an entry
+/// if it's at most `max_length` bytes long,
+/// possibly without its query string and fragment if `shorten` is true,
+/// or `None` if it's too long.
+fn fit_url_length(url: &Url, max_length: usize, shorten: bool) -> Option<Url> {
+    if url.as_str().len() <= max_length {
+        return Some(url.clone());
+    }
+    if !shorten {
+        return None;
+    }
+
+    let mut shortened = url.clone();
+    shortened.set_query(None);
+    shortened.set_fragment(None);
+    Some(shortened).filter(|url| url.as_str().len() <= max_length)
+}
+
+/// Marks an entry as processed,
+/// along with its canonical URL if it has a different one,
+/// so that neither form is sent to Pocket again.
+fn mark_resolved_processed(
+    feed: &mut FeedConfiguration,
+    canonical_urls: &HashMap<String, Url>,
+    entry_url: &str,
+    status: EntryStatus,
+) {
+    feed.mark_processed(entry_url, status);
+    if let Some(canonical_url) = canonical_urls.get(entry_url) {
+        feed.mark_processed(canonical_url.as_str(), status);
+    }
+}
+
+/// Runs the feed's filter command on the entries
+/// and returns the verdict for each entry,
+/// or `None` for the entries that couldn't be filtered
+/// (the errors are added to `errors`).
+fn run_filter(
+    feed: &FeedConfiguration,
+    fetcher: &Fetcher,
+    command: &str,
+    candidates: &[(String, Url, Option<String>)],
+    errors: &mut Vec<ErrorWithContext>,
+) -> Vec<Option<Verdict>> {
+    let entries: Vec<_> = candidates
+        .iter()
+        .map(|(_, url, title)| FilterEntry {
+            url: url.as_str(),
+            title: title.as_deref(),
+            feed_url: &feed.url,
+        })
+        .collect();
+
+    if !feed.filter_batch {
+        return entries
+            .iter()
+            .map(
+                |entry| match filter_entry(command, entry, fetcher.command_timeout()) {
+                    Ok(verdict) => Some(verdict),
+                    Err(error) => {
+                        errors.push(ErrorWithContext::new(
+                            error,
+                            format!(
+                                "the filter command for feed {} failed on {}",
+                                feed.label(),
+                                entry.url
+                            ),
+                        ));
+                        None
+                    }
+                },
+            )
+            .collect();
+    }
+
+    if entries.is_empty() {
+        return vec![];
+    }
+    match filter_entries(command, &entries, fetcher.command_timeout()) {
+        Ok(verdicts) => verdicts.into_iter().map(Some).collect(),
+        Err(error) => {
+            errors.push(ErrorWithContext::new(
+                error,
+                format!("the filter command for feed {} failed", feed.label()),
+            ));
+            vec![None; entries.len()]
+        }
+    }
+}
+
+/// Sends the entries that were queued because Pocket was unreachable.
+/// Entries that fail again stay in the queue,
+/// unless they've already been tried `max_attempts` times.
+///
+/// Each entry is sent with the pool of its account, from `pools`.
+fn send_push_queue(
+    feed: &mut FeedConfiguration,
+    pools: &HashMap<String, PushPool>,
+    push_delay: Duration,
+    max_attempts: u32,
+) {
+    let queue = mem::take(&mut feed.push_queue);
+    let mut batches: BTreeMap<String, (Vec<PushRequest>, Vec<QueuedPush>)> = BTreeMap::new();
+    for queued_push in queue {
+        match Url::parse(&queued_push.url) {
+            Ok(url) => {
+                let account = queued_push
+                    .account
+                    .clone()
+                    .unwrap_or_else(|| feed.account_name().to_owned());
+                let (requests, queued) = batches.entry(account).or_default();
+                requests.push(PushRequest {
+                    feed_url: feed.url.clone(),
+                    feed_label: feed.label(),
+                    url,
+                    title: queued_push.title.clone(),
+                    tags: queued_push.tags.clone(),
+                });
+                queued.push(queued_push);
+            }
+            Err(e) => eprintln!(
+                "'{}' is not a valid URL ({}). ignoring.",
+                queued_push.url, e
+            ),
+        }
+    }
+
+    eprintln!(
+        "sending {} queued entries of {}",
+        batches
+            .values()
+            .map(|(_, queued)| queued.len())
+            .sum::<usize>(),
+        feed.label()
+    );
+    // The entries are identified by their URL in the feed,
+    // which is how they're recorded in `processed_entries`.
+    let mut pushed = vec![];
+    let mut rejected = vec![];
+    for (account, (requests, queued)) in batches {
+        let Some(pool) = pools.get(&account) else {
+            feed.push_queue.extend(queued);
+            continue;
+        };
+        let results = pool.push(requests, push_delay);
+        for (queued_push, result) in queued.into_iter().zip(results) {
+            match result {
+                Ok(()) => pushed.push(queued_push),
+                Err(error) if error.is_permanent() => {
+                    eprintln!(
+                        "URL {url} was rejected by Pocket and won't be retried:\n  {error}",
+                        url = queued_push.url,
+                        error = Indented(&error)
+                    );
+                    feed.set_queued_status(&queued_push, EntryStatus::RejectedByPocket);
+                    rejected.push(queued_push.entry_url().to_owned());
+                }
+                Err(error) => requeue(feed, queued_push, &error, max_attempts),
+            }
+        }
+    }
+
+    // An entry that is sent to several accounts is only pushed
+    // once none of them is still waiting for it.
+    pushed.sort_by(|a, b| a.entry_url().cmp(b.entry_url()));
+    pushed.dedup_by(|a, b| a.entry_url() == b.entry_url());
+    for queued_push in pushed {
+        let entry_url = queued_push.entry_url();
+        if !rejected.iter().any(|x| x == entry_url)
+            && !feed.push_queue.iter().any(|x| x.entry_url() == entry_url)
+        {
+            feed.set_queued_status(&queued_push, EntryStatus::Pushed);
+            feed.entries_pushed_total += 1;
+            feed.last_push_date = Some(Utc::now());
+        }
+    }
+}
+
+/// Puts an entry that failed to be sent back in the push queue,
+/// unless it's already been tried `max_attempts` times.
+fn requeue(
+    feed: &mut FeedConfiguration,
+    mut queued_push: QueuedPush,
+    error: &PocketError,
+    max_attempts: u32,
+) {
+    queued_push.attempts += 1;
+    if queued_push.attempts >= max_attempts {
+        eprintln!(
+            "WARNING: giving up on sending URL {url} to Pocket after {attempts} syncs:\n  {error}",
+            url = queued_push.url,
+            attempts = queued_push.attempts,
+            error = Indented(error)
+        );
+    } else {
+        eprintln!(
+            "error while adding URL {url} to Pocket, it will be retried on the next sync:\n  {error}",
+            url = queued_push.url,
+            error = Indented(error)
+        );
+        feed.push_queue.push(queued_push);
+    }
+}
+
+/// Replaces the line breaks and other control characters in a title with spaces
+/// and collapses runs of whitespace,
+/// so that feeds can't mess up the output with their titles.
+fn sanitize_title(title: &str) -> String {
+    title
+        .split(|c: char| c.is_control() || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses an entry's URL,
+/// resolving it against `base_url` (the feed's URL) if it's relative.
+fn resolve_entry_url(entry_url: &str, base_url: Option<&Url>) -> Result<Url, url::ParseError> {
+    match (Url::parse(entry_url), base_url) {
+        (Err(url::ParseError::RelativeUrlWithoutBase), Some(base_url)) => base_url.join(entry_url),
+        (result, _) => result,
+    }
+}
+
+/// Returns the title to send to Pocket for an entry.
+fn entry_title(
+    feed: &FeedConfiguration,
+    fetcher: &mut Fetcher,
+    entry_url: &Url,
+    title: Option<String>,
+) -> Option<String> {
+    let title = match title {
+        Some(title) => Some(title),
+        None if feed.fetch_titles => {
+            // Pocket would show the URL instead of a title,
+            // so get the title from the entry's web page.
+            fetcher.fetch_title(feed, entry_url).unwrap_or_else(|e| {
+                debug!("failed to fetch the title of {}: {}", entry_url, e);
+                None
+            })
+        }
+        None => None,
+    };
+
+    match &feed.title_template {
+        Some(template) => {
+            // Use the URL's path in place of a missing title.
+            let rendered = template.render(&TitleValues {
+                title: title.as_deref().unwrap_or(entry_url.path()),
+                feed_title: feed.title.as_deref().unwrap_or(""),
+                feed_host: Url::parse(&feed.url)
+                    .ok()
+                    .as_ref()
+                    .and_then(Url::host_str)
+                    .unwrap_or(""),
+            });
+            Some(rendered).filter(|title| !title.trim().is_empty())
+        }
+        None => title,
+    }
+}
+
+/// Simple program to greet a person
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None, display_name = "Feeds to Pocket")]
+struct Args {
+    /// A YAML file containing your feeds configuration.
+    //#[clap(short, long, value_parser)]
+    #[clap(index = 1)]
+    config: PathBuf,
+
+    /// Print more details (e.g. feed parsing errors).
+    /// Pass it twice to also print how each feed was downloaded.
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Refuse to load a configuration file with unknown fields
+    /// (e.g. misspelled ones), which are otherwise ignored.
+    #[clap(long, global = true)]
+    strict: bool,
+
+    /// When the configuration file can't be loaded,
+    /// also print the complete chain of errors with all their details.
+    #[clap(long, global = true)]
+    show_config_errors_full: bool,
+
+    /// Never write to disk: subcommands that change the configuration file fail,
+    /// and `sync` only keeps its changes in memory.
+    /// With `sync --dry-run`, nothing is sent to Pocket either.
+    #[clap(long, global = true)]
+    read_only: bool,
+
+    /// Don't send requests through a proxy,
+    /// even if the HTTP_PROXY, HTTPS_PROXY or ALL_PROXY environment variables are set.
+    #[clap(long, global = true)]
+    no_proxy: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    fn load_config(&self) -> Result<Configuration, ErrorWithContext> {
+        load_config(&self.config, self.strict).map_err(|e| e.with_kind(ErrorKind::Config))
+    }
+
+    fn with_config<T>(
+        &self,
+        mut callback: impl FnMut(&mut Configuration) -> Result<T, ErrorWithContext>,
+    ) -> Result<T, ErrorWithContext> {
+        // Refuse before doing anything, e.g. logging in.
+        if read_only() {
+            try_with_context!(
+                Err(ReadOnly::ReadOnly),
+                "this subcommand changes the configuration file"
+            );
+        }
+
+        let mut config = self.load_config()?;
+
+        let result = callback(&mut config)?;
+
+        save_config(&config, &self.config)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(Parser, Debug)]
+enum Command {
+    /// Creates an empty configuration file (if it doesn't already exist).
+    Init,
+
+    /// Sets the consumer key in the configuration file.
+    SetConsumerKey {
+        /// A consumer key obtained from Pocket's website.
+        /// You must create your own application
+        /// at https://getpocket.com/developer/apps/new
+        /// to obtain a consumer key;
+        /// I don't want you kicking me out of my own application! :)
+        /// Make sure your application has at least the "Add" permission.
+        key: String,
+
+        /// The account to set the consumer key for.
+        #[clap(long, default_value = DEFAULT_ACCOUNT)]
+        account: String,
+    },
+
+    /// Obtains and saves an access token from Pocket.
+    /// This will print a URL on the standard output,
+    /// which you must open in a web browser
+    /// in order to grant your application access to your Pocket account.
+    /// Once authorization has been obtained,
+    /// an access token is saved in the configuration file,
+    /// which will be used to queue up entries in your Pocket list.
+    Login(LoginCommand),
+
+    /// Finishes a login started with `login --no-wait`
+    /// and saves the access token in the configuration file.
+    FinishLogin {
+        /// The account to finish the login of.
+        #[clap(long, default_value = DEFAULT_ACCOUNT)]
+        account: String,
+    },
+
+    /// Downloads your feeds and sends new entries to Pocket.
+    /// This is the default when no subcommand is given.
+    Sync(SyncCommand),
+
+    /// Adds a feed to your feeds configuration
+    /// or updates an existing feed in your feeds configuration.
+    Add(AddCommand),
+
+    /// Marks the entries of your feeds that are already in your Pocket list
+    /// (on the same website as the feed's entries) as processed,
+    /// so that they won't be sent to Pocket again.
+    ImportPocketState(ImportPocketStateCommand),
+
+    /// Marks all the entries currently in a feed as processed
+    /// without sending them to Pocket.
+    MarkRead {
+        /// The URL or name of the feed whose entries are marked as processed.
+        feed_url: String,
+    },
+
+    /// Makes the next sync try again the entries of a feed
+    /// that were skipped because their URL was invalid.
+    ReprocessInvalid {
+        /// The URL or name of the feed whose invalid entries are tried again.
+        feed_url: String,
+    },
+
+    /// Prints a feed's processed entries and validators,
+    /// to be imported in another configuration file with `import-state`.
+    ExportState {
+        /// The URL or name of the feed to export.
+        feed_url: String,
+    },
+
+    /// Merges a feed's state exported with `export-state`
+    /// into the feed in your feeds configuration.
+    ImportState {
+        /// The URL or name of the feed to update.
+        feed_url: String,
+
+        /// A file produced by `export-state`.
+        state_file: PathBuf,
+    },
+
+    /// Merges the feeds from another configuration file
+    /// into your feeds configuration.
+    /// Feeds that are in both files keep the processed entries from both files.
+    /// The consumer key and access token of your configuration are kept.
+    Merge(MergeCommand),
+
+    /// Removes a feed from your feeds configuration.
+    Remove {
+        /// The URL or name of the feed to remove.
+        feed_url: String,
+    },
+
+    /// Lists the feeds in your feeds configuration.
+    List {
+        /// Also print each feed's title, tags
+        /// and when it was last fetched and last had a new entry.
+        #[clap(short, long)]
+        long: bool,
+
+        /// Sort the feeds by when they were last synced, least recent first,
+        /// to show which feeds are falling behind.
+        #[clap(long)]
+        by_last_synced: bool,
+    },
+
+    /// Checks that every feed can be downloaded and parsed.
+    /// Nothing is sent to Pocket and the configuration file isn't modified.
+    Validate(ValidateCommand),
+
+    /// Finds which feeds produced the entries whose URL matches a query.
+    Search(SearchCommand),
+
+    /// Shows the entries that the next sync would send to Pocket.
+    /// Nothing is sent to Pocket and the configuration file isn't modified.
+    Pending(PendingCommand),
+
+    /// Disables feeds, so that they're not downloaded when syncing.
+    Disable(ToggleCommand),
+
+    /// Enables feeds that were disabled with `disable`.
+    Enable(ToggleCommand),
+
+    /// Shows how many entries each feed pushed to Pocket
+    /// and how many times it failed to download.
+    Stats(StatsCommand),
+
+    /// Shows the last entries added to Pocket,
+    /// as recorded in the history file (see `history_file`).
+    History(HistoryCommand),
+
+    /// Shows whether the accounts are set up, how many feeds there are,
+    /// when the last successful sync finished and which feeds are failing.
+    /// Nothing is downloaded and the configuration file isn't modified.
+    Status(StatusCommand),
+
+    /// Makes the state saved in the configuration file smaller:
+    /// removes the oldest processed entries beyond `max_processed_entries`,
+    /// replaces the processed entries of the feeds
+    /// whose `processed_entries_format` is `hashed` with their hashes
+    /// and removes empty and expired fields.
+    CompactState(CompactStateCommand),
+}
+
+#[derive(Parser, Debug)]
+struct LoginCommand {
+    /// The account to login to.
+    /// Use a different account for each Pocket account
+    /// you want to send entries to.
+    #[clap(long, default_value = DEFAULT_ACCOUNT)]
+    account: String,
+
+    /// The page Pocket sends you to after you authorize the application.
+    /// Must be an absolute URI.
+    /// Overrides redirect_uri in the configuration file.
+    #[clap(long)]
+    redirect_uri: Option<Url>,
+
+    /// Also show the authorization URL as a QR code,
+    /// to open it on another device (e.g. a phone).
+    #[clap(long)]
+    qr: bool,
+
+    /// Don't wait for the authorization:
+    /// save the request token in the configuration file and exit.
+    /// Run `finish-login` once you've authorized the application.
+    #[clap(long)]
+    no_wait: bool,
+
+    /// Once logged in, check that the consumer key has the "Add" permission
+    /// by adding this program's home page to your Pocket list.
+    #[clap(long, conflicts_with = "no_wait")]
+    verify_permissions: bool,
+}
+
+#[derive(Parser, Debug, Default)]
+struct SyncCommand {
+    /// Download every feed,
+    /// even those whose last response is still fresh
+    /// according to the server's Cache-Control header.
+    #[clap(long)]
+    force: bool,
+
+    /// Only download the feeds in this group.
+    #[clap(long)]
+    group: Option<String>,
+
+    /// Replace the URL of the feeds whose self link points to another URL
+    /// (e.g. because the feed moved) with that URL.
+    #[clap(long)]
+    update_feed_urls: bool,
+
+    /// Don't start downloading another feed after this time
+    /// (e.g. 90s, 15m or 2h; seconds if there's no unit).
+    /// The next sync starts with the feeds that were skipped.
+    #[clap(long, value_parser = parse_duration)]
+    max_runtime: Option<Duration>,
+
+    /// Don't start downloading another feed
+    /// after downloading this amount of data
+    /// (e.g. 512KB, 20MB or 1GB; bytes if there's no unit).
+    /// The next sync starts with the feeds that were skipped.
+    #[clap(long, value_parser = parse_size)]
+    max_download: Option<u64>,
+
+    /// Send all the new entries of every feed to Pocket,
+    /// ignoring `max_pushes_per_run`.
+    #[clap(long)]
+    no_limit: bool,
+
+    /// Send the new entries of this feed to Pocket
+    /// even if they look like old entries that were republished.
+    /// Can be repeated.
+    #[clap(long, value_name = "FEED")]
+    accept_republished: Vec<String>,
+
+    /// Don't ask for confirmation before sending many entries to Pocket
+    /// (see `confirm_pushes_over`).
+    #[clap(long, short)]
+    yes: bool,
+
+    /// Send the entries kept for the weekly digests now
+    /// (see `digest_day`).
+    #[clap(long)]
+    flush_digests: bool,
+
+    /// Download the feeds and show what would be sent to Pocket,
+    /// but don't send anything or modify the configuration file.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// With --dry-run, print the changes the sync would make
+    /// to the configuration file as a unified diff.
+    #[clap(long, requires = "dry_run")]
+    diff: bool,
+
+    /// Only process the feeds that don't need the network
+    /// (local files and `exec:` commands)
+    /// and queue their new entries instead of sending them to Pocket;
+    /// the next sync sends them.
+    #[clap(long)]
+    offline: bool,
+}
+
+impl SyncCommand {
+    /// Returns why the sync must stop, if it exceeded its budget.
+    fn exceeded_budget(&self, start: Instant, fetcher: &Fetcher) -> Option<String> {
+        if let Some(max_runtime) = self.max_runtime {
+            if start.elapsed() >= max_runtime {
+                return Some(format!(
+                    "the sync reached its maximum runtime of {} seconds (--max-runtime)",
+                    max_runtime.as_secs_f64()
+                ));
+            }
+        }
+
+        if let Some(max_download) = self.max_download {
+            if fetcher.downloaded_bytes() >= max_download {
+                return Some(format!(
+                    "the sync reached its maximum download size of {} bytes (--max-download)",
+                    max_download
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// Splits a number followed by an optional unit, e.g. `15m`,
+/// returning the unit in lowercase.
+fn split_unit(s: &str) -> Result<(u64, String), String> {
+    let s = s.trim();
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let number = s[..end]
+        .parse()
+        .map_err(|_| format!("expected a number, optionally followed by a unit: {:?}", s))?;
+    Ok((number, s[end..].trim().to_ascii_lowercase()))
+}
+
+/// Parses a duration such as `90s`, `15m` or `2h`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = split_unit(s)?;
+    let seconds = match &*unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return Err(format!("unknown unit {:?} (expected s, m or h)", unit)),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// Parses a size such as `512KB`, `20MB` or `1GB`,
+/// where a kilobyte is 1024 bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (number, unit) = split_unit(s)?;
+    let bytes = match &*unit {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown unit {:?} (expected KB, MB or GB)", unit)),
+    };
+    Ok(number.saturating_mul(bytes))
+}
+
+#[derive(Parser, Debug)]
+struct AddCommand {
+    /// Consider all the entries in the feed to be unread.
+    /// All entries will be sent to Pocket immediately.
+    /// By default, all the entries present when the feed is added
+    /// are considered read and are not sent to Pocket.
+    #[clap(long)]
+    unread: bool,
+
+    /// A comma-separated list of tags to attach to the URLs sent to Pocket.
+    /// To change the tags of a feed that's already in the configuration file,
+    /// use --tags-replace or --tags-append instead.
+    #[clap(long, conflicts_with_all = ["tags_replace", "tags_append"])]
+    tags: Option<String>,
+
+    /// Replace the feed's tags with this comma-separated list of tags.
+    #[clap(long, value_name = "TAGS", conflicts_with = "tags_append")]
+    tags_replace: Option<String>,
+
+    /// Add this comma-separated list of tags to the feed's tags.
+    #[clap(long, value_name = "TAGS")]
+    tags_append: Option<String>,
+
+    /// With --unread, don't send the entries that are already in your Pocket list.
+    #[clap(long, requires = "unread")]
+    skip_existing: bool,
+
+    /// The account whose Pocket list the feed's entries are sent to.
+    /// By default, entries are sent to the "default" account.
+    #[clap(long)]
+    account: Option<String>,
+
+    /// Show the feed's title and entries without adding the feed.
+    /// The configuration file isn't modified and Pocket isn't contacted.
+    #[clap(long, visible_alias = "dry-run")]
+    preview: bool,
+
+    /// With --preview, the maximum number of entries to show.
+    #[clap(long, default_value_t = 10, requires = "preview")]
+    limit: usize,
+
+    /// A short name for the feed,
+    /// which can be used instead of its URL in other subcommands.
+    #[clap(long)]
+    name: Option<String>,
+
+    /// The group the feed belongs to.
+    /// Feeds without a group are in the "default" group.
+    #[clap(long)]
+    group: Option<String>,
+
+    /// Use the URL as the feed's URL even if it's the URL of a page
+    /// of a well-known site (e.g. a YouTube channel or a subreddit)
+    /// rather than the URL of its feed.
+    #[clap(long)]
+    literal: bool,
+
+    /// For GitHub repositories, use the feed of the repository's releases.
+    /// This is the default.
+    #[clap(long, conflicts_with_all = ["github_commits", "literal"])]
+    github_releases: bool,
+
+    /// For GitHub repositories, use the feed of the repository's commits
+    /// instead of the feed of its releases.
+    #[clap(long, conflicts_with = "literal")]
+    github_commits: bool,
+
+    /// The URL of the feed to add,
+    /// or the URL or name of the feed to update.
+    feed_url: String,
+}
+
+impl AddCommand {
+    /// Returns the URL of the feed to add;
+    /// see [`resolve_known_sites`].
+    fn resolve_feed_url(&self, fetcher: &mut Fetcher) -> String {
+        if self.literal {
+            return self.feed_url.clone();
+        }
+
+        let github_feed = if self.github_commits {
+            GitHubFeed::Commits
+        } else {
+            GitHubFeed::Releases
+        };
+        resolve_known_sites(fetcher, &self.feed_url, github_feed)
+    }
+}
+
+#[derive(Parser, Debug)]
+struct ImportPocketStateCommand {
+    /// The account whose Pocket list is imported.
+    /// Only the feeds sent to this account are updated.
+    #[clap(long, default_value = DEFAULT_ACCOUNT)]
+    account: String,
+
+    /// Only consider the items that were saved to Pocket
+    /// or modified after this time (a Unix timestamp).
+    #[clap(long)]
+    since: Option<i64>,
+
+    /// The URLs or names of the feeds to update.
+    /// By default, all feeds are updated.
+    feed_urls: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct MergeCommand {
+    /// For feeds that are in both files,
+    /// use the tags from the other file instead of the tags from this file.
+    #[clap(long)]
+    prefer_other: bool,
+
+    /// The configuration file to merge into this one.
+    other_config: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct ValidateCommand {
+    /// Only validate the feeds whose URL contains this text.
+    /// Can be specified multiple times.
+    #[clap(long)]
+    only: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct SearchCommand {
+    /// Interpret the query as a regular expression
+    /// instead of as text to search for.
+    #[clap(long)]
+    regex: bool,
+
+    /// Search the entries currently in the feeds
+    /// that haven't been sent to Pocket yet,
+    /// instead of the entries that have already been processed.
+    /// This downloads every feed.
+    #[clap(long)]
+    pending: bool,
+
+    /// The text (or regular expression) to search for in entry URLs.
+    query: String,
+}
+
+#[derive(Parser, Debug)]
+struct ToggleCommand {
+    /// Apply to all the feeds in this group.
+    #[clap(
+        long,
+        conflicts_with = "feed_urls",
+        required_unless_present = "feed_urls"
+    )]
+    group: Option<String>,
+
+    /// The URLs or names of the feeds.
+    feed_urls: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct PendingCommand {
+    /// Show the pending entries of every feed.
+    #[clap(long, conflicts_with = "feed_url")]
+    all: bool,
+
+    /// The URL or name of the feed whose pending entries are shown.
+    #[clap(required_unless_present = "all")]
+    feed_url: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct CompactStateCommand {
+    /// Also download the feeds
+    /// and remove the processed entries that are no longer in them.
+    #[clap(long)]
+    fetch: bool,
+
+    /// Show how much smaller the configuration file would be,
+    /// but don't modify it.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Parser, Debug)]
+struct StatsCommand {
+    /// Point out the feeds that pushed nothing in this many days.
+    #[clap(long, default_value_t = 30)]
+    days: i64,
+
+    /// Reset the statistics of a feed (given by its URL or name)
+    /// instead of showing the statistics.
+    #[clap(long, value_name = "FEED_URL")]
+    reset: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct HistoryCommand {
+    /// The number of entries to show.
+    #[clap(long, default_value_t = 20)]
+    tail: usize,
+}
+
+#[derive(Parser, Debug)]
+struct StatusCommand {
+    /// Print the status as JSON.
+    #[clap(long)]
+    json: bool,
+
+    /// Exit with an error if the last successful sync finished
+    /// longer ago than this (e.g. 90s, 15m or 24h; seconds if there's no unit),
+    /// or if no sync finished successfully yet.
+    #[clap(long, value_parser = parse_duration)]
+    max_staleness: Option<Duration>,
+}
+
+/// The name of the account stored at the top level of the configuration.
+const DEFAULT_ACCOUNT: &str = "default";
+
+/// The URL `login --verify-permissions` adds to Pocket.
+const PERMISSION_CHECK_URL: &str = env!("CARGO_PKG_REPOSITORY");
+
+/// How long the request token saved by `login --no-wait` can be used.
+const PENDING_LOGIN_LIFETIME: chrono::TimeDelta = chrono::TimeDelta::hours(1);
+
+/// The name of the group of the feeds that don't have a group.
+const DEFAULT_GROUP: &str = "default";
+
+/// The maximum number of new entries `sync` sends to Pocket for a feed,
+/// if `max_pushes_per_run` isn't set.
+const DEFAULT_MAX_PUSHES_PER_RUN: usize = 50;
+
+/// The number of entries a sync sends to Pocket before asking for confirmation,
+/// if `confirm_pushes_over` isn't set.
+const DEFAULT_CONFIRM_PUSHES_OVER: usize = 100;
+
+/// The size in bytes above which feeds are parsed as a stream to save memory.
+const LARGE_FEED_SIZE: usize = 10 * 1024 * 1024;
+
+/// The fraction of a feed's entries that must be new
+/// for `sync` to check whether the feed republished old entries.
+const REPUBLISHED_FRACTION: f64 = 0.5;
+
+/// The number of syncs that try to send a queued entry before it's dropped,
+/// if `max_push_attempts` isn't set.
+const DEFAULT_MAX_PUSH_ATTEMPTS: u32 = 10;
+
+/// The length above which Pocket rejects a URL,
+/// if `max_url_length` isn't set.
+const DEFAULT_MAX_URL_LENGTH: usize = 2048;
+
+#[derive(Default, Deserialize, Serialize)]
+struct Configuration {
+    /// The Pocket credentials of the "default" account.
+    #[serde(flatten)]
+    default_account: Account,
+    /// The Pocket credentials of the other accounts.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    accounts: BTreeMap<String, Account>,
+    /// The minimum delay between two requests to the same host,
+    /// in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host_delay_ms: Option<u64>,
+    /// The maximum size of a feed's response body, in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_body_size: Option<u64>,
+    /// The maximum number of processed entries `compact-state` keeps per feed.
+    /// The oldest entries are removed first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_processed_entries: Option<usize>,
+    /// The maximum time a feed command (`exec:` feeds) may run, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command_timeout_secs: Option<u64>,
+    /// The maximum number of redirects to follow when downloading a feed.
+    /// 0 disables redirects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_redirects: Option<usize>,
+    /// The number of times to retry downloading a feed
+    /// after a network error (not after an error status).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fetch_retries: Option<u32>,
+    /// The delay before the first retry, in milliseconds.
+    /// The delay doubles after each retry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fetch_retry_delay_ms: Option<u64>,
+    /// The User-Agent to send when downloading feeds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    /// The delay between two consecutive pushes to Pocket
+    /// for the same feed, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    push_delay_ms: Option<u64>,
+    /// The base URL of Pocket's API,
+    /// to send the requests for Pocket to another server (e.g. for testing).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pocket_api_url: Option<String>,
+    /// The maximum time a request to Pocket may take, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pocket_timeout_secs: Option<u64>,
+    /// The maximum number of concurrent pushes to each Pocket account.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    push_workers: Option<usize>,
+    /// The maximum number of new entries `sync` sends to Pocket
+    /// for a feed in one run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_pushes_per_run: Option<usize>,
+    /// The number of syncs that try to send a queued entry
+    /// (see `push_queue`) before it's dropped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_push_attempts: Option<u32>,
+    /// The number of entries an interactive sync sends to Pocket
+    /// before asking for confirmation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confirm_pushes_over: Option<usize>,
+    /// The length above which entry URLs are too long for Pocket
+    /// (see `shorten_long_urls`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_url_length: Option<usize>,
+    /// A comma-separated list of tags to attach to every URL sent to Pocket,
+    /// after the feed's tags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag_all: Option<String>,
+    /// Try IPv4 addresses before IPv6 addresses when downloading feeds.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    prefer_ipv4: bool,
+    /// Only use IPv4 addresses when downloading feeds.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    force_ipv4: bool,
+    /// Honor the Crawl-delay and Disallow rules
+    /// of the robots.txt files of the feeds' hosts.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    respect_robots_txt: bool,
+    /// A file to which a JSON line is appended
+    /// for every entry added to Pocket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history_file: Option<PathBuf>,
+    /// The page Pocket sends the user to after authorizing the application
+    /// during login.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_uri: Option<Url>,
+    /// When a sync last finished, even if some feeds failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_sync: Option<DateTime<Utc>>,
+    /// When a sync last finished without any feed failing to download or parse.
+    /// Entries that failed to be sent to Pocket don't count,
+    /// since they're retried on the next sync.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_successful_sync: Option<DateTime<Utc>>,
+    /// Other files with more feeds,
+    /// relative to the directory of this file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    feeds: Vec<FeedConfiguration>,
+    /// The files loaded through `include`, in the order they were loaded.
+    #[serde(skip)]
+    included_files: Vec<IncludedFile>,
+}
+
+/// A file listed in the `include` field of a configuration file.
+#[derive(Default, Deserialize, Serialize)]
+struct IncludeFile {
+    /// Other files with more feeds,
+    /// relative to the directory of this file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    feeds: Vec<FeedConfiguration>,
+}
+
+/// A file loaded through `include`,
+/// whose feeds are saved back to it.
+struct IncludedFile {
+    /// The path of the file, relative to the current directory.
+    path: PathBuf,
+    /// The `include` field of the file.
+    include: Vec<PathBuf>,
+}
+
+impl Configuration {
+    /// Checks the values that can't be checked while deserializing.
+    fn validate(&self) -> Result<(), ErrorWithContext> {
+        if let Some(user_agent) = &self.user_agent {
+            try_with_context!(
+                HeaderValue::from_str(user_agent),
+                format!("invalid user_agent {:?}", user_agent)
+            );
+        }
+
+        for feed in &self.feeds {
+            if let Some(name) = &feed.name {
+                try_with_context!(self.check_feed_name(name, &feed.url), "invalid feed name");
+            }
+
+            if let Some(user_agent) = &feed.user_agent {
+                try_with_context!(
+                    HeaderValue::from_str(user_agent),
+                    format!("invalid user_agent {:?} for feed {}", user_agent, feed.url)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `name` can be the name of the feed with URL `feed_url`,
+    /// i.e. that no other feed uses it as its name or URL.
+    fn check_feed_name(&self, name: &str, feed_url: &str) -> Result<(), InvalidFeedName> {
+        if name.is_empty() {
+            return Err(InvalidFeedName::Empty(feed_url.into()));
+        }
+
+        match self
+            .feeds
+            .iter()
+            .find(|feed| feed.url != feed_url && feed.is_identified_by(name))
+        {
+            Some(feed) => Err(InvalidFeedName::Duplicate(name.into(), feed.url.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn account(&self, name: &str) -> Result<&Account, PocketSetupError> {
+        if name == DEFAULT_ACCOUNT {
+            Ok(&self.default_account)
+        } else {
+            self.accounts
+                .get(name)
+                .ok_or_else(|| PocketSetupError::UnknownAccount(name.into()))
+        }
+    }
+
+    /// Returns the account with the given name,
+    /// adding it to the configuration if it doesn't exist.
+    fn account_mut(&mut self, name: &str) -> &mut Account {
+        if name == DEFAULT_ACCOUNT {
+            &mut self.default_account
+        } else {
+            self.accounts.entry(name.into()).or_default()
+        }
+    }
+}
+
+/// The credentials for a Pocket account.
+#[derive(Default, Deserialize, Serialize)]
+struct Account {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consumer_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    access_token: Option<String>,
+    /// The Pocket username the access token belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    /// A login started with `login --no-wait`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending_login: Option<PendingLogin>,
+}
+
+impl Account {
+    /// Saves the access token of a successful login.
+    fn logged_in(&mut self, pocket: &Pocket, username: String) {
+        eprintln!("Logged in as {}", username);
+        self.access_token = Some(String::from(pocket.access_token().unwrap()));
+        self.username = Some(username);
+        self.pending_login = None;
+    }
+}
+
+/// The request token of a login that `finish-login` will complete.
+#[derive(Deserialize, Serialize)]
+struct PendingLogin {
+    code: String,
+    requested_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct FeedConfiguration {
+    url: String,
+    /// A short name that can be used instead of the URL in subcommands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// The group this feed belongs to.
+    /// The "default" group is used if this is not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// Don't download this feed when syncing.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    disabled: bool,
+    /// The account to send this feed's entries to.
+    /// The "default" account is used if this is not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<String>,
+    /// Other accounts that also receive this feed's entries.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    also_push_to: Vec<String>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    #[serde(default)]
+    tags: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    processed_entries: Vec<ProcessedEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pending_entries: Vec<String>,
+    /// The new entries held back by `push_window` or `digest_day`,
+    /// which a later sync sends to Pocket.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    deferred_entries: Vec<String>,
+    /// The entries that couldn't be sent because Pocket was unreachable.
+    /// They're already marked as processed,
+    /// and `sync` sends them before downloading the feeds.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    push_queue: Vec<QueuedPush>,
+    /// The accounts that already received an entry
+    /// that some of the feed's other accounts haven't received yet,
+    /// by entry URL, so that retrying the entry doesn't send it twice.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    partial_pushes: BTreeMap<String, Vec<String>>,
+    /// The entries that were marked as processed because their URL was invalid,
+    /// as they appeared in the feed, for `reprocess-invalid`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    invalid_entries: Vec<String>,
+    /// The Last-Modified header from the last response,
+    /// or the modification time of a local file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_e_tag: Option<String>,
+    /// A file containing an additional root certificate (PEM or DER)
+    /// to trust when downloading this feed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_ca_file: Option<PathBuf>,
+    /// Don't validate the server's certificate when downloading this feed.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    danger_accept_invalid_certs: bool,
+    /// A template for the titles sent to Pocket,
+    /// with `{title}`, `{feed_title}` and `{feed_host}` placeholders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title_template: Option<TitleTemplate>,
+    /// Get the title of entries that don't have one in the feed
+    /// from the entry's web page.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    fetch_titles: bool,
+    /// Check that new entries' web pages exist before pushing them,
+    /// and skip the entries whose page is gone (404 Not Found or 410 Gone).
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    verify_links: bool,
+    /// Send the canonical URL of new entries' web pages
+    /// (from `<link rel="canonical">` or `og:url`) to Pocket
+    /// instead of the URL in the feed.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    resolve_canonical: bool,
+    /// Send entry URLs that are too long for Pocket
+    /// without their query string and fragment
+    /// instead of skipping them.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    shorten_long_urls: bool,
+    /// A command that decides whether each new entry is sent to Pocket
+    /// (see the `filter` module).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter_command: Option<String>,
+    /// Run `filter_command` once for all the new entries of the feed
+    /// instead of once per entry.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    filter_batch: bool,
+    /// The time of day during which new entries are sent to Pocket;
+    /// outside it, they're kept in `deferred_entries` for a later sync.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    push_window: Option<PushWindow>,
+    /// Send the new entries to Pocket once a week, on the first sync
+    /// on or after this day; until then, they're kept in `deferred_entries`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest_day: Option<DigestDay>,
+    /// Parse the feed as a stream, keeping only the parts of the entries we use,
+    /// even if it's smaller than `LARGE_FEED_SIZE`.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    large_feed: bool,
+    /// The character encoding of the feed,
+    /// for servers that send the wrong one in the Content-Type header
+    /// and the XML declaration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    charset: Option<String>,
+    /// Send If-Modified-Since even when an ETag is known
+    /// (by default, only If-None-Match is sent in that case).
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    always_send_if_modified_since: bool,
+    /// Download the feed again without validators
+    /// if the server has answered 304 Not Modified for this many days,
+    /// for servers that don't update their validators.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_not_modified_age_days: Option<u64>,
+    /// Compare entry URLs exactly when checking if an entry was processed,
+    /// e.g. for sites where the `http` and `https` versions of a page differ.
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    exact_entry_urls: bool,
+    /// How the processed entries are stored.
+    #[serde(skip_serializing_if = "ProcessedEntriesFormat::is_plain")]
+    #[serde(default)]
+    processed_entries_format: ProcessedEntriesFormat,
+    /// When the validators of the last response
+    /// (`last_modified` and `last_e_tag`) are saved.
+    #[serde(skip_serializing_if = "ValidatorUpdate::is_default")]
+    #[serde(default)]
+    validator_update: ValidatorUpdate,
+    /// The delay between two consecutive pushes to Pocket, in milliseconds,
+    /// overriding the one in the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    push_delay_ms: Option<u64>,
+    /// The maximum number of new entries `sync` sends to Pocket in one run,
+    /// overriding the one in the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_pushes_per_run: Option<usize>,
+    /// The User-Agent to send when downloading this feed,
+    /// overriding the one in the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    /// Whether to try IPv4 addresses before IPv6 addresses
+    /// when downloading this feed,
+    /// overriding `prefer_ipv4` in the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefer_ipv4: Option<bool>,
+    /// Whether to only use IPv4 addresses when downloading this feed,
+    /// overriding `force_ipv4` in the configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    force_ipv4: Option<bool>,
+    /// Connect to this address instead of resolving the feed's host,
+    /// written as `HOST:PORT:ADDRESS` (like curl's `--resolve` option).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolve: Option<ResolveOverride>,
+    /// The feed's title, as of the last successful fetch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    /// When the feed was last fetched successfully.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_fetched: Option<DateTime<Utc>>,
+    /// When a new entry was last found in the feed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_new_entry: Option<DateTime<Utc>>,
+    /// The publication date of the newest processed entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    newest_entry_published: Option<DateTime<Utc>>,
+    /// When `sync` last processed the feed, whether it succeeded or not.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_synced: Option<DateTime<Utc>>,
+    /// Why the feed failed the last time `sync` processed it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    /// The number of entries pushed to Pocket from this feed
+    /// since the statistics were last reset.
+    #[serde(skip_serializing_if = "is_zero")]
+    #[serde(default)]
+    entries_pushed_total: u64,
+    /// When an entry from this feed was last pushed to Pocket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_push_date: Option<DateTime<Utc>>,
+    /// Since when the server has answered 304 Not Modified
+    /// (see `max_not_modified_age_days`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_modified_since: Option<DateTime<Utc>>,
+    /// When the last weekly digest was sent (see `digest_day`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_digest: Option<DateTime<Utc>>,
+    /// The number of times this feed failed to download
+    /// since the statistics were last reset.
+    #[serde(skip_serializing_if = "is_zero")]
+    #[serde(default)]
+    fetch_error_count: u64,
+    /// The feed won't be downloaded again before this time,
+    /// according to the Cache-Control header of the last response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fresh_until: Option<DateTime<Utc>>,
+    /// The index in `Configuration::included_files` of the file
+    /// this feed was loaded from and is saved to,
+    /// or `None` for the main configuration file.
+    #[serde(skip)]
+    source_file: Option<usize>,
+}
+
+/// Returns the standard serialization of a URL,
+/// with a punycode host and a percent-encoded path,
+/// so that e.g. `https://яндекс.рф/` and `https://xn--d1acpjx3f.xn--p1ai/` are equal.
+///
+/// Strings that aren't valid URLs (such as local paths) are returned as is.
+fn serialized_url(url: &str) -> String {
+    Url::parse(url).map_or_else(|_| url.into(), String::from)
+}
+
+/// Returns the form of an entry URL used to tell if an entry was already processed,
+/// so that the variants of a URL that sites use interchangeably are equal:
+/// `http` is replaced with `https`
+/// and default ports, trailing slashes and fragments are removed.
+///
+/// Strings that aren't valid URLs are returned as is.
+fn canonical_entry_url(entry_url: &str) -> String {
+    let Ok(mut url) = Url::parse(entry_url) else {
+        return entry_url.into();
+    };
+
+    if url.scheme() == "http" {
+        let _ = url.set_scheme("https");
+    }
+    if url.path().len() > 1 && url.path().ends_with('/') {
+        let path = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&path);
+    }
+    url.set_fragment(None);
+    url.into()
+}
+
+/// How a feed stores its processed entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ProcessedEntriesFormat {
+    /// The entry URLs.
+    #[default]
+    Plain,
+    /// A short hash of the entry URLs,
+    /// which keeps the configuration file small when the URLs are long.
+    Hashed,
+}
+
+impl ProcessedEntriesFormat {
+    fn is_plain(&self) -> bool {
+        *self == ProcessedEntriesFormat::Plain
+    }
+}
+
+/// When a feed saves the validators (`Last-Modified` and `ETag`) of a response,
+/// which make the server answer 304 Not Modified on the next sync
+/// if the feed didn't change.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ValidatorUpdate {
+    /// Once the feed was downloaded and parsed.
+    /// The entries that failed to be sent are retried from `pending_entries`,
+    /// even if the feed didn't change.
+    #[default]
+    OnFetchSuccess,
+    /// Only once every new entry was sent to Pocket.
+    /// If a push fails, the whole feed is downloaded again on the next sync.
+    Strict,
+    /// After every successful download,
+    /// even if the feed can't be parsed
+    /// or its new entries were skipped (e.g. because they look republished).
+    /// Those entries are only seen again once the feed changes.
+    Always,
+}
+
+impl ValidatorUpdate {
+    fn is_default(&self) -> bool {
+        *self == ValidatorUpdate::default()
+    }
+}
+
+/// The number of hex digits of the hashes stored in `processed_entries`.
+const ENTRY_HASH_LENGTH: usize = 16;
+
+/// Returns the hash of an entry key
+/// that feeds with the `hashed` format store in `processed_entries`:
+/// the first hex digits of its SHA-256.
+fn entry_hash(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .take(ENTRY_HASH_LENGTH / 2)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Returns true if a processed entry is a hash rather than a URL.
+fn is_entry_hash(entry: &str) -> bool {
+    entry.len() == ENTRY_HASH_LENGTH && entry.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Returns the form of an entry URL stored in `processed_entries`:
+/// the canonical URL, unless `exact_entry_urls` is set.
+fn entry_key(entry_url: &str, exact_entry_urls: bool) -> String {
+    if exact_entry_urls {
+        serialized_url(entry_url)
+    } else {
+        canonical_entry_url(entry_url)
+    }
+}
+
+/// The keys of the processed entries of a feed.
+/// An entry was already processed, possibly with another variant of its URL,
+/// if its key is in this set.
+///
+/// Build the set once to check many entries;
+/// `processed_entries` can hold many thousands of URLs.
+struct ProcessedKeys {
+    keys: HashSet<String>,
+    exact_entry_urls: bool,
+    /// Compare hashes instead of URLs,
+    /// because the feed stores hashes or already has some.
+    hashed: bool,
+}
+
+impl ProcessedKeys {
+    fn key(&self, entry_url: &str) -> String {
+        let key = entry_key(entry_url, self.exact_entry_urls);
+        if self.hashed {
+            entry_hash(&key)
+        } else {
+            key
+        }
+    }
+
+    fn contains(&self, entry_url: &str) -> bool {
+        self.keys.contains(&self.key(entry_url))
+    }
+
+    /// Returns the key of an entry stored in `processed_entries`.
+    fn stored_key(&self, entry: &str) -> String {
+        if is_entry_hash(entry) {
+            entry.into()
+        } else {
+            // Entries processed by older versions weren't stored in canonical form.
+            self.key(entry)
+        }
+    }
+
+    /// Adds an entry to the set, returning false if it was already processed.
+    fn insert(&mut self, entry_url: &str) -> bool {
+        self.keys.insert(self.key(entry_url))
+    }
+}
+
+impl FeedConfiguration {
+    /// Returns the delay between two consecutive pushes to Pocket,
+    /// given the delay from the configuration.
+    fn push_delay(&self, default_push_delay_ms: Option<u64>) -> Duration {
+        Duration::from_millis(self.push_delay_ms.or(default_push_delay_ms).unwrap_or(0))
+    }
+
+    /// Returns the maximum number of new entries `sync` sends to Pocket in one run,
+    /// given the maximum from the configuration.
+    fn max_pushes_per_run(&self, default_max_pushes_per_run: Option<usize>) -> usize {
+        self.max_pushes_per_run
+            .or(default_max_pushes_per_run)
+            .unwrap_or(DEFAULT_MAX_PUSHES_PER_RUN)
+    }
+
+    /// Returns true if the server has answered 304 Not Modified
+    /// for longer than `max_not_modified_age_days` at `now`.
+    fn not_modified_for_too_long(&self, now: DateTime<Utc>) -> bool {
+        match (self.max_not_modified_age_days, self.not_modified_since) {
+            (Some(days), Some(since)) => i64::try_from(days)
+                .ok()
+                .and_then(chrono::TimeDelta::try_days)
+                .is_some_and(|max_age| now - since > max_age),
+            _ => false,
+        }
+    }
+
+    /// Returns true if `feed_id` is this feed's URL or name.
+    fn is_identified_by(&self, feed_id: &str) -> bool {
+        self.url == feed_id
+            || serialized_url(&self.url) == serialized_url(feed_id)
+            || self.name.as_deref() == Some(feed_id)
+    }
+
+    /// Returns the feed's URL followed by its title, if known,
+    /// to tell feeds apart in messages.
+    fn label(&self) -> String {
+        match self.display_title() {
+            Some(title) => format!("{} ({})", self.url, title),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Returns the feed's title, safe to print on a single line.
+    fn display_title(&self) -> Option<String> {
+        self.title
+            .as_deref()
+            .map(sanitize_title)
+            .filter(|title| !title.is_empty())
+    }
+
+    fn account_name(&self) -> &str {
+        self.account.as_deref().unwrap_or(DEFAULT_ACCOUNT)
+    }
+
+    /// Returns the names of the accounts this feed's entries are sent to:
+    /// the feed's account, then the ones in `also_push_to`.
+    fn account_names(&self) -> Vec<&str> {
+        let mut names = vec![self.account_name()];
+        for name in &self.also_push_to {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn was_partially_pushed_to(&self, entry_url: &str, account: &str) -> bool {
+        self.partial_pushes
+            .get(entry_url)
+            .is_some_and(|accounts| accounts.iter().any(|x| x == account))
+    }
+
+    /// Records that `account` received an entry,
+    /// until every account of the feed has received it.
+    fn record_partial_push(&mut self, entry_url: &str, account: &str) {
+        if !self.was_partially_pushed_to(entry_url, account) {
+            self.partial_pushes
+                .entry(entry_url.to_owned())
+                .or_default()
+                .push(account.to_owned());
+        }
+    }
+
+    fn group_name(&self) -> &str {
+        self.group.as_deref().unwrap_or(DEFAULT_GROUP)
+    }
+
+    fn entry_key(&self, entry_url: &str) -> String {
+        entry_key(entry_url, self.exact_entry_urls)
+    }
+
+    fn processed_keys(&self) -> ProcessedKeys {
+        // Entries processed before the feed switched to hashes are still URLs.
+        let hashed = self.processed_entries_format == ProcessedEntriesFormat::Hashed
+            || self
+                .processed_entries
+                .iter()
+                .any(|entry| is_entry_hash(&entry.url));
+        let mut keys = ProcessedKeys {
+            keys: HashSet::new(),
+            exact_entry_urls: self.exact_entry_urls,
+            hashed,
+        };
+        for entry in &self.processed_entries {
+            keys.keys.insert(keys.stored_key(&entry.url));
+        }
+        keys
+    }
+
+    /// Removes the processed entries that aren't in the feed anymore.
+    /// Returns the number of entries that were removed.
+    fn forget_unseen_entries(&mut self, parsed_feed: &Feed) -> usize {
+        // The feed may be empty temporarily;
+        // forgetting everything would push every entry again.
+        if parsed_feed.entries.is_empty() {
+            return 0;
+        }
+
+        let mut current = self.processed_keys();
+        current.keys.clear();
+        for entry_url in parsed_feed
+            .entries
+            .iter()
+            .flat_map(|entry| entry.alternate_links())
+        {
+            current.insert(entry_url);
+        }
+
+        let count = self.processed_entries.len();
+        self.processed_entries
+            .retain(|entry| current.keys.contains(&current.stored_key(&entry.url)));
+        count - self.processed_entries.len()
+    }
+
+    /// Moves the entries that were skipped because their URL was invalid
+    /// from the processed entries to the pending entries.
+    /// Entries skipped by older versions weren't recorded,
+    /// so the processed entries that are stored as invalid URLs are moved too.
+    /// Returns the number of entries that were moved.
+    fn reprocess_invalid_entries(&mut self) -> usize {
+        let mut invalid = mem::take(&mut self.invalid_entries);
+        for entry in &self.processed_entries {
+            let entry_url = &entry.url;
+            if !is_entry_hash(entry_url)
+                && Url::parse(entry_url).is_err()
+                && !invalid.contains(entry_url)
+            {
+                invalid.push(entry_url.clone());
+            }
+        }
+
+        let processed = self.processed_keys();
+        let invalid_keys: HashSet<String> = invalid
+            .iter()
+            .map(|entry_url| processed.key(entry_url))
+            .collect();
+        self.processed_entries
+            .retain(|entry| !invalid_keys.contains(&processed.stored_key(&entry.url)));
+        for entry_url in &invalid {
+            if !self.pending_entries.contains(entry_url) {
+                self.pending_entries.push(entry_url.clone());
+            }
+        }
+        invalid.len()
+    }
+
+    /// Removes the oldest processed entries beyond `max`.
+    /// Returns the number of entries that were removed.
+    fn trim_processed_entries(&mut self, max: usize) -> usize {
+        let count = self.processed_entries.len().saturating_sub(max);
+        self.processed_entries.drain(..count);
+        count
+    }
+
+    /// Removes the fields that are set to an empty string
+    /// and the freshness lifetime once it has expired.
+    fn drop_empty_fields(&mut self) {
+        for field in [
+            &mut self.name,
+            &mut self.group,
+            &mut self.account,
+            &mut self.title,
+            &mut self.user_agent,
+            &mut self.last_modified,
+            &mut self.last_e_tag,
+        ] {
+            if field.as_deref() == Some("") {
+                *field = None;
+            }
+        }
+        if self
+            .fresh_until
+            .is_some_and(|fresh_until| fresh_until < Utc::now())
+        {
+            self.fresh_until = None;
+        }
+    }
+
+    /// Keeps an entry for a later sync:
+    /// in `pending_entries` if it already failed to be sent to Pocket,
+    /// so that it's still reported as failing,
+    /// or in `deferred_entries` otherwise.
+    fn defer_entry(&mut self, entry_url: String, failed: bool) {
+        let entries = if failed {
+            &mut self.pending_entries
+        } else {
+            &mut self.deferred_entries
+        };
+        if !entries.contains(&entry_url) {
+            entries.push(entry_url);
+        }
+    }
+
+    fn mark_processed(&mut self, entry_url: &str, status: EntryStatus) {
+        let key = self.entry_key(entry_url);
+        self.processed_entries.push(ProcessedEntry {
+            url: match self.processed_entries_format {
+                ProcessedEntriesFormat::Plain => key,
+                ProcessedEntriesFormat::Hashed => entry_hash(&key),
+            },
+            status: Some(status),
+            timestamp: Some(Utc::now().trunc_subsecs(0)),
+        });
+    }
+
+    /// Updates the status of a processed entry.
+    fn set_status(&mut self, entry_url: &str, status: EntryStatus) {
+        let processed = self.processed_keys();
+        let key = processed.key(entry_url);
+        if let Some(entry) = self
+            .processed_entries
+            .iter_mut()
+            .rfind(|entry| processed.stored_key(&entry.url) == key)
+        {
+            entry.status = Some(status);
+            entry.timestamp = Some(Utc::now().trunc_subsecs(0));
+        }
+    }
+
+    /// Updates the status of an entry that was in the push queue,
+    /// and of the URL that was sent if it was also marked as processed
+    /// (e.g. a canonical URL).
+    fn set_queued_status(&mut self, queued_push: &QueuedPush, status: EntryStatus) {
+        self.set_status(queued_push.entry_url(), status);
+        if queued_push.entry_url.is_some() {
+            self.set_status(&queued_push.url, status);
+        }
+    }
+
+    /// Replaces the processed entries that are URLs with their hashes
+    /// if the feed stores hashes.
+    /// Returns the number of entries that were replaced.
+    fn hash_processed_entries(&mut self) -> usize {
+        if self.processed_entries_format != ProcessedEntriesFormat::Hashed {
+            return 0;
+        }
+
+        let mut count = 0;
+        let mut seen = HashSet::new();
+        let entries = mem::take(&mut self.processed_entries);
+        for mut entry in entries {
+            if !is_entry_hash(&entry.url) {
+                count += 1;
+                entry.url = entry_hash(&self.entry_key(&entry.url));
+            }
+            // Variants of the same URL have the same hash.
+            if seen.insert(entry.url.clone()) {
+                self.processed_entries.push(entry);
+            }
+        }
+        count
+    }
+
+    /// Removes the duplicate processed entries and queued pushes,
+    /// keeping their first occurrence,
+    /// and the pending entries that are already queued
+    /// (unless some of the feed's accounts still have to receive them).
+    /// Returns the number of entries that were removed.
+    fn remove_duplicates(&mut self) -> usize {
+        let count =
+            self.processed_entries.len() + self.push_queue.len() + self.pending_entries.len();
+        let mut seen = HashSet::new();
+        self.processed_entries
+            .retain(|entry| seen.insert(entry.url.clone()));
+        let mut queued = HashSet::new();
+        self.push_queue.retain(|queued_push| {
+            queued.insert((
+                queued_push.entry_url().to_owned(),
+                queued_push.account.clone(),
+            ))
+        });
+        let queued: HashSet<String> = queued.into_iter().map(|(url, _)| url).collect();
+        let partial_pushes = &self.partial_pushes;
+        self.pending_entries.retain(|entry_url| {
+            !queued.contains(entry_url) || partial_pushes.contains_key(entry_url)
+        });
+        count - self.processed_entries.len() - self.push_queue.len() - self.pending_entries.len()
+    }
+
+    /// Merges state exported from another configuration file into this feed.
+    ///
+    /// The processed entries are combined
+    /// and the validators from the most recent fetch are kept.
+    /// Merging the same state multiple times has no further effect.
+    fn merge_state(&mut self, state: FeedState) {
+        for entry in state.processed_entries {
+            if !self.processed_entries.iter().any(|x| x.url == entry.url) {
+                self.processed_entries.push(entry);
+            }
+        }
+        self.pending_entries
+            .retain(|entry_url| !self.processed_entries.iter().any(|x| x.url == *entry_url));
+        self.deferred_entries
+            .retain(|entry_url| !self.processed_entries.iter().any(|x| x.url == *entry_url));
+
+        if state.last_fetched > self.last_fetched {
+            self.last_modified = state.last_modified;
+            self.last_e_tag = state.last_e_tag;
+            self.last_fetched = state.last_fetched;
+        }
+    }
+}
+
+/// An entry that was sent to Pocket or skipped.
+///
+/// Entries processed by older versions only have a URL (or its hash),
+/// and are stored as a plain string.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(from = "StoredProcessedEntry", into = "StoredProcessedEntry")]
+struct ProcessedEntry {
+    /// The entry's URL in the form stored by the feed
+    /// (see `processed_entries_format`).
+    url: String,
+    status: Option<EntryStatus>,
+    /// When the entry was processed.
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl From<String> for ProcessedEntry {
+    fn from(url: String) -> ProcessedEntry {
+        ProcessedEntry {
+            url,
+            status: None,
+            timestamp: None,
+        }
+    }
+}
+
+impl From<&str> for ProcessedEntry {
+    fn from(url: &str) -> ProcessedEntry {
+        ProcessedEntry::from(String::from(url))
+    }
+}
+
+impl PartialEq<&str> for ProcessedEntry {
+    fn eq(&self, other: &&str) -> bool {
+        self.url == *other
+    }
+}
+
+impl PartialEq<String> for ProcessedEntry {
+    fn eq(&self, other: &String) -> bool {
+        self.url == *other
+    }
+}
+
+/// How a processed entry is stored in the configuration file.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum StoredProcessedEntry {
+    Url(String),
+    Entry {
+        url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        status: Option<EntryStatus>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        timestamp: Option<DateTime<Utc>>,
+    },
+}
+
+impl From<StoredProcessedEntry> for ProcessedEntry {
+    fn from(entry: StoredProcessedEntry) -> ProcessedEntry {
+        match entry {
+            StoredProcessedEntry::Url(url) => ProcessedEntry::from(url),
+            StoredProcessedEntry::Entry {
+                url,
+                status,
+                timestamp,
+            } => ProcessedEntry {
+                url,
+                status,
+                timestamp,
+            },
+        }
+    }
+}
+
+impl From<ProcessedEntry> for StoredProcessedEntry {
+    fn from(entry: ProcessedEntry) -> StoredProcessedEntry {
+        match entry {
+            ProcessedEntry {
+                url,
+                status: None,
+                timestamp: None,
+            } => StoredProcessedEntry::Url(url),
+            ProcessedEntry {
+                url,
+                status,
+                timestamp,
+            } => StoredProcessedEntry::Entry {
+                url,
+                status,
+                timestamp,
+            },
+        }
+    }
+}
+
+/// Why an entry was marked as processed.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryStatus {
+    /// The entry was sent to Pocket.
+    Pushed,
+    /// The entry was marked as processed without being sent to Pocket
+    /// (e.g. with `mark-read`).
+    MarkedRead,
+    /// The entry's URL is invalid.
+    SkippedInvalid,
+    /// Pocket rejected the entry's URL.
+    RejectedByPocket,
+    /// The entry's link is dead (with `verify_links`).
+    DeadLink,
+    /// The feed's `filter_command` skipped the entry.
+    Filtered,
+    /// The entry is in the push queue, waiting for Pocket to be reachable.
+    Queued,
+    /// The entry's canonical URL was already processed (with `resolve_canonical`).
+    Duplicate,
+    /// The entry's URL is too long for Pocket (see `max_url_length`).
+    TooLong,
+}
+
+impl EntryStatus {
+    fn description(self) -> &'static str {
+        match self {
+            EntryStatus::Pushed => "pushed",
+            EntryStatus::MarkedRead => "marked as read",
+            EntryStatus::SkippedInvalid => "skipped (invalid URL)",
+            EntryStatus::RejectedByPocket => "rejected by Pocket",
+            EntryStatus::DeadLink => "skipped (dead link)",
+            EntryStatus::Filtered => "skipped (filter command)",
+            EntryStatus::Queued => "queued",
+            EntryStatus::Duplicate => "skipped (duplicate)",
+            EntryStatus::TooLong => "skipped (URL too long)",
+        }
+    }
+}
+
+/// An entry waiting to be sent to Pocket once it's reachable again.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct QueuedPush {
+    /// The URL to send to Pocket.
+    url: String,
+    /// The entry's URL as it appears in the feed,
+    /// if it's not `url` (e.g. for relative, canonical or shortened URLs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<String>,
+    /// The number of syncs that failed to send the entry.
+    #[serde(skip_serializing_if = "is_zero_u32")]
+    #[serde(default)]
+    attempts: u32,
+    /// The account to send the entry to,
+    /// if it's not the feed's account (see `also_push_to`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<String>,
+}
+
+impl QueuedPush {
+    /// Returns the entry's URL as it's recorded in `processed_entries`.
+    fn entry_url(&self) -> &str {
+        self.entry_url.as_deref().unwrap_or(&self.url)
+    }
+}
+
+/// The state of a feed that can be moved between configuration files.
+#[derive(Deserialize, Serialize)]
+struct FeedState {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    processed_entries: Vec<ProcessedEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_e_tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_fetched: Option<DateTime<Utc>>,
+}
+
+impl FeedState {
+    fn of(feed: &FeedConfiguration) -> FeedState {
+        FeedState {
+            processed_entries: feed.processed_entries.clone(),
+            last_modified: feed.last_modified.clone(),
+            last_e_tag: feed.last_e_tag.clone(),
+            last_fetched: feed.last_fetched,
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_zero_u32(value: &u32) -> bool {
+    *value == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{Datelike, Local};
+
+    use crate::fetch::{enter_test_runtime, FetchDetails};
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::pocket::{AddTarget, PocketResult};
+
+    fn two_feeds() -> Configuration {
+        Configuration {
+            feeds: vec![atom_feed(&["https://example.com/posts/1"]), atom_feed(&[])],
+            ..Configuration::default()
+        }
+    }
+
+    #[test]
+    fn save_config_replaces_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("feeds-to-pocket-save-{}.yaml", process::id()));
+        fs::write(&path, "feeds: []\n").unwrap();
+        let config = two_feeds();
+        save_config(&config, &path).unwrap();
+
+        let saved: Configuration =
+            serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.feeds.len(), 2);
+        assert_eq!(saved.feeds[0].processed_entries.len(), 1);
+        for extension in ["yaml.new", "yaml.old"] {
+            assert!(!path.with_extension(extension).exists());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_config_normalizes_tags() {
+        let path =
+            std::env::temp_dir().join(format!("feeds-to-pocket-tags-{}.yaml", process::id()));
+        fs::write(
+            &path,
+            "tag_all: ' work,'\n\
+             feeds:\n\
+             - url: https://example.com/atom.xml\n  \
+               tags: 'rust, programming ,,rust'\n\
+             - url: https://example.com/rss.xml\n  \
+               tags: ' , '\n",
+        )
+        .unwrap();
+        let config = load_config(&path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.tag_all.as_deref(), Some("work"));
+        assert_eq!(config.feeds[0].tags, "rust,programming");
+        assert_eq!(config.feeds[1].tags, "");
+    }
+
+    #[test]
+    fn load_config_shows_where_errors_are() {
+        let path =
+            std::env::temp_dir().join(format!("feeds-to-pocket-invalid-{}.yaml", process::id()));
+        fs::write(
+            &path,
+            "feeds:\n\
+             - url: https://example.com/atom.xml\n  \
+               tags: [rust, programming]\n",
+        )
+        .unwrap();
+        for strict in [false, true] {
+            let error = load_config(&path, strict).err().unwrap().to_string();
+            assert!(error.contains("at line 3 column 9"), "{}", error);
+            assert!(error.ends_with(
+                "  1 | feeds:\n  \
+                 2 | - url: https://example.com/atom.xml\n  \
+                 3 |   tags: [rust, programming]\n    \
+                 |         ^"
+            ));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    /// Creates an empty directory for a test's configuration files.
+    fn include_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "feeds-to-pocket-include-{}-{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("feeds")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn config_includes_are_saved_back_to_their_files() {
+        let dir = include_dir("save");
+        let main = dir.join("feeds-to-pocket.yaml");
+        fs::write(
+            &main,
+            "consumer_key: 1234-abcd\n\
+             include:\n\
+             - feeds/news.yaml\n\
+             feeds:\n\
+             - url: https://example.com/main.xml\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("feeds/news.yaml"),
+            "include: [podcasts.yaml]\n\
+             feeds:\n\
+             - url: https://example.com/news.xml\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("feeds/podcasts.yaml"),
+            "feeds:\n- url: https://example.com/podcast.xml\n",
+        )
+        .unwrap();
+
+        let mut config = load_config(&main, true).unwrap();
+        let feeds: Vec<_> = config
+            .feeds
+            .iter()
+            .map(|feed| (feed.url.as_str(), feed.source_file))
+            .collect();
+        assert_eq!(
+            feeds,
+            [
+                ("https://example.com/main.xml", None),
+                ("https://example.com/news.xml", Some(0)),
+                ("https://example.com/podcast.xml", Some(1)),
+            ]
+        );
+
+        config.feeds[2].mark_processed("https://example.com/episode/1", EntryStatus::Pushed);
+        config.feeds.push(FeedConfiguration {
+            url: "https://example.com/new.xml".into(),
+            ..FeedConfiguration::default()
+        });
+        save_config(&config, &main).unwrap();
+
+        let main_file = fs::read_to_string(&main).unwrap();
+        assert!(main_file.contains("include:\n- feeds/news.yaml\n"));
+        assert!(main_file.contains("https://example.com/new.xml"));
+        assert!(!main_file.contains("https://example.com/podcast.xml"));
+        assert_eq!(
+            fs::read_to_string(dir.join("feeds/news.yaml")).unwrap(),
+            "include:\n- podcasts.yaml\nfeeds:\n- url: https://example.com/news.xml\n"
+        );
+        let podcasts = fs::read_to_string(dir.join("feeds/podcasts.yaml")).unwrap();
+        assert!(podcasts.contains("https://example.com/episode/1"));
+
+        // The new feed comes after the other feeds of the main file.
+        let config = load_config(&main, true).unwrap();
+        assert_eq!(config.feeds[1].url, "https://example.com/new.xml");
+        assert_eq!(config.feeds[3].processed_entries.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_include_cycles_are_rejected() {
+        let dir = include_dir("cycle");
+        let main = dir.join("feeds-to-pocket.yaml");
+        fs::write(&main, "include: [feeds/a.yaml]\n").unwrap();
+        fs::write(dir.join("feeds/a.yaml"), "include: [b.yaml]\n").unwrap();
+        fs::write(
+            dir.join("feeds/b.yaml"),
+            "include: [../feeds-to-pocket.yaml]\n",
+        )
+        .unwrap();
+
+        let error = load_config(&main, false).err().unwrap().to_string();
+        assert!(
+            error.contains("feeds-to-pocket.yaml includes itself"),
+            "{}",
+            error
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_includes_reject_duplicate_feeds() {
+        let dir = include_dir("duplicate");
+        let main = dir.join("feeds-to-pocket.yaml");
+        fs::write(
+            &main,
+            "include: [feeds/a.yaml]\nfeeds:\n- url: https://example.com/atom.xml\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("feeds/a.yaml"),
+            "feeds:\n- url: https://example.com/atom.xml\n",
+        )
+        .unwrap();
+
+        let error = load_config(&main, false).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Config);
+        assert!(error
+            .to_string()
+            .contains("feed https://example.com/atom.xml is in both"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_saved_config_detects_truncated_files() {
+        let path =
+            std::env::temp_dir().join(format!("feeds-to-pocket-check-{}.yaml", process::id()));
+        let mut files = config_files(&two_feeds(), &path).unwrap();
+        let file = files.pop().unwrap();
+        let yaml = file.contents.clone();
+        fs::write(&path, &yaml).unwrap();
+        check_saved_config(&file, &path).unwrap();
+
+        // Cut after the first feed: still valid YAML, but a feed is missing.
+        let second_feed = yaml.rfind("- url:").unwrap();
+        fs::write(&path, &yaml[..second_feed]).unwrap();
+        let error = check_saved_config(&file, &path).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("expected 2 feeds, but the file has 1"));
+
+        // Cut in the middle of a value.
+        fs::write(&path, &yaml[..yaml.find("url:").unwrap() + 3]).unwrap();
+        assert!(check_saved_config(&file, &path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fetch_summary_lists_details() {
+        let feed_response = FeedResponse::Success {
+            body: String::new(),
+            content_type: None,
+            last_modified: Some("Mon, 01 Jan 2024 00:00:00 GMT".into()),
+            e_tag: Some("\"abc\"".into()),
+            fresh_until: None,
+            details: FetchDetails {
+                final_url: Some("https://example.com/atom.xml".into()),
+                status: Some(reqwest::StatusCode::OK),
+                size: 1234,
+                elapsed: Duration::from_millis(56),
+            },
+        };
+        assert_eq!(
+            fetch_summary(&feed_response),
+            "  status: 200 OK\n  \
+             final URL: https://example.com/atom.xml\n  \
+             Last-Modified: Mon, 01 Jan 2024 00:00:00 GMT\n  \
+             ETag: \"abc\"\n  \
+             1234 bytes in 56 ms"
+        );
+
+        let feed_response = FeedResponse::NotModified {
+            fresh_until: None,
+            e_tag: None,
+            details: FetchDetails {
+                final_url: None,
+                status: None,
+                size: 0,
+                elapsed: Duration::from_millis(3),
+            },
+        };
+        assert_eq!(fetch_summary(&feed_response), "  0 bytes in 3 ms");
+    }
+
+    /// The URL and title of an entry sent to Pocket.
+    type Added = (String, Option<String>);
+
+    /// Records the URLs that would be sent to Pocket.
+    #[derive(Clone, Default)]
+    struct MockPocket {
+        added: Arc<Mutex<Vec<Added>>>,
+        /// Fail every request, without recording it.
+        failing: Arc<AtomicBool>,
+    }
+
+    impl MockPocket {
+        fn set_failing(&self, failing: bool) {
+            self.failing.store(failing, Ordering::SeqCst);
+        }
+
+        fn added(&self) -> Vec<String> {
+            let added = self.added.lock().unwrap();
+            added.iter().map(|(url, _)| url.clone()).collect()
+        }
+
+        fn titles(&self) -> Vec<Option<String>> {
+            let added = self.added.lock().unwrap();
+            added.iter().map(|(_, title)| title.clone()).collect()
+        }
+    }
+
+    impl AddTarget for MockPocket {
+        fn add(&mut self, url: &Url, title: Option<&str>, _tags: Option<&str>) -> PocketResult<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(PocketError::Io(io::Error::other("mock failure")));
+            }
+            let mut added = self.added.lock().unwrap();
+            added.push((url.to_string(), title.map(String::from)));
+            Ok(())
+        }
+    }
+
+    fn atom_feed(processed_entries: &[&str]) -> FeedConfiguration {
+        FeedConfiguration {
+            url: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/atom.xml").into(),
+            processed_entries: processed_entries.iter().map(|&x| x.into()).collect(),
+            ..FeedConfiguration::default()
+        }
+    }
+
+    #[test]
+    fn moved_url_ignores_equivalent_urls() {
+        let parsed_feed = fs::read_to_string(atom_feed(&[]).url)
+            .unwrap()
+            .replace(
+                "https://example.com/atom.xml",
+                "https://example.com/atom.xml?b=2&a=1",
+            )
+            .parse::<Feed>()
+            .unwrap();
+        let feed = |url: &str| FeedConfiguration {
+            url: url.into(),
+            ..FeedConfiguration::default()
+        };
+
+        assert_eq!(
+            moved_url(
+                &feed("HTTPS://Example.COM:443/atom.xml?a=1&b=2"),
+                &parsed_feed
+            ),
+            None
+        );
+        assert_eq!(
+            moved_url(&feed("https://example.com/atom.xml?a=2&b=1"), &parsed_feed).as_deref(),
+            Some("https://example.com/atom.xml?b=2&a=1")
+        );
+        assert_eq!(
+            moved_url(&feed("http://mirror.example.net/atom.xml"), &parsed_feed).as_deref(),
+            Some("https://example.com/atom.xml?b=2&a=1")
+        );
+        // Local feeds aren't published at their self link.
+        assert_eq!(moved_url(&atom_feed(&[]), &parsed_feed), None);
+    }
+
+    #[test]
+    fn retry_retries_transient_errors() {
+        let locked = || io::Error::from(io::ErrorKind::WouldBlock);
+        let is_transient = |error: &io::Error| error.kind() == io::ErrorKind::WouldBlock;
+
+        // Succeeds on the third attempt.
+        let mut calls = 0;
+        let result = retry(5, Duration::ZERO, is_transient, || {
+            calls += 1;
+            if calls < 3 {
+                Err(locked())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Gives up after the last attempt.
+        let mut calls = 0;
+        let result: io::Result<()> = retry(4, Duration::ZERO, is_transient, || {
+            calls += 1;
+            Err(locked())
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(calls, 4);
+
+        // Other errors aren't retried.
+        let mut calls = 0;
+        let result: io::Result<()> = retry(4, Duration::ZERO, is_transient, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn parse_budgets() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("120s"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert!(parse_duration("2d").is_err());
+        assert!(parse_duration("s").is_err());
+
+        assert_eq!(parse_size("1000"), Ok(1000));
+        assert_eq!(parse_size("512KB"), Ok(512 * 1024));
+        assert_eq!(parse_size("20MB"), Ok(20 * 1024 * 1024));
+        assert_eq!(parse_size("1 GiB"), Ok(1024 * 1024 * 1024));
+        assert!(parse_size("20 bananas").is_err());
+    }
+
+    #[test]
+    fn sync_order_starts_with_least_recently_synced() {
+        let feed = |last_synced: Option<i64>| FeedConfiguration {
+            last_synced: last_synced
+                .map(|timestamp| DateTime::from_timestamp(timestamp, 0).unwrap()),
+            ..FeedConfiguration::default()
+        };
+        let mut feeds = vec![feed(Some(2)), feed(Some(1)), feed(None), feed(Some(1))];
+        assert_eq!(sync_order(&feeds), [2, 1, 3, 0]);
+
+        for feed in &mut feeds {
+            feed.last_synced = None;
+        }
+        assert_eq!(sync_order(&feeds), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn canonical_entry_urls() {
+        for url in [
+            "https://example.com/posts/1",
+            "http://example.com/posts/1",
+            "https://EXAMPLE.com:443/posts/1",
+            "http://example.com:80/posts/1/",
+            "https://example.com/posts/1#comments",
+        ] {
+            assert_eq!(canonical_entry_url(url), "https://example.com/posts/1");
+        }
+        assert_eq!(
+            canonical_entry_url("http://example.com:8080/"),
+            "https://example.com:8080/"
+        );
+        assert_eq!(canonical_entry_url("not a URL"), "not a URL");
+    }
+
+    #[test]
+    fn new_entries_ignores_idn_variants() {
+        // The feed uses Unicode host names and paths,
+        // while the processed entries are stored in their serialized form.
+        let mut feed = FeedConfiguration {
+            url: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/idn.xml").into(),
+            processed_entries: vec![
+                "https://xn--d1acpjx3f.xn--p1ai/%D0%BD%D0%BE%D0%B2%D0%BE%D1%81%D1%82%D0%B8/1"
+                    .into(),
+            ],
+            ..FeedConfiguration::default()
+        };
+        let parsed_feed = fs::read_to_string(&feed.url)
+            .unwrap()
+            .parse::<Feed>()
+            .unwrap();
+        for exact_entry_urls in [false, true] {
+            feed.exact_entry_urls = exact_entry_urls;
+            let new_entries = new_entries(&feed, &parsed_feed);
+            assert_eq!(new_entries.len(), 1);
+            assert_eq!(
+                serialized_url(new_entries[0].url),
+                serialized_url("https://яндекс.рф/новости/2")
+            );
+        }
+
+        feed.mark_processed("https://яндекс.рф/новости/2", EntryStatus::Pushed);
+        assert_eq!(
+            feed.processed_entries[1],
+            "https://xn--d1acpjx3f.xn--p1ai/%D0%BD%D0%BE%D0%B2%D0%BE%D1%81%D1%82%D0%B8/2"
+        );
+        assert!(new_entries(&feed, &parsed_feed).is_empty());
+
+        let feed = FeedConfiguration {
+            url: "https://яндекс.рф/лента.xml".into(),
+            ..FeedConfiguration::default()
+        };
+        assert!(feed
+            .is_identified_by("https://xn--d1acpjx3f.xn--p1ai/%D0%BB%D0%B5%D0%BD%D1%82%D0%B0.xml"));
+    }
+
+    #[test]
+    fn new_entries_ignores_url_variants() {
+        let mut feed = atom_feed(&[
+            "http://example.com/posts/1/",
+            "https://example.com/posts/2#top",
+        ]);
+        let parsed_feed = fs::read_to_string(&feed.url)
+            .unwrap()
+            .parse::<Feed>()
+            .unwrap();
+        let urls = |feed: &FeedConfiguration| -> Vec<String> {
+            new_entries(feed, &parsed_feed)
+                .into_iter()
+                .map(|entry| entry.url.to_owned())
+                .collect()
+        };
+        assert_eq!(urls(&feed), ["https://example.com/posts/3"]);
+
+        feed.exact_entry_urls = true;
+        assert_eq!(
+            urls(&feed),
+            [
+                "https://example.com/posts/1",
+                "https://example.com/posts/2",
+                "https://example.com/posts/3"
+            ]
+        );
+    }
+
+    #[test]
+    fn new_entries_handles_large_feeds() {
+        let mut rss = String::from("<rss version=\"2.0\"><channel><title>Firehose</title>");
+        for i in (0..2_000).rev() {
+            rss.push_str(&format!(
+                "<item><link>https://example.com/items/{0}</link><guid>{0}</guid></item>",
+                i
+            ));
+        }
+        rss.push_str("</channel></rss>");
+        let parsed_feed = rss.parse::<Feed>().unwrap();
+
+        // Every entry but the last 3 was processed, among many older entries.
+        let feed = FeedConfiguration {
+            url: "https://example.com/firehose.xml".into(),
+            processed_entries: (-100_000..1_997)
+                .map(|i| format!("https://example.com/items/{}", i).into())
+                .collect(),
+            ..FeedConfiguration::default()
+        };
+        let start = Instant::now();
+        let urls: Vec<&str> = new_entries(&feed, &parsed_feed)
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/items/1997",
+                "https://example.com/items/1998",
+                "https://example.com/items/1999"
+            ]
+        );
+        // Scanning the processed entries for every entry took minutes.
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn hashed_processed_entries() {
+        let mut feed = atom_feed(&["http://example.com/posts/1/"]);
+        feed.processed_entries_format = ProcessedEntriesFormat::Hashed;
+        feed.mark_processed("https://example.com/posts/2", EntryStatus::Pushed);
+        assert_eq!(feed.processed_entries[1].url.len(), ENTRY_HASH_LENGTH);
+        assert!(is_entry_hash(&feed.processed_entries[1].url));
+
+        // Plain URLs and hashes are both recognized.
+        let parsed_feed = fs::read_to_string(&feed.url)
+            .unwrap()
+            .parse::<Feed>()
+            .unwrap();
+        let urls = |feed: &FeedConfiguration| -> Vec<String> {
+            new_entries(feed, &parsed_feed)
+                .into_iter()
+                .map(|entry| entry.url.to_owned())
+                .collect()
+        };
+        assert_eq!(urls(&feed), ["https://example.com/posts/3"]);
+
+        // Compacting replaces the URL with its hash.
+        let mut config = Configuration {
+            feeds: vec![feed],
+            ..Configuration::default()
+        };
+        compact_state(
+            &mut config,
+            &CompactStateCommand {
+                fetch: false,
+                dry_run: false,
+            },
+        )
+        .unwrap();
+        let feed = &config.feeds[0];
+        assert_eq!(
+            feed.processed_entries,
+            [
+                entry_hash("https://example.com/posts/1"),
+                entry_hash("https://example.com/posts/2")
+            ]
+        );
+        assert_eq!(urls(feed), ["https://example.com/posts/3"]);
+
+        // Feeds that store URLs are left alone.
+        let mut feed = atom_feed(&["https://example.com/posts/1"]);
+        assert_eq!(feed.hash_processed_entries(), 0);
+        assert_eq!(feed.processed_entries, ["https://example.com/posts/1"]);
+    }
+
+    #[test]
+    fn compact_state_removes_old_entries_and_empty_fields() {
+        let mut feed = atom_feed(&[
+            "https://example.com/posts/1",
+            "https://example.com/posts/2",
+            "https://example.com/gone",
+            "https://example.com/posts/3",
+        ]);
+        feed.title = Some(String::new());
+        feed.last_e_tag = Some("\"abc\"".into());
+        feed.fresh_until = Some(Utc::now() - chrono::TimeDelta::hours(1));
+        let mut config = Configuration {
+            max_processed_entries: Some(2),
+            feeds: vec![feed],
+            ..Configuration::default()
+        };
+        let _runtime = enter_test_runtime();
+        compact_state(
+            &mut config,
+            &CompactStateCommand {
+                fetch: true,
+                dry_run: false,
+            },
+        )
+        .unwrap();
+
+        let feed = &config.feeds[0];
+        assert_eq!(
+            feed.processed_entries,
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert_eq!(feed.title, None);
+        assert_eq!(feed.last_e_tag.as_deref(), Some("\"abc\""));
+        assert_eq!(feed.fresh_until, None);
+    }
+
+    #[test]
+    fn process_feed_pushes_new_entries_oldest_first() {
+        let mut feed = atom_feed(&["https://example.com/posts/1"]);
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let push_errors = process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+        assert!(push_errors.is_empty());
+        assert_eq!(
+            pocket.added(),
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert_eq!(feed.processed_entries.len(), 3);
+        assert!(feed.last_new_entry.is_some());
+        assert_eq!(feed.entries_pushed_total, 2);
+        assert!(feed.last_push_date.is_some());
+        assert_eq!(
+            feed.newest_entry_published,
+            Some("2024-01-03T00:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn process_feed_checks_validators_after_many_not_modified_responses() {
+        let server = MockServer::start(vec![
+            MockResponse::new("304 Not Modified", ""),
+            MockResponse::new("200 OK", include_str!("../tests/fixtures/atom.xml"))
+                .header("ETag", "\"stale\""),
+        ]);
+        let not_modified_since = Utc::now() - chrono::TimeDelta::days(8);
+        let mut feed = FeedConfiguration {
+            url: server.url("/atom.xml"),
+            last_e_tag: Some("\"stale\"".into()),
+            max_not_modified_age_days: Some(7),
+            not_modified_since: Some(not_modified_since),
+            ..atom_feed(&["https://example.com/posts/1"])
+        };
+        assert!(!feed.not_modified_for_too_long(not_modified_since + chrono::TimeDelta::days(7)));
+        assert!(feed.not_modified_for_too_long(Utc::now()));
+
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let config = Configuration {
+            host_delay_ms: Some(0),
+            ..Configuration::default()
+        };
+        let mut fetcher = Fetcher::new(&config).unwrap();
+        process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(server.request().header("If-None-Match"), Some("\"stale\""));
+        assert_eq!(server.request().header("If-None-Match"), None);
+        assert_eq!(
+            pocket.added(),
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert_eq!(feed.not_modified_since, None);
+    }
+
+    #[test]
+    fn process_feed_detects_republished_entries() {
+        // The site changed its URL scheme:
+        // every entry looks new, but they're older than the newest processed entry.
+        let newest_entry_published = Some("2024-01-04T00:00:00Z".parse().unwrap());
+        let mut feed = FeedConfiguration {
+            pending_entries: vec!["https://example.com/?p=5".into()],
+            newest_entry_published,
+            ..atom_feed(&["https://example.com/?p=4"])
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let error = process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("--accept-republished"));
+        assert!(pocket.added().is_empty());
+        assert_eq!(feed.processed_entries, ["https://example.com/?p=4"]);
+        assert_eq!(feed.pending_entries, ["https://example.com/?p=5"]);
+
+        process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits {
+                accept_republished: true,
+                ..PushLimits::default()
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(pocket.added().len(), 4);
+        assert_eq!(feed.newest_entry_published, newest_entry_published);
+    }
+
+    #[test]
+    fn process_feed_asks_before_large_pushes() {
+        let mut feed = atom_feed(&[]);
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let mut sync = |feed: &mut FeedConfiguration, confirmation: &mut PushConfirmation| {
+            let limits = PushLimits {
+                confirmation: Some(confirmation),
+                ..PushLimits::default()
+            };
+            process_feed(
+                feed,
+                &[(DEFAULT_ACCOUNT, &pool)],
+                &mut fetcher,
+                Duration::ZERO,
+                limits,
+                None,
+            )
+            .unwrap();
+        };
+
+        // Declining leaves the entries for the next sync.
+        let mut confirmation = PushConfirmation::new(2, Box::new(io::Cursor::new("show\nn\n")));
+        sync(&mut feed, &mut confirmation);
+        assert!(pocket.added().is_empty());
+        assert!(feed.processed_entries.is_empty());
+        assert_eq!(confirmation.answer, Some(false));
+
+        let mut confirmation = PushConfirmation::new(2, Box::new(io::Cursor::new("y\n")));
+        sync(&mut feed, &mut confirmation);
+        assert_eq!(pocket.added().len(), 3);
+        assert_eq!(confirmation.count, 3);
+    }
+
+    #[test]
+    fn process_feed_pushes_to_every_target() {
+        let mut feed = FeedConfiguration {
+            also_push_to: vec!["work".into()],
+            ..atom_feed(&["https://example.com/posts/1"])
+        };
+        assert_eq!(feed.account_names(), [DEFAULT_ACCOUNT, "work"]);
+        let personal = MockPocket::default();
+        let work = MockPocket::default();
+        let personal_pool = PushPool::new(personal.clone(), 1, None, None);
+        let work_pool = PushPool::new(work.clone(), 1, None, None);
+        let targets = [(DEFAULT_ACCOUNT, &personal_pool), ("work", &work_pool)];
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let mut sync = |feed: &mut FeedConfiguration| {
+            process_feed(
+                feed,
+                &targets,
+                &mut fetcher,
+                Duration::ZERO,
+                PushLimits::default(),
+                None,
+            )
+            .unwrap()
+        };
+
+        // The entries stay pending until every target has received them.
+        work.set_failing(true);
+        assert_eq!(sync(&mut feed).len(), 2);
+        assert_eq!(feed.processed_entries.len(), 1);
+        assert_eq!(
+            feed.pending_entries,
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert_eq!(
+            feed.partial_pushes["https://example.com/posts/2"],
+            [DEFAULT_ACCOUNT]
+        );
+
+        // Retrying only sends them to the targets that don't have them yet.
+        work.set_failing(false);
+        assert!(sync(&mut feed).is_empty());
+        assert_eq!(
+            personal.added(),
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert_eq!(work.added(), personal.added());
+        assert_eq!(feed.processed_entries.len(), 3);
+        assert_eq!(feed.entries_pushed_total, 2);
+        assert!(feed.pending_entries.is_empty());
+        assert!(feed.partial_pushes.is_empty());
+    }
+
+    #[test]
+    fn process_feed_updates_validators_by_policy() {
+        let sync = |validator_update, body| {
+            let server = MockServer::start(vec![
+                MockResponse::new("200 OK", body).header("ETag", "\"new\"")
+            ]);
+            let mut feed = FeedConfiguration {
+                url: server.url("/atom.xml"),
+                last_e_tag: Some("\"old\"".into()),
+                validator_update,
+                ..FeedConfiguration::default()
+            };
+            let pocket = MockPocket::default();
+            pocket.set_failing(true);
+            let pool = PushPool::new(pocket, 1, None, None);
+            let _runtime = enter_test_runtime();
+            let config = Configuration {
+                fetch_retries: Some(0),
+                ..Configuration::default()
+            };
+            let mut fetcher = Fetcher::new(&config).unwrap();
+            let result = process_feed(
+                &mut feed,
+                &[(DEFAULT_ACCOUNT, &pool)],
+                &mut fetcher,
+                Duration::ZERO,
+                PushLimits::default(),
+                None,
+            );
+            (result.is_ok(), feed.last_e_tag.unwrap())
+        };
+        let atom = include_str!("../tests/fixtures/atom.xml");
+
+        // The pushes fail, but the feed was parsed.
+        assert_eq!(
+            sync(ValidatorUpdate::OnFetchSuccess, atom),
+            (true, "\"new\"".into())
+        );
+        assert_eq!(
+            sync(ValidatorUpdate::Strict, atom),
+            (true, "\"old\"".into())
+        );
+        assert_eq!(
+            sync(ValidatorUpdate::Always, atom),
+            (true, "\"new\"".into())
+        );
+
+        // The feed can't be parsed.
+        let garbage = "<html>not a feed</html>";
+        assert_eq!(
+            sync(ValidatorUpdate::OnFetchSuccess, garbage),
+            (false, "\"old\"".into())
+        );
+        assert_eq!(
+            sync(ValidatorUpdate::Always, garbage),
+            (false, "\"new\"".into())
+        );
+    }
+
+    #[test]
+    fn push_entries_resolves_canonical_urls() {
+        let page = r#"<html><head><link rel="canonical" href="/posts/1"></head></html>"#;
+        let server = MockServer::start(vec![
+            MockResponse::new("200 OK", "").header("Content-Type", "text/html"),
+            MockResponse::new("200 OK", page),
+            // Another URL for the same page.
+            MockResponse::new("200 OK", "").header("Content-Type", "text/html"),
+            MockResponse::new("200 OK", page),
+            // The page can't be downloaded, so its own URL is sent.
+            MockResponse::new("500 Internal Server Error", ""),
+            MockResponse::new("500 Internal Server Error", ""),
+        ]);
+        let mut feed = FeedConfiguration {
+            resolve_canonical: true,
+            ..FeedConfiguration::default()
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let entries = ["/posts/1?utm_source=rss", "/posts/1?from=feed", "/posts/2"]
+            .map(|path| (server.url(path), None));
+        let push_errors = push_entries(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            entries.to_vec(),
+            None,
+            Duration::ZERO,
+            DEFAULT_MAX_URL_LENGTH,
+        );
+
+        assert!(push_errors.is_empty());
+        assert_eq!(
+            pocket.added(),
+            [server.url("/posts/1"), server.url("/posts/2")]
+        );
+        let processed = feed.processed_keys();
+        assert!(processed.contains(&server.url("/posts/1")));
+        for (entry_url, _) in &entries {
+            assert!(processed.contains(entry_url));
+        }
+        // The duplicate is skipped before the others are pushed.
+        assert_eq!(
+            feed.processed_entries[0].status,
+            Some(EntryStatus::Duplicate)
+        );
+        assert_eq!(feed.processed_entries.len(), 4);
+    }
+
+    #[test]
+    fn fit_url_length_shortens_only_when_allowed() {
+        let url = Url::parse("https://arxiv.org/abs/1234?q=abcdefghij#section").unwrap();
+        let length = url.as_str().len();
+        assert_eq!(fit_url_length(&url, length, false), Some(url.clone()));
+        assert_eq!(fit_url_length(&url, length - 1, false), None);
+        assert_eq!(
+            fit_url_length(&url, length - 1, true).unwrap().as_str(),
+            "https://arxiv.org/abs/1234"
+        );
+        assert_eq!(
+            fit_url_length(&url, 26, true).unwrap().as_str(),
+            "https://arxiv.org/abs/1234"
+        );
+        assert_eq!(fit_url_length(&url, 25, true), None);
+    }
+
+    #[test]
+    fn push_entries_skips_urls_too_long_for_pocket() {
+        let long_url = format!("https://example.com/posts/1?q={}", "a".repeat(100));
+        let entries = vec![
+            (long_url.clone(), None),
+            ("https://example.com/posts/2".to_owned(), None),
+        ];
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+
+        let mut feed = FeedConfiguration::default();
+        let push_errors = push_entries(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            entries.clone(),
+            None,
+            Duration::ZERO,
+            100,
+        );
+        assert!(push_errors.is_empty());
+        assert_eq!(pocket.added(), ["https://example.com/posts/2"]);
+        // The entry isn't retried on the next sync.
+        assert_eq!(feed.processed_entries[0].url, long_url);
+        assert_eq!(feed.processed_entries[0].status, Some(EntryStatus::TooLong));
+
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let mut feed = FeedConfiguration {
+            shorten_long_urls: true,
+            ..FeedConfiguration::default()
+        };
+        push_entries(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            entries,
+            None,
+            Duration::ZERO,
+            100,
+        );
+        assert_eq!(
+            pocket.added(),
+            ["https://example.com/posts/1", "https://example.com/posts/2"]
+        );
+        assert!(feed.processed_keys().contains(&long_url));
+    }
+
+    #[test]
+    fn process_feed_runs_the_filter_command() {
+        let script =
+            std::env::temp_dir().join(format!("feeds-to-pocket-filter-sync-{}.sh", process::id()));
+        fs::write(
+            &script,
+            r#"case "$1" in */posts/2) exit 1;; */posts/3) exit 2;; esac"#,
+        )
+        .unwrap();
+        let mut feed = FeedConfiguration {
+            filter_command: Some(format!("sh {}", script.display())),
+            ..atom_feed(&[])
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let push_errors = process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+        fs::remove_file(script).unwrap();
+
+        assert_eq!(push_errors.len(), 1);
+        assert!(push_errors[0]
+            .to_string()
+            .contains("failed on https://example.com/posts/3"));
+        assert_eq!(pocket.added(), ["https://example.com/posts/1"]);
+        let statuses: Vec<_> = feed
+            .processed_entries
+            .iter()
+            .map(|entry| (entry.url.as_str(), entry.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("https://example.com/posts/2", Some(EntryStatus::Filtered)),
+                ("https://example.com/posts/1", Some(EntryStatus::Pushed)),
+            ]
+        );
+        assert_eq!(feed.pending_entries, ["https://example.com/posts/3"]);
+    }
+
+    #[test]
+    fn process_feed_defers_pushes_outside_the_push_window() {
+        let window = |from: i64, to: i64| {
+            let now = Utc::now();
+            let time = |hours| (now + chrono::Duration::hours(hours)).format("%H:%M");
+            format!("{}-{} UTC", time(from), time(to)).parse().unwrap()
+        };
+        let mut feed = FeedConfiguration {
+            push_window: Some(window(1, 2)),
+            ..atom_feed(&["https://example.com/posts/1"])
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let mut sync = |feed: &mut FeedConfiguration| {
+            process_feed(
+                feed,
+                &[(DEFAULT_ACCOUNT, &pool)],
+                &mut fetcher,
+                Duration::ZERO,
+                PushLimits::default(),
+                None,
+            )
+            .unwrap();
+        };
+
+        sync(&mut feed);
+        assert!(pocket.added().is_empty());
+        assert_eq!(feed.processed_entries, ["https://example.com/posts/1"]);
+        assert_eq!(
+            feed.deferred_entries,
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert!(feed.pending_entries.is_empty());
+        assert!(feed.last_modified.is_some());
+
+        // The file hasn't changed, so the entries are sent from `deferred_entries`.
+        feed.push_window = Some(window(-1, 1));
+        sync(&mut feed);
+        assert_eq!(
+            pocket.added(),
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert!(feed.deferred_entries.is_empty());
+    }
+
+    #[test]
+    fn status_doesnt_report_deferred_entries_as_failures() {
+        let config = Configuration {
+            feeds: vec![
+                FeedConfiguration {
+                    deferred_entries: vec!["https://example.com/posts/2".into()],
+                    ..atom_feed(&["https://example.com/posts/1"])
+                },
+                FeedConfiguration {
+                    url: "https://example.com/feed.xml".into(),
+                    pending_entries: vec!["https://example.com/posts/3".into()],
+                    ..FeedConfiguration::default()
+                },
+            ],
+            ..Configuration::default()
+        };
+        let status = Status::of(Path::new("config.yaml"), &config);
+        let failing: Vec<_> = status.failing_feeds.iter().map(|feed| feed.url).collect();
+        assert_eq!(failing, ["https://example.com/feed.xml"]);
+    }
+
+    #[test]
+    fn process_feed_keeps_entries_for_the_weekly_digest() {
+        // The digest was sent today and isn't due before next week.
+        let tomorrow = (Local::now() + chrono::Duration::days(1)).weekday();
+        let last_digest = Some(Utc::now());
+        let mut feed = FeedConfiguration {
+            digest_day: Some(tomorrow.to_string().parse().unwrap()),
+            last_digest,
+            ..atom_feed(&[])
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let mut sync = |feed: &mut FeedConfiguration, flush_digests| {
+            let limits = PushLimits {
+                max_pushes: Some(2),
+                flush_digests,
+                ..PushLimits::default()
+            };
+            process_feed(
+                feed,
+                &[(DEFAULT_ACCOUNT, &pool)],
+                &mut fetcher,
+                Duration::ZERO,
+                limits,
+                None,
+            )
+            .unwrap();
+        };
+
+        sync(&mut feed, false);
+        assert!(pocket.added().is_empty());
+        assert_eq!(feed.deferred_entries.len(), 3);
+        assert!(feed.pending_entries.is_empty());
+        assert_eq!(feed.last_digest, last_digest);
+
+        // The digest is larger than the push limit,
+        // so it's sent over two syncs.
+        sync(&mut feed, true);
+        assert_eq!(pocket.added().len(), 2);
+        assert_eq!(feed.deferred_entries.len(), 1);
+        assert_eq!(feed.last_digest, last_digest);
+
+        sync(&mut feed, true);
+        assert_eq!(pocket.added().len(), 3);
+        assert!(feed.deferred_entries.is_empty());
+        assert!(feed.last_digest > last_digest);
+    }
+
+    #[test]
+    fn push_confirmation_asks_once() {
+        let feed = atom_feed(&[]);
+        let entries = |count| {
+            vec![
+                EntryRef {
+                    url: "https://example.com/posts/1",
+                    title: None,
+                };
+                count
+            ]
+        };
+
+        // Nothing is read below the threshold.
+        let mut confirmation = PushConfirmation::new(3, Box::new(io::empty()));
+        assert!(confirmation.confirm(&feed, &entries(2)));
+        assert!(confirmation.confirm(&feed, &entries(1)));
+        assert_eq!(confirmation.answer, None);
+        // Without an answer, nothing more is sent.
+        assert!(!confirmation.confirm(&feed, &entries(1)));
+        assert!(!confirmation.confirm(&feed, &entries(5)));
+        assert_eq!(confirmation.count, 3);
+
+        let mut confirmation = PushConfirmation::new(
+            0,
+            Box::new(io::Cursor::new(
+                "yes
+",
+            )),
+        );
+        assert!(confirmation.confirm(&feed, &entries(2)));
+        assert!(confirmation.confirm(&feed, &entries(2)));
+        assert_eq!(confirmation.count, 4);
+    }
+
+    #[test]
+    fn process_feed_limits_pushes_per_run() {
+        let mut feed = atom_feed(&[]);
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits {
+                max_pushes: Some(2),
+                ..PushLimits::default()
+            },
+            None,
+        )
+        .unwrap();
+
+        // Only the newest entries are pushed;
+        // the oldest entry is left for the next sync.
+        assert_eq!(
+            pocket.added(),
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert!(!feed
+            .processed_keys()
+            .contains("https://example.com/posts/1"));
+
+        // `mark-read` marks the remaining entry as processed.
+        let mut config = Configuration {
+            feeds: vec![feed],
+            ..Configuration::default()
+        };
+        let feed_url = config.feeds[0].url.clone();
+        mark_read(&mut config, &feed_url).unwrap();
+        assert_eq!(config.feeds[0].processed_entries.len(), 3);
+        assert!(config.feeds[0]
+            .processed_keys()
+            .contains("https://example.com/posts/1"));
+    }
+
+    /// A configuration that downloads feeds from mock servers
+    /// and sends the entries to a mock Pocket.
+    fn sync_config(pocket: &MockServer, feeds: Vec<FeedConfiguration>) -> Configuration {
+        Configuration {
+            default_account: Account {
+                consumer_key: Some("1234-abcd".into()),
+                access_token: Some("token".into()),
+                ..Account::default()
+            },
+            pocket_api_url: Some(pocket.url("/v3/")),
+            host_delay_ms: Some(0),
+            push_workers: Some(1),
+            fetch_retries: Some(0),
+            feeds,
+            ..Configuration::default()
+        }
+    }
+
+    #[test]
+    fn sync_pushes_new_entries_and_updates_state() {
+        let atom = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            include_str!("../tests/fixtures/atom.xml"),
+        )
+        .header("ETag", "\"atom-2\"")]);
+        let rss = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            include_str!("../tests/fixtures/rss.xml"),
+        )
+        .header("Last-Modified", "Wed, 03 Jan 2024 00:00:00 GMT")]);
+        let not_modified = MockServer::start(vec![MockResponse::new("304 Not Modified", "")]);
+        let pocket = MockServer::start(vec![
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+        ]);
+
+        let mut config = sync_config(
+            &pocket,
+            vec![
+                FeedConfiguration {
+                    url: atom.url("/atom.xml"),
+                    processed_entries: vec!["https://example.com/posts/1".into()],
+                    last_e_tag: Some("\"atom-1\"".into()),
+                    ..FeedConfiguration::default()
+                },
+                FeedConfiguration {
+                    url: rss.url("/rss.xml"),
+                    tags: "rss,news".into(),
+                    processed_entries: vec![
+                        "https://example.com/posts/1".into(),
+                        "https://example.com/posts/2".into(),
+                    ],
+                    ..FeedConfiguration::default()
+                },
+                FeedConfiguration {
+                    url: not_modified.url("/feed.xml"),
+                    last_e_tag: Some("\"unchanged\"".into()),
+                    ..FeedConfiguration::default()
+                },
+            ],
+        );
+        let _runtime = enter_test_runtime();
+        let (failures, budget_exceeded) = sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(failures.is_empty());
+        assert!(budget_exceeded.is_none());
+
+        let adds: Vec<(String, String)> = (0..3)
+            .map(|_| {
+                let request = pocket.request();
+                (request.request_line, request.body)
+            })
+            .collect();
+        let add = |body: &str| ("POST /v3/add HTTP/1.1".to_string(), body.to_string());
+        assert_eq!(
+            adds,
+            [
+                add(
+                    r#"{"consumer_key":"1234-abcd","access_token":"token","url":"https://example.com/posts/2","title":"Second post"}"#
+                ),
+                add(
+                    r#"{"consumer_key":"1234-abcd","access_token":"token","url":"https://example.com/posts/3","title":"Third post"}"#
+                ),
+                add(
+                    r#"{"consumer_key":"1234-abcd","access_token":"token","url":"https://example.com/posts/3","title":"Third post","tags":"rss,news"}"#
+                ),
+            ]
+        );
+
+        assert_eq!(atom.request().header("If-None-Match"), Some("\"atom-1\""));
+        assert_eq!(
+            not_modified.request().header("If-None-Match"),
+            Some("\"unchanged\"")
+        );
+
+        let [atom_feed, rss_feed, not_modified_feed] = &config.feeds[..] else {
+            panic!("expected 3 feeds");
+        };
+        assert_eq!(atom_feed.processed_entries.len(), 3);
+        assert_eq!(atom_feed.last_e_tag.as_deref(), Some("\"atom-2\""));
+        assert_eq!(rss_feed.processed_entries.len(), 3);
+        assert_eq!(
+            rss_feed.last_modified.as_deref(),
+            Some("Wed, 03 Jan 2024 00:00:00 GMT")
+        );
+        assert!(not_modified_feed.processed_entries.is_empty());
+        assert_eq!(
+            not_modified_feed.last_e_tag.as_deref(),
+            Some("\"unchanged\"")
+        );
+        assert!(config.feeds.iter().all(|feed| feed.last_synced.is_some()));
+        assert!(config.last_successful_sync.is_some());
+    }
+
+    #[test]
+    fn sync_dry_run_shows_the_changes_as_a_diff() {
+        let atom = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            include_str!("../tests/fixtures/atom.xml"),
+        )
+        .header("ETag", "\"atom-2\"")]);
+        // Pocket isn't contacted.
+        let pocket = MockServer::start(vec![]);
+        let mut config = sync_config(
+            &pocket,
+            vec![FeedConfiguration {
+                url: atom.url("/atom.xml"),
+                processed_entries: vec![
+                    "https://example.com/posts/1".into(),
+                    "https://example.com/posts/2".into(),
+                ],
+                ..FeedConfiguration::default()
+            }],
+        );
+        let path =
+            std::env::temp_dir().join(format!("feeds-to-pocket-dry-run-{}.yaml", process::id()));
+        fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+
+        let _runtime = enter_test_runtime();
+        let args = SyncCommand {
+            dry_run: true,
+            diff: true,
+            ..SyncCommand::default()
+        };
+        let (failures, _) = sync(&mut config, &args).unwrap();
+        assert!(failures.is_empty());
+        let diff = config_diff(&config, &path).unwrap();
+        fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = diff.lines().collect();
+        assert!(lines[0].starts_with("--- "));
+        assert!(lines.contains(&"   - https://example.com/posts/2"));
+        assert!(lines.contains(&"+  - url: https://example.com/posts/3"));
+        assert!(lines.contains(&"+    status: pushed"));
+        assert!(lines.contains(&"+  last_e_tag: '\"atom-2\"'"));
+    }
+
+    #[test]
+    fn sync_offline_only_processes_local_feeds() {
+        // Neither the HTTP feed nor Pocket is contacted.
+        let http = MockServer::start(vec![]);
+        let pocket = MockServer::start(vec![]);
+        let mut config = sync_config(
+            &pocket,
+            vec![
+                atom_feed(&["https://example.com/posts/1"]),
+                FeedConfiguration {
+                    url: http.url("/atom.xml"),
+                    ..FeedConfiguration::default()
+                },
+            ],
+        );
+        let _runtime = enter_test_runtime();
+        let args = SyncCommand {
+            offline: true,
+            ..SyncCommand::default()
+        };
+        let (failures, budget_exceeded) = sync(&mut config, &args).unwrap();
+        assert!(failures.is_empty());
+        assert!(budget_exceeded.is_none());
+
+        let [local_feed, http_feed] = &config.feeds[..] else {
+            panic!("expected 2 feeds");
+        };
+        let queued: Vec<_> = local_feed.push_queue.iter().map(|x| &*x.url).collect();
+        assert_eq!(
+            queued,
+            ["https://example.com/posts/2", "https://example.com/posts/3"]
+        );
+        assert!(local_feed.last_synced.is_some());
+        assert!(http_feed.last_synced.is_none());
+        assert!(http_feed.last_error.is_none());
+        assert!(config.last_sync.is_some());
+        assert!(config.last_successful_sync.is_none());
+
+        // The queue isn't sent while offline.
+        sync(&mut config, &args).unwrap();
+        assert_eq!(config.feeds[0].push_queue.len(), 2);
+        assert!(config.feeds[0].push_queue.iter().all(|x| x.attempts == 0));
+    }
+
+    #[test]
+    fn sync_keeps_entries_pocket_failed_to_add() {
+        let atom = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            include_str!("../tests/fixtures/atom.xml"),
+        )]);
+        let pocket = MockServer::start(vec![
+            MockResponse::new("503 Service Unavailable", ""),
+            MockResponse::new("400 Bad Request", "")
+                .header("X-Error-Code", "111")
+                .header("X-Error", "Invalid URL"),
+        ]);
+        let mut config = sync_config(
+            &pocket,
+            vec![FeedConfiguration {
+                url: atom.url("/atom.xml"),
+                processed_entries: vec!["https://example.com/posts/1".into()],
+                ..FeedConfiguration::default()
+            }],
+        );
+        let _runtime = enter_test_runtime();
+        let (failures, _) = sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(!failures.is_empty());
+
+        // The entry Pocket couldn't add is retried on the next sync,
+        // but the one it rejected isn't.
+        assert_eq!(
+            config.feeds[0].processed_entries,
+            ["https://example.com/posts/1", "https://example.com/posts/3"]
+        );
+        assert_eq!(config.feeds[0].entries_pushed_total, 0);
+
+        // Entries that failed to be sent to Pocket are retried on the next sync,
+        // so the sync still counts as successful.
+        assert!(config.last_successful_sync.is_some());
+        assert!(config.feeds[0].last_error.is_none());
+    }
+
+    #[test]
+    fn sync_queues_entries_while_pocket_is_unreachable() {
+        // Nothing listens on this port.
+        let unreachable = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/v3/", listener.local_addr().unwrap())
+        };
+        let pocket = MockServer::start(vec![
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+        ]);
+        let mut config = Configuration {
+            pocket_api_url: Some(unreachable.clone()),
+            max_push_attempts: Some(2),
+            ..sync_config(&pocket, vec![atom_feed(&["https://example.com/posts/1"])])
+        };
+        let _runtime = enter_test_runtime();
+
+        // The entries are queued and won't be found new again.
+        let (failures, _) = sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(failures.is_empty());
+        let feed = &config.feeds[0];
+        assert_eq!(feed.processed_entries.len(), 3);
+        assert!(feed.pending_entries.is_empty());
+        assert_eq!(
+            feed.push_queue,
+            [
+                QueuedPush {
+                    url: "https://example.com/posts/2".into(),
+                    entry_url: None,
+                    title: Some("Second post".into()),
+                    tags: None,
+                    attempts: 0,
+                    account: None,
+                },
+                QueuedPush {
+                    url: "https://example.com/posts/3".into(),
+                    entry_url: None,
+                    title: Some("Third post".into()),
+                    tags: None,
+                    attempts: 0,
+                    account: None,
+                },
+            ]
+        );
+
+        // Pocket is still unreachable on the next sync.
+        sync(&mut config, &SyncCommand::default()).unwrap();
+        assert_eq!(config.feeds[0].push_queue.len(), 2);
+        assert!(config.feeds[0]
+            .push_queue
+            .iter()
+            .all(|queued_push| queued_push.attempts == 1));
+
+        // The queue is sent once Pocket is back.
+        config.pocket_api_url = Some(pocket.url("/v3/"));
+        sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(config.feeds[0].push_queue.is_empty());
+        assert_eq!(config.feeds[0].entries_pushed_total, 2);
+
+        // Entries are dropped after `max_push_attempts` syncs.
+        config.pocket_api_url = Some(unreachable);
+        config.feeds[0].push_queue = vec![QueuedPush {
+            url: "https://example.com/posts/4".into(),
+            entry_url: None,
+            title: None,
+            tags: None,
+            attempts: 1,
+            account: None,
+        }];
+        sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(config.feeds[0].push_queue.is_empty());
+    }
+
+    #[test]
+    fn sync_records_failing_feeds() {
+        let pocket = MockServer::start(vec![]);
+        let mut config = sync_config(
+            &pocket,
+            vec![FeedConfiguration {
+                url: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/missing.xml").into(),
+                ..FeedConfiguration::default()
+            }],
+        );
+        let _runtime = enter_test_runtime();
+        let (failures, _) = sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(!failures.is_empty());
+        assert!(config.feeds[0].last_error.is_some());
+        assert!(config.last_sync.is_some());
+        assert!(config.last_successful_sync.is_none());
+    }
+
+    #[test]
+    fn check_staleness_requires_recent_sync() {
+        let now: DateTime<Utc> = "2024-01-02T12:00:00Z".parse().unwrap();
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert!(matches!(
+            check_staleness(None, day, now),
+            Err(StaleSync::Never)
+        ));
+        assert!(check_staleness(Some("2024-01-01T12:00:00Z".parse().unwrap()), day, now).is_ok());
+        let error =
+            check_staleness(Some("2024-01-01T11:59:59Z".parse().unwrap()), day, now).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the last successful sync finished at 2024-01-01T11:59:59+00:00, more than 24h ago"
+        );
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+        assert_eq!(format_duration(Duration::from_secs(15 * 60)), "15m");
+    }
+
+    #[test]
+    fn status_summarizes_configuration() {
+        let config = Configuration {
+            default_account: Account {
+                consumer_key: Some("1234-abcd".into()),
+                access_token: Some("token".into()),
+                username: Some("someone".into()),
+                ..Account::default()
+            },
+            accounts: BTreeMap::from([("work".into(), Account::default())]),
+            feeds: vec![
+                FeedConfiguration {
+                    url: "https://a.example/feed".into(),
+                    processed_entries: vec!["https://a.example/1".into()],
+                    ..FeedConfiguration::default()
+                },
+                FeedConfiguration {
+                    url: "https://b.example/feed".into(),
+                    disabled: true,
+                    processed_entries: vec![
+                        "https://b.example/1".into(),
+                        "https://b.example/2".into(),
+                    ],
+                    pending_entries: vec!["https://b.example/3".into()],
+                    last_error: Some("download error".into()),
+                    title: Some("Feed\nB".into()),
+                    ..FeedConfiguration::default()
+                },
+            ],
+            ..Configuration::default()
+        };
+
+        let status = Status::of(Path::new("config.yaml"), &config);
+        assert_eq!(status.enabled_feeds, 1);
+        assert_eq!(status.disabled_feeds, 1);
+        assert_eq!(status.processed_entries, 3);
+        let json: serde_json::Value = serde_json::to_value(&status).unwrap();
+        assert_eq!(
+            json["accounts"],
+            serde_json::json!([
+                {"name": "default", "consumer_key": true, "access_token": true, "username": "someone"},
+                {"name": "work", "consumer_key": false, "access_token": false, "username": null},
+            ])
+        );
+        assert_eq!(
+            json["failing_feeds"],
+            serde_json::json!([
+                {"url": "https://b.example/feed", "title": "Feed B", "error": "download error", "pending_entries": 1, "queued_entries": 0},
+            ])
+        );
+        assert_eq!(json["last_successful_sync"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn sync_keeps_pending_entries_when_a_feed_fails_to_parse() {
+        let feed = MockServer::start(vec![MockResponse::new("200 OK", "not a feed")]);
+        let pocket = MockServer::start(vec![]);
+        let mut config = sync_config(
+            &pocket,
+            vec![FeedConfiguration {
+                url: feed.url("/atom.xml"),
+                pending_entries: vec!["https://example.com/posts/0".into()],
+                ..FeedConfiguration::default()
+            }],
+        );
+        let _runtime = enter_test_runtime();
+        let (failures, _) = sync(&mut config, &SyncCommand::default()).unwrap();
+        assert!(!failures.is_empty());
+        assert_eq!(
+            config.feeds[0].pending_entries,
+            ["https://example.com/posts/0"]
+        );
+    }
+
+    #[test]
+    fn sync_skips_the_feeds_of_accounts_that_arent_logged_in() {
+        let pocket = MockServer::start(vec![
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+            MockResponse::new("200 OK", r#"{"status": 1}"#),
+        ]);
+        let mut config = sync_config(
+            &pocket,
+            vec![
+                atom_feed(&[]),
+                FeedConfiguration {
+                    url: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rss.xml").into(),
+                    account: Some("work".into()),
+                    ..FeedConfiguration::default()
+                },
+            ],
+        );
+        config.accounts.insert("work".into(), Account::default());
+        let _runtime = enter_test_runtime();
+        let (failures, _) = sync(&mut config, &SyncCommand::default()).unwrap();
+
+        assert_eq!(config.feeds[0].processed_entries.len(), 3);
+        assert!(config.feeds[1].processed_entries.is_empty());
+        assert!(config.feeds[1].last_synced.is_none());
+        assert!(config.last_successful_sync.is_none());
+        let message = failures.to_string();
+        assert!(message.starts_with("1 feed failed:"), "{}", message);
+        assert!(message.contains("unable to send entries to account work"));
+        // Logging in is still the fix to report.
+        let error = ErrorWithContext::new(Box::new(failures), "some feeds failed");
+        assert_eq!(error.kind(), ErrorKind::Auth);
+    }
+
+    #[test]
+    fn fetch_current_feed_reports_unexpected_not_modified_responses() {
+        let server = MockServer::start(vec![MockResponse::new("304 Not Modified", "")]);
+        let feed = FeedConfiguration {
+            url: server.url("/atom.xml"),
+            ..FeedConfiguration::default()
+        };
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let (step, error) = fetch_current_feed(&mut fetcher, &feed).err().unwrap();
+        assert_eq!(step, "download error");
+        assert!(error.to_string().contains("304 Not Modified"));
+    }
+
+    #[test]
+    fn feed_label_includes_sanitized_title() {
+        let mut feed = FeedConfiguration {
+            url: "https://example.com/feed?format=atom".into(),
+            ..FeedConfiguration::default()
+        };
+        assert_eq!(feed.label(), "https://example.com/feed?format=atom");
+
+        feed.title = Some("  Tom &\r\nJerry\u{1b}[31m\t ".into());
+        assert_eq!(
+            feed.label(),
+            "https://example.com/feed?format=atom (Tom & Jerry [31m)"
+        );
+
+        feed.title = Some("\n".into());
+        assert_eq!(feed.label(), "https://example.com/feed?format=atom");
+    }
+
+    #[test]
+    fn process_feed_counts_fetch_errors() {
+        let mut feed = FeedConfiguration {
+            url: concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/missing.xml").into(),
+            ..FeedConfiguration::default()
+        };
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        assert!(process_feed(
+            &mut feed,
+            &[],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None
+        )
+        .is_err());
+        assert_eq!(feed.fetch_error_count, 1);
+
+        feed.entries_pushed_total = 3;
+        let feed_url = feed.url.clone();
+        let mut config = Configuration {
+            feeds: vec![feed],
+            ..Configuration::default()
+        };
+        reset_stats(&mut config, &feed_url).unwrap();
+        assert_eq!(config.feeds[0].entries_pushed_total, 0);
+        assert_eq!(config.feeds[0].fetch_error_count, 0);
+        assert!(reset_stats(&mut config, "https://example.com/unknown.xml").is_err());
+    }
+
+    #[test]
+    fn process_feed_resolves_relative_entry_urls() {
+        let server = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            include_str!("../tests/fixtures/atom.xml"),
+        )]);
+        let mut feed = FeedConfiguration {
+            url: server.url("/feeds/atom.xml"),
+            // Entries left by older versions that didn't resolve URLs.
+            pending_entries: vec!["../posts/4".into(), "http://[::1/posts/5".into()],
+            ..atom_feed(&[
+                "https://example.com/posts/1",
+                "https://example.com/posts/2",
+                "https://example.com/posts/3",
+            ])
+        };
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(pocket.added(), [server.url("/posts/4")]);
+        // The invalid URL is marked as processed so it isn't retried.
+        assert!(feed.pending_entries.is_empty());
+        assert!(feed.processed_keys().contains("http://[::1/posts/5"));
+        assert_eq!(feed.invalid_entries, ["http://[::1/posts/5"]);
+        let status = |url: &str| {
+            let entry = feed.processed_entries.iter().find(|entry| entry.url == url);
+            entry.and_then(|entry| entry.status)
+        };
+        assert_eq!(
+            status("http://[::1/posts/5"),
+            Some(EntryStatus::SkippedInvalid)
+        );
+        assert_eq!(status("../posts/4"), Some(EntryStatus::Pushed));
+    }
+
+    #[test]
+    fn reprocess_invalid_entries() {
+        let mut feed = FeedConfiguration {
+            invalid_entries: vec!["http://[::1/posts/4".into()],
+            ..feed(
+                &[
+                    "https://example.com/posts/1",
+                    // Skipped by an older version
+                    "/posts/2",
+                    "http://[::1/posts/4",
+                ],
+                "tag",
+                1,
+            )
+        };
+        assert_eq!(feed.reprocess_invalid_entries(), 2);
+        assert_eq!(feed.processed_entries, ["https://example.com/posts/1"]);
+        assert_eq!(feed.pending_entries, ["http://[::1/posts/4", "/posts/2"]);
+        assert!(feed.invalid_entries.is_empty());
+
+        // Feeds that store hashes only know about the recorded entries.
+        let mut feed = FeedConfiguration {
+            processed_entries_format: ProcessedEntriesFormat::Hashed,
+            ..FeedConfiguration::default()
+        };
+        feed.mark_processed("https://example.com/posts/1", EntryStatus::Pushed);
+        feed.mark_processed("/posts/2", EntryStatus::SkippedInvalid);
+        feed.invalid_entries = vec!["/posts/2".into()];
+        assert_eq!(feed.reprocess_invalid_entries(), 1);
+        assert_eq!(feed.processed_entries.len(), 1);
+        assert!(feed
+            .processed_keys()
+            .contains("https://example.com/posts/1"));
+        assert_eq!(feed.pending_entries, ["/posts/2"]);
+
+        let mut config = Configuration {
+            feeds: vec![feed],
+            ..Configuration::default()
+        };
+        assert!(reprocess_invalid(&mut config, "https://example.com/unknown.xml").is_err());
+    }
+
+    #[test]
+    fn process_feed_applies_title_template() {
+        let mut feed = atom_feed(&["https://example.com/posts/1"]);
+        feed.title_template = Some("{feed_title}: {title}".parse().unwrap());
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            pocket.titles(),
+            [
+                Some("Example Feed: Second post".into()),
+                Some("Example Feed: Third post".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn process_feed_without_new_entries_doesnt_push() {
+        let mut feed = atom_feed(&[
+            "https://example.com/posts/1",
+            "https://example.com/posts/2",
+            "https://example.com/posts/3",
+        ]);
+        let pocket = MockPocket::default();
+        let pool = PushPool::new(pocket.clone(), 1, None, None);
+        let _runtime = enter_test_runtime();
+        let mut fetcher = Fetcher::new(&Configuration::default()).unwrap();
+        let push_errors = process_feed(
+            &mut feed,
+            &[(DEFAULT_ACCOUNT, &pool)],
+            &mut fetcher,
+            Duration::ZERO,
+            PushLimits::default(),
+            None,
+        )
+        .unwrap();
+        assert!(push_errors.is_empty());
+        assert!(pocket.added().is_empty());
+        assert_eq!(feed.processed_entries.len(), 3);
+        assert!(feed.last_new_entry.is_none());
+        assert!(feed.last_fetched.is_some());
+    }
+
+    fn feed(processed_entries: &[&str], last_e_tag: &str, last_fetched: i64) -> FeedConfiguration {
+        FeedConfiguration {
+            url: "https://example.com/feed.xml".into(),
+            processed_entries: processed_entries.iter().map(|&x| x.into()).collect(),
+            last_e_tag: Some(last_e_tag.into()),
+            last_fetched: DateTime::from_timestamp(last_fetched, 0),
+            ..FeedConfiguration::default()
+        }
+    }
+
+    #[test]
+    fn merge_state_combines_entries_and_keeps_newer_validators() {
+        let mut target = feed(&["a", "b"], "old", 1);
+        target.merge_state(FeedState::of(&feed(&["b", "c"], "new", 2)));
+        assert_eq!(target.processed_entries, ["a", "b", "c"]);
+        assert_eq!(target.last_e_tag.as_deref(), Some("new"));
+
+        let mut target = feed(&["a"], "new", 2);
+        target.merge_state(FeedState::of(&feed(&["c"], "old", 1)));
+        assert_eq!(target.processed_entries, ["a", "c"]);
+        assert_eq!(target.last_e_tag.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn single_account_configuration_is_unchanged() {
+        let yaml =
+            "consumer_key: key\naccess_token: token\nfeeds:\n- url: https://example.com/feed.xml\n";
+        let config: Configuration = serde_yaml::from_str(yaml).unwrap();
+        let account = config.account(config.feeds[0].account_name()).unwrap();
+        assert_eq!(account.consumer_key.as_deref(), Some("key"));
+        assert_eq!(account.access_token.as_deref(), Some("token"));
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), yaml);
+    }
+
+    #[test]
+    fn processed_entries_keep_their_form() {
+        let yaml = "\
+feeds:
+- url: https://example.com/feed.xml
+  processed_entries:
+  - https://example.com/posts/1
+  - url: https://example.com/posts/2
+    status: pushed
+    timestamp: 2024-01-02T00:00:00Z
+  - url: https://example.com/posts/3
+    status: marked_read
+";
+        let mut config: Configuration = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), yaml);
+
+        let feed = &mut config.feeds[0];
+        assert_eq!(feed.processed_entries[0].status, None);
+        assert_eq!(feed.processed_entries[1].status, Some(EntryStatus::Pushed));
+        assert_eq!(
+            feed.processed_entries[1].timestamp,
+            DateTime::from_timestamp(1_704_153_600, 0)
+        );
+
+        feed.mark_processed("https://example.com/posts/4", EntryStatus::Queued);
+        feed.set_status("https://example.com/posts/4", EntryStatus::Pushed);
+        assert_eq!(feed.processed_entries[3], "https://example.com/posts/4");
+        assert_eq!(feed.processed_entries[3].status, Some(EntryStatus::Pushed));
+        assert!(feed.processed_entries[3].timestamp.is_some());
+    }
+
+    #[test]
+    fn finish_login_requires_fresh_pending_login() {
+        let mut config = Configuration {
+            default_account: Account {
+                consumer_key: Some("1234-abcd".into()),
+                ..Account::default()
+            },
+            ..Configuration::default()
+        };
+        let error = finish_login(&mut config, DEFAULT_ACCOUNT).unwrap_err();
+        assert!(matches!(
+            error.source().unwrap().downcast_ref(),
+            Some(PocketSetupError::NoPendingLogin(_))
+        ));
+
+        config.default_account.pending_login = Some(PendingLogin {
+            code: "request-token".into(),
+            requested_at: Utc::now() - chrono::TimeDelta::hours(2),
+        });
+        let error = finish_login(&mut config, DEFAULT_ACCOUNT).unwrap_err();
+        assert!(matches!(
+            error.source().unwrap().downcast_ref(),
+            Some(PocketSetupError::PendingLoginExpired(_))
+        ));
+        assert!(config.default_account.access_token.is_none());
+    }
+
+    #[test]
+    fn qr_code_fits_terminal() {
+        let url = "https://getpocket.com/auth/authorize?request_token=abcd";
+        let code = qr_code(url, None).unwrap();
+        assert!(code.contains('█'));
+        let width = code.lines().next().unwrap().chars().count();
+        assert_eq!(qr_code(url, Some(width)).unwrap(), code);
+        assert!(qr_code(url, Some(width - 1))
+            .unwrap_err()
+            .contains("too narrow"));
+    }
+
+    #[test]
+    fn redirect_uri_must_be_absolute() {
+        let yaml = "redirect_uri: https://example.com/done\n";
+        let config: Configuration = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.redirect_uri.as_ref().map(Url::as_str),
+            Some("https://example.com/done")
+        );
+        assert!(serde_yaml::from_str::<Configuration>("redirect_uri: /done\n").is_err());
+
+        assert!(Args::try_parse_from([
+            "feeds-to-pocket",
+            "config.yaml",
+            "login",
+            "--redirect-uri",
+            "/done"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn username_is_saved_with_the_account() {
+        let yaml = "consumer_key: key\naccess_token: token\nusername: alice\n";
+        let config: Configuration = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.default_account.username.as_deref(), Some("alice"));
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), yaml);
+    }
+
+    #[test]
+    fn validate_user_agent() {
+        let mut config = Configuration {
+            user_agent: Some("Mozilla/5.0".into()),
+            feeds: vec![FeedConfiguration {
+                url: "https://example.com/feed.xml".into(),
+                user_agent: Some("Custom/1.0".into()),
+                ..FeedConfiguration::default()
+            }],
+            ..Configuration::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.feeds[0].user_agent = Some("Bad\nAgent".into());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn add_replaces_or_appends_tags_of_existing_feeds() {
+        let mut config = Configuration {
+            feeds: vec![FeedConfiguration {
+                url: "https://example.com/feed.xml".into(),
+                name: Some("example".into()),
+                tags: "rust,news".into(),
+                ..FeedConfiguration::default()
+            }],
+            ..Configuration::default()
+        };
+        let add_args = |args: &[&str]| {
+            AddCommand::try_parse_from(["add"].iter().chain(args).chain(&["example"])).unwrap()
+        };
+        let _runtime = enter_test_runtime();
+
+        // --tags alone could wipe the feed's tags by mistake.
+        let error = add(&mut config, &add_args(&["--tags", "work"])).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Config);
+        assert_eq!(config.feeds[0].tags, "rust,news");
+
+        add(
+            &mut config,
+            &add_args(&["--tags-append", " news , work,,rust ,work"]),
+        )
+        .unwrap();
+        assert_eq!(config.feeds[0].tags, "rust,news,work");
+
+        add(
+            &mut config,
+            &add_args(&["--tags-replace", " later ,, later"]),
+        )
+        .unwrap();
+        assert_eq!(config.feeds[0].tags, "later");
+
+        add(&mut config, &add_args(&["--tags-replace", " , "])).unwrap();
+        assert_eq!(config.feeds[0].tags, "");
+
+        assert!(AddCommand::try_parse_from([
+            "add",
+            "--tags-replace",
+            "a",
+            "--tags-append",
+            "b",
+            "example"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn add_rejects_unsupported_schemes() {
+        let mut config = Configuration::default();
+        let args = AddCommand::try_parse_from(["add", "gemini://example.com/feed.xml"]).unwrap();
+        let _runtime = enter_test_runtime();
+
+        let error = add(&mut config, &args).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Config);
+        assert!(config.feeds.is_empty());
+    }
+
+    #[test]
+    fn validate_feed_names() {
+        let mut config = Configuration {
+            feeds: vec![
+                FeedConfiguration {
+                    url: "https://example.com/feed.xml".into(),
+                    name: Some("example".into()),
+                    ..FeedConfiguration::default()
+                },
+                FeedConfiguration {
+                    url: "https://example.org/feed.xml".into(),
+                    name: Some("other".into()),
+                    ..FeedConfiguration::default()
+                },
+            ],
+            ..Configuration::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(config.feeds[0].is_identified_by("example"));
+        assert!(config.feeds[0].is_identified_by("https://example.com/feed.xml"));
+        assert!(!config.feeds[0].is_identified_by("other"));
+
+        // A feed can keep its own name.
+        assert!(config
+            .check_feed_name("example", "https://example.com/feed.xml")
+            .is_ok());
+        assert!(config
+            .check_feed_name("example", "https://example.net/feed.xml")
+            .is_err());
+        assert!(config
+            .check_feed_name(
+                "https://example.org/feed.xml",
+                "https://example.net/feed.xml"
+            )
+            .is_err());
+
+        config.feeds[1].name = Some("example".into());
+        assert!(config.validate().is_err());
+        config.feeds[1].name = Some("".into());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn unknown_account() {
+        let config = Configuration::default();
+        assert!(config.account(DEFAULT_ACCOUNT).is_ok());
+        assert!(config.account("work").is_err());
+    }
+
+    #[test]
+    fn remove_duplicates_keeps_first_occurrences() {
+        let queued_push = |url: &str, attempts| QueuedPush {
+            url: url.into(),
+            entry_url: None,
+            title: None,
+            tags: None,
+            attempts,
+            account: None,
+        };
+        let mut feed = FeedConfiguration {
+            pending_entries: vec!["c".into(), "d".into()],
+            push_queue: vec![
+                queued_push("b", 1),
+                queued_push("c", 0),
+                queued_push("b", 0),
+            ],
+            ..feed(&["b", "a", "b", "c", "a"], "tag", 1)
+        };
+        assert_eq!(feed.remove_duplicates(), 4);
+        assert_eq!(feed.processed_entries, ["b", "a", "c"]);
+        assert_eq!(feed.push_queue, [queued_push("b", 1), queued_push("c", 0)]);
+        assert_eq!(feed.pending_entries, ["d"]);
+        assert_eq!(feed.remove_duplicates(), 0);
+    }
+
+    #[test]
+    fn send_push_queue_updates_the_status_of_the_entry() {
+        // A relative link in the feed, sent to Pocket as an absolute URL.
+        let mut feed = FeedConfiguration {
+            push_queue: vec![QueuedPush {
+                url: "https://example.com/posts/1".into(),
+                entry_url: Some("/posts/1".into()),
+                title: None,
+                tags: None,
+                attempts: 0,
+                account: None,
+            }],
+            ..FeedConfiguration::default()
+        };
+        feed.mark_processed("/posts/1", EntryStatus::Queued);
+        let pocket = MockPocket::default();
+        let pools = HashMap::from([(
+            DEFAULT_ACCOUNT.to_owned(),
+            PushPool::new(pocket.clone(), 1, None, None),
+        )]);
+        let _runtime = enter_test_runtime();
+        send_push_queue(&mut feed, &pools, Duration::ZERO, 10);
+
+        assert_eq!(pocket.added(), ["https://example.com/posts/1"]);
+        assert!(feed.push_queue.is_empty());
+        assert_eq!(feed.processed_entries[0].status, Some(EntryStatus::Pushed));
+        assert_eq!(feed.entries_pushed_total, 1);
+    }
+
+    #[test]
+    fn merge_state_is_idempotent() {
+        let mut target = feed(&["a", "b"], "old", 1);
+        let source = feed(&["b", "c"], "new", 2);
+        target.merge_state(FeedState::of(&source));
+        target.merge_state(FeedState::of(&source));
+        assert_eq!(target.processed_entries, ["a", "b", "c"]);
+        assert_eq!(target.last_e_tag.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn parse_feed_reports_html_pages() {
+        let feed = FeedConfiguration {
+            url: "https://example.com/feed.xml".into(),
+            ..FeedConfiguration::default()
+        };
+        let body = "<html><head><title>Sign in</title></head></html>";
+        let error = parse_feed(&feed, body, Some("text/html".into()))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            error.contains("server returned an HTML page, not a feed (Content-Type: text/html)"),
+            "{}",
+            error
+        );
+        assert!(error.contains("body starts with: <html><head>"));
+        // The parser errors are only shown with -v.
+        assert!(!error.contains("could not parse input as a feed"));
     }
 }
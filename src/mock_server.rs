@@ -0,0 +1,168 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A minimal HTTP server for tests.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// A request received by a `MockServer`.
+pub struct MockRequest {
+    /// The request line, e.g. `GET /feed.xml HTTP/1.1`.
+    pub request_line: String,
+    headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    /// Returns the value of a header, if the request has it.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &**value)
+    }
+}
+
+/// A response for a `MockServer` to send.
+pub struct MockResponse {
+    status: &'static str,
+    headers: Vec<(&'static str, &'static str)>,
+    body: &'static str,
+    delay: Duration,
+    truncated: bool,
+}
+
+impl MockResponse {
+    /// Creates a response with a status (e.g. `200 OK`) and a body.
+    pub fn new(status: &'static str, body: &'static str) -> MockResponse {
+        MockResponse {
+            status,
+            headers: vec![],
+            body,
+            delay: Duration::ZERO,
+            truncated: false,
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: &'static str) -> MockResponse {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Announces a longer body than the one sent,
+    /// so that reading the body fails.
+    pub fn truncated(mut self) -> MockResponse {
+        self.truncated = true;
+        self
+    }
+
+    /// Waits this long after receiving the request before responding.
+    pub fn delay(mut self, delay: Duration) -> MockResponse {
+        self.delay = delay;
+        self
+    }
+}
+
+/// Answers the connections it receives with a list of responses, in order,
+/// and records the requests.
+/// Each response closes its connection.
+pub struct MockServer {
+    port: u16,
+    requests: Receiver<MockRequest>,
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<MockResponse>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let request = handle(stream, &response);
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+        });
+
+        MockServer { port, requests }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the URL of a path on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Returns the next request the server received.
+    pub fn request(&self) -> MockRequest {
+        self.requests
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the server didn't receive a request")
+    }
+}
+
+fn handle(stream: TcpStream, response: &MockResponse) -> MockRequest {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = MockRequest {
+        request_line: request_line.trim_end().into(),
+        headers,
+        body: String::new(),
+    };
+    let content_length = request
+        .header("Content-Length")
+        .map_or(0, |length| length.parse().unwrap());
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    request.body = String::from_utf8(body).unwrap();
+
+    thread::sleep(response.delay);
+    // The client may have given up waiting (e.g. to test timeouts),
+    // so failing to send the response isn't an error.
+    let _ = write_response(reader.into_inner(), response);
+
+    request
+}
+
+fn write_response(mut stream: TcpStream, response: &MockResponse) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.body.len() + usize::from(response.truncated),
+        response.body
+    )
+}
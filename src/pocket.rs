@@ -19,27 +19,42 @@
 //    but they're also presumably broken,
 //    and I didn't feel like fixing and testing them.
 
+use std::collections::HashMap;
 use std::convert::From;
 use std::error::Error;
 use std::fmt;
 use std::io::Error as IoError;
-use std::io::Read;
 use std::result::Result;
+use std::time::Duration;
 
 use reqwest::{
-    blocking::Client,
     header::{self, HeaderValue},
-    Error as HttpError,
+    Client, Error as HttpError, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
 use url::Url;
 
+use crate::proxy;
+
 #[derive(Debug)]
 pub enum PocketError {
     Http(HttpError, Option<String>),
     Io(IoError),
     SerdeJson(serde_json::Error),
-    Proto(String, String, Option<String>),
+    Proto(StatusCode, String, String, Option<String>),
+    /// The request didn't complete within the client's timeout.
+    Timeout(HttpError),
+    /// The response body is larger than this many bytes.
+    ResponseTooLarge(usize),
+    /// The state in the URL Pocket redirected to
+    /// isn't the one sent with the request token.
+    StateMismatch(Option<String>),
+    /// The consumer key doesn't have the permission (e.g. "Add")
+    /// needed for the request, with Pocket's message.
+    MissingPermission(&'static str, String),
+    /// The request wasn't sent because of `sync --offline`.
+    Offline,
 }
 
 struct HttpErrorWithBody {
@@ -63,7 +78,11 @@ impl From<IoError> for PocketError {
 
 impl From<HttpError> for PocketError {
     fn from(err: HttpError) -> PocketError {
-        PocketError::Http(err, None)
+        if err.is_timeout() {
+            PocketError::Timeout(err)
+        } else {
+            PocketError::Http(err, None)
+        }
     }
 }
 
@@ -74,14 +93,89 @@ impl From<HttpErrorWithBody> for PocketError {
 }
 
 impl Error for PocketError {
-    fn cause(&self) -> Option<&dyn Error> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            PocketError::Http(e, _) => Some(e),
+            PocketError::Http(e, _) | PocketError::Timeout(e) => Some(e),
             PocketError::Io(e) => Some(e),
             PocketError::SerdeJson(e) => Some(e),
-            PocketError::Proto(..) => None,
+            PocketError::Proto(..)
+            | PocketError::ResponseTooLarge(_)
+            | PocketError::StateMismatch(_)
+            | PocketError::MissingPermission(..)
+            | PocketError::Offline => None,
+        }
+    }
+}
+
+impl PocketError {
+    /// Returns `true` if the request didn't reach Pocket
+    /// or Pocket didn't answer it
+    /// (e.g. because the computer is offline).
+    pub fn is_network_error(&self) -> bool {
+        match self {
+            PocketError::Http(e, _) => e.status().is_none() && (e.is_connect() || e.is_request()),
+            PocketError::Timeout(_) | PocketError::Offline => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if Pocket rejected the request itself,
+    /// i.e. sending the same request again would fail in the same way.
+    /// Network errors (including timeouts), server errors
+    /// and authentication problems are not permanent.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            PocketError::Proto(status, code, ..) => {
+                *status == StatusCode::BAD_REQUEST && code != MISSING_CONSUMER_KEY_ERROR_CODE
+            }
+            PocketError::Http(e, _) => matches!(
+                e.status(),
+                Some(
+                    StatusCode::BAD_REQUEST
+                        | StatusCode::PAYLOAD_TOO_LARGE
+                        | StatusCode::URI_TOO_LONG
+                )
+            ),
+            PocketError::Io(_)
+            | PocketError::SerdeJson(_)
+            | PocketError::Timeout(_)
+            | PocketError::ResponseTooLarge(_)
+            | PocketError::StateMismatch(_)
+            | PocketError::MissingPermission(..)
+            | PocketError::Offline => false,
+        }
+    }
+
+    /// Turns the error Pocket returns when the consumer key
+    /// doesn't have `permission` into a `MissingPermission` error,
+    /// as Pocket's message doesn't say what's wrong.
+    fn for_permission(self, permission: &'static str) -> PocketError {
+        match self {
+            PocketError::Proto(status, code, message, _)
+                if status == StatusCode::FORBIDDEN
+                    && (code == MISSING_PERMISSION_ERROR_CODE
+                        || message.to_lowercase().contains("permission")) =>
+            {
+                PocketError::MissingPermission(permission, message)
+            }
+            error => error,
         }
     }
+
+    /// Returns `true` if Pocket rejected the consumer key or the access token,
+    /// i.e. the user has to log in again.
+    pub fn is_auth_error(&self) -> bool {
+        let status = match self {
+            PocketError::MissingPermission(..) => return true,
+            PocketError::Proto(status, ..) => Some(*status),
+            PocketError::Http(e, _) => e.status(),
+            _ => None,
+        };
+        matches!(
+            status,
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)
+        )
+    }
 }
 
 impl fmt::Display for PocketError {
@@ -97,7 +191,7 @@ impl fmt::Display for PocketError {
             }
             PocketError::Io(e) => e.fmt(fmt),
             PocketError::SerdeJson(e) => e.fmt(fmt),
-            PocketError::Proto(code, msg, body) => {
+            PocketError::Proto(_, code, msg, body) => {
                 write!(fmt, "{} (code {})", msg, code)?;
                 if let Some(body) = body {
                     writeln!(fmt)?;
@@ -105,6 +199,28 @@ impl fmt::Display for PocketError {
                 }
                 Ok(())
             }
+            PocketError::Timeout(e) => write!(fmt, "Pocket didn't respond in time: {}", e),
+            PocketError::ResponseTooLarge(limit) => {
+                write!(fmt, "the response is larger than {} bytes", limit)
+            }
+            PocketError::StateMismatch(Some(state)) => write!(
+                fmt,
+                "the state in the redirect URL ({}) doesn't match this login attempt",
+                state
+            ),
+            PocketError::StateMismatch(None) => {
+                write!(fmt, "the redirect URL has no state parameter")
+            }
+            PocketError::MissingPermission(permission, message) => write!(
+                fmt,
+                "{}\n\
+                The consumer key doesn't have the \"{}\" permission. \
+                Give your application this permission at {} \
+                (or create a new application with it), \
+                then run `set-consumer-key` and `login` again.",
+                message, permission, APPS_URL
+            ),
+            PocketError::Offline => write!(fmt, "the sync is offline"),
         }
     }
 }
@@ -113,11 +229,47 @@ const X_ACCEPT: &str = "X-Accept";
 const X_ERROR: &str = "X-Error";
 const X_ERROR_CODE: &str = "X-Error-Code";
 
+/// The X-Error-Code Pocket returns (with a 400 status)
+/// when the consumer key is missing from the request.
+const MISSING_CONSUMER_KEY_ERROR_CODE: &str = "138";
+
+/// The X-Error-Code Pocket returns (with a 403 status)
+/// when the consumer key doesn't have the permission for the request.
+const MISSING_PERMISSION_ERROR_CODE: &str = "150";
+
+/// The page where the user manages their Pocket applications.
+const APPS_URL: &str = "https://getpocket.com/developer/apps/";
+
+/// The base URL of Pocket's API.
+const API_URL: &str = "https://getpocket.com/v3/";
+
+/// The default time limit for a request to Pocket.
+pub const DEFAULT_POCKET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The maximum size of a response from Pocket.
+/// A page of the "get" endpoint is much smaller than that.
+const MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// The page Pocket sends the user to after they authorize the application
+/// when no other redirect URI is configured.
+pub const DEFAULT_REDIRECT_URI: &str = "https://fragag.github.io/feeds-to-pocket/login-successful";
+
+/// A client for Pocket's API.
+///
+/// The requests run on the Tokio runtime
+/// that was current when the `Pocket` was created;
+/// the methods block until the requests complete.
+#[derive(Clone)]
 pub struct Pocket {
     consumer_key: String,
     access_token: Option<String>,
     code: Option<String>,
+    /// The random nonce sent with the request token,
+    /// which the redirect URL must carry back.
+    state: Option<String>,
     client: Client,
+    runtime: Handle,
+    api_url: String,
 }
 
 #[derive(Serialize)]
@@ -158,77 +310,216 @@ pub struct PocketAddRequest<'a> {
     tweet_id: Option<&'a str>,
 }
 
+#[derive(Serialize)]
+pub struct PocketGetRequest<'a> {
+    consumer_key: &'a str,
+    access_token: &'a str,
+    state: &'a str,
+    #[serde(rename = "detailType")]
+    detail_type: &'a str,
+    sort: &'a str,
+    count: usize,
+    offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct PocketGetResponse {
+    #[serde(default)]
+    list: PocketItems,
+    since: Option<i64>,
+}
+
+/// The items returned by the "get" endpoint.
+/// Pocket returns an empty array instead of an empty object
+/// when there are no items.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PocketItems {
+    Items(HashMap<String, PocketItem>),
+    Empty([(); 0]),
+}
+
+impl Default for PocketItems {
+    fn default() -> PocketItems {
+        PocketItems::Empty([])
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PocketItem {
+    given_url: Option<String>,
+    resolved_url: Option<String>,
+}
+
+impl PocketGetResponse {
+    /// Returns the number of items in this response.
+    pub fn len(&self) -> usize {
+        match &self.list {
+            PocketItems::Items(items) => items.len(),
+            PocketItems::Empty(_) => 0,
+        }
+    }
+
+    /// Returns the URLs of the items in this response,
+    /// both as they were saved and as Pocket resolved them.
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        let items = match &self.list {
+            PocketItems::Items(items) => Some(items.values()),
+            PocketItems::Empty(_) => None,
+        };
+        items
+            .into_iter()
+            .flatten()
+            .flat_map(|item| item.given_url.iter().chain(item.resolved_url.iter()))
+            .map(|url| &**url)
+    }
+
+    /// Returns the time of this response,
+    /// to pass as `since` to get only the items changed after this response.
+    pub fn since(&self) -> Option<i64> {
+        self.since
+    }
+}
+
+/// Reads a response body, up to `MAX_RESPONSE_SIZE` bytes.
+async fn read_body(mut response: Response) -> PocketResult<String> {
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            return Err(PocketError::ResponseTooLarge(MAX_RESPONSE_SIZE));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Generates a random nonce for the `state` parameter of a login.
+fn new_state() -> PocketResult<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(IoError::from)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// The maximum number of items to request per page from the "get" endpoint.
+const GET_PAGE_SIZE: usize = 30;
+
 impl Pocket {
     pub fn new(consumer_key: &str, access_token: Option<&str>, client: Client) -> Pocket {
         Pocket {
             consumer_key: consumer_key.to_string(),
             access_token: access_token.map(|v| v.to_string()),
             code: None,
+            state: None,
             client,
+            runtime: Handle::current(),
+            api_url: API_URL.into(),
         }
     }
 
+    /// Sends the requests to another server instead of Pocket's.
+    pub fn with_api_url(mut self, api_url: &str) -> Pocket {
+        self.api_url = api_url.into();
+        self
+    }
+
+    /// Resumes a login whose request token was obtained earlier,
+    /// possibly by another process, so that [`Pocket::authorize`] can be called.
+    pub fn with_code(mut self, code: &str) -> Pocket {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// The request token obtained by [`Pocket::get_auth_url`].
+    #[inline]
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
     #[inline]
     pub fn access_token(&self) -> Option<&str> {
         self.access_token.as_deref()
     }
 
-    fn request<Req: Serialize>(&self, url: &str, request: &Req) -> PocketResult<String> {
+    fn request<Req: Serialize>(&self, endpoint: &str, request: &Req) -> PocketResult<String> {
+        self.runtime.block_on(self.request_async(endpoint, request))
+    }
+
+    async fn request_async<Req: Serialize>(
+        &self,
+        endpoint: &str,
+        request: &Req,
+    ) -> PocketResult<String> {
         let request = serde_json::to_string(request)?;
 
         let app_json = "application/json";
 
-        self.client
+        let url = format!("{}{}", self.api_url, endpoint);
+        if let Ok(url) = Url::parse(&url) {
+            proxy::log_request(&url);
+        }
+
+        let r = self
+            .client
             .post(url)
             .header(X_ACCEPT, HeaderValue::from_static(app_json))
             .header(header::CONTENT_TYPE, HeaderValue::from_static(app_json))
             .body(request)
             .send()
-            .map_err(From::from)
-            .and_then(|mut r| {
-                let mut body = String::new();
-                let body = r
-                    .read_to_string(&mut body)
-                    .map_err(From::from)
-                    .map(|_| body);
-
-                if let Some(code) = r.headers().get(X_ERROR_CODE) {
-                    return Err(PocketError::Proto(
-                        code.to_str()
-                            .expect("X-Error-Code is not well-formed UTF-8")
-                            .into(),
-                        r.headers()
-                            .get(X_ERROR)
-                            .map(|v| v.to_str().expect("X-Error is not well-formed UTF-8").into())
-                            .unwrap_or("unknown protocol error".into()),
-                        body.ok(),
-                    ));
-                }
+            .await?;
+
+        // Check the status before reading the body,
+        // as reading the body consumes the response.
+        let status = r.status();
+        let http_error = r.error_for_status_ref().err();
+        let headers = r.headers().clone();
+        let body = read_body(r).await;
+
+        if let Some(code) = headers.get(X_ERROR_CODE) {
+            return Err(PocketError::Proto(
+                status,
+                code.to_str()
+                    .expect("X-Error-Code is not well-formed UTF-8")
+                    .into(),
+                headers
+                    .get(X_ERROR)
+                    .map(|v| v.to_str().expect("X-Error is not well-formed UTF-8").into())
+                    .unwrap_or("unknown protocol error".into()),
+                body.ok(),
+            ));
+        }
 
-                if !r.status().is_success() {
-                    r.error_for_status()
-                        .map_err(|http_error| HttpErrorWithBody {
-                            http_error,
-                            body: body.as_ref().ok().cloned(),
-                        })?;
-                }
+        if let Some(http_error) = http_error {
+            return Err(HttpErrorWithBody {
+                http_error,
+                body: body.ok(),
+            }
+            .into());
+        }
 
-                body
-            })
+        body
     }
 
-    pub fn get_auth_url(&mut self) -> PocketResult<Url> {
-        const REDIRECT_URI: &str = "https://fragag.github.io/feeds-to-pocket/login-successful";
+    /// Obtains a request token and returns the URL of the page
+    /// where the user authorizes the application.
+    /// Pocket sends the user to `redirect_uri` once they're done,
+    /// with a random `state` parameter added
+    /// that [`Pocket::verify_state`] checks.
+    pub fn get_auth_url(&mut self, redirect_uri: &Url) -> PocketResult<Url> {
+        let state = new_state()?;
+        let mut redirect_uri = redirect_uri.clone();
+        redirect_uri.query_pairs_mut().append_pair("state", &state);
 
         let response = {
             // scope to release borrow on self
             let request = PocketOAuthRequest {
                 consumer_key: &self.consumer_key,
-                redirect_uri: REDIRECT_URI,
-                state: None,
+                redirect_uri: redirect_uri.as_str(),
+                state: Some(&state),
             };
 
-            self.request("https://getpocket.com/v3/oauth/request", &request)
+            self.request("oauth/request", &request)
         };
 
         response
@@ -237,12 +528,28 @@ impl Pocket {
                 let mut url = Url::parse("https://getpocket.com/auth/authorize").unwrap();
                 url.query_pairs_mut()
                     .append_pair("request_token", &r.code)
-                    .append_pair("redirect_uri", REDIRECT_URI);
+                    .append_pair("redirect_uri", redirect_uri.as_str());
                 self.code = Some(r.code);
+                self.state = Some(state);
                 url
             })
     }
 
+    /// Checks that the URL Pocket redirected the user to
+    /// carries the state of the last call to [`Pocket::get_auth_url`],
+    /// i.e. that it completes this login attempt
+    /// and not a stale or forged one.
+    pub fn verify_state(&self, redirect_url: &Url) -> PocketResult<()> {
+        let state = redirect_url
+            .query_pairs()
+            .find(|(name, _)| name == "state")
+            .map(|(_, value)| value.into_owned());
+        match (&state, &self.state) {
+            (Some(state), Some(expected)) if state == expected => Ok(()),
+            _ => Err(PocketError::StateMismatch(state)),
+        }
+    }
+
     pub fn authorize(&mut self) -> PocketResult<String> {
         {
             let request = PocketAuthorizeRequest {
@@ -250,7 +557,7 @@ impl Pocket {
                 code: self.code.as_deref().unwrap(),
             };
 
-            self.request("https://getpocket.com/v3/oauth/authorize", &request)
+            self.request("oauth/authorize", &request)
         }
         .and_then(|r| r.decode())
         .map(|r: PocketAuthorizeResponse| {
@@ -275,8 +582,69 @@ impl Pocket {
             tweet_id,
         };
 
-        self.request("https://getpocket.com/v3/add", &request)
+        self.request("add", &request)
             .map(|_| ())
+            .map_err(|e| e.for_permission("Add"))
+    }
+
+    /// Retrieves one page of the items in the user's list
+    /// (including archived items),
+    /// optionally only those that changed since a given time.
+    pub fn get(
+        &mut self,
+        since: Option<i64>,
+        offset: usize,
+        count: usize,
+    ) -> PocketResult<PocketGetResponse> {
+        let request = PocketGetRequest {
+            consumer_key: &self.consumer_key,
+            access_token: self.access_token.as_ref().unwrap(),
+            state: "all",
+            detail_type: "simple",
+            sort: "oldest",
+            count,
+            offset,
+            since,
+        };
+
+        self.request("get", &request)
+            .map_err(|e| e.for_permission("Retrieve"))
+            .and_then(|r| r.decode())
+    }
+
+    /// Retrieves the URLs of all the items in the user's list
+    /// (optionally only those that changed since a given time),
+    /// going through all the pages.
+    ///
+    /// Also returns the time of the first response,
+    /// to pass as `since` in a later call.
+    pub fn get_all_urls(&mut self, since: Option<i64>) -> PocketResult<(Vec<String>, Option<i64>)> {
+        let mut urls = vec![];
+        let mut response_since = None;
+        let mut offset = 0;
+        loop {
+            let response = self.get(since, offset, GET_PAGE_SIZE)?;
+            response_since = response_since.or(response.since());
+            urls.extend(response.urls().map(String::from));
+            if response.len() < GET_PAGE_SIZE {
+                return Ok((urls, response_since));
+            }
+
+            offset += response.len();
+        }
+    }
+}
+
+/// Something entries can be added to.
+/// This is implemented by `Pocket`,
+/// and by mocks in tests to check what would be sent to Pocket.
+pub trait AddTarget {
+    fn add(&mut self, url: &Url, title: Option<&str>, tags: Option<&str>) -> PocketResult<()>;
+}
+
+impl AddTarget for Pocket {
+    fn add(&mut self, url: &Url, title: Option<&str>, tags: Option<&str>) -> PocketResult<()> {
+        Pocket::add(self, url, title, tags, None)
     }
 }
 
@@ -289,3 +657,187 @@ impl DecodeExt for str {
         serde_json::from_str::<Resp>(self).map_err(From::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::enter_test_runtime;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    fn pocket(server: &MockServer) -> Pocket {
+        let _runtime = enter_test_runtime();
+        Pocket::new("1234-abcd", Some("token"), Client::new()).with_api_url(&server.url("/v3/"))
+    }
+
+    #[test]
+    fn add_sends_request() {
+        let server = MockServer::start(vec![MockResponse::new("200 OK", r#"{"status": 1}"#)]);
+        let url = Url::parse("https://example.com/a").unwrap();
+        pocket(&server)
+            .add(&url, Some("A"), Some("x,y"), None)
+            .unwrap();
+
+        let request = server.request();
+        assert_eq!(request.request_line, "POST /v3/add HTTP/1.1");
+        assert_eq!(request.header("X-Accept"), Some("application/json"));
+        assert_eq!(request.header("Content-Type"), Some("application/json"));
+        assert_eq!(
+            request.body,
+            r#"{"consumer_key":"1234-abcd","access_token":"token","url":"https://example.com/a","title":"A","tags":"x,y"}"#
+        );
+    }
+
+    #[test]
+    fn get_auth_url_uses_redirect_uri() {
+        let server = MockServer::start(vec![MockResponse::new(
+            "200 OK",
+            r#"{"code": "request-token"}"#,
+        )]);
+        let redirect_uri = Url::parse("https://example.com/done?from=pocket").unwrap();
+        let mut pocket = pocket(&server);
+        let auth_url = pocket.get_auth_url(&redirect_uri).unwrap();
+        let state = pocket.state.clone().unwrap();
+        assert_eq!(state.len(), 32);
+
+        let request = server.request();
+        assert_eq!(request.request_line, "POST /v3/oauth/request HTTP/1.1");
+        assert_eq!(
+            request.body,
+            format!(
+                r#"{{"consumer_key":"1234-abcd","redirect_uri":"https://example.com/done?from=pocket&state={0}","state":"{0}"}}"#,
+                state
+            )
+        );
+        assert_eq!(
+            auth_url.as_str(),
+            format!(
+                "https://getpocket.com/auth/authorize?request_token=request-token\
+                &redirect_uri=https%3A%2F%2Fexample.com%2Fdone%3Ffrom%3Dpocket%26state%3D{}",
+                state
+            )
+        );
+
+        let redirected =
+            |query: &str| Url::parse(&format!("https://example.com/done?{}", query)).unwrap();
+        assert!(pocket
+            .verify_state(&redirected(&format!("from=pocket&state={}", state)))
+            .is_ok());
+        assert!(pocket
+            .verify_state(&redirected("from=pocket&state=forged"))
+            .is_err());
+        assert!(pocket.verify_state(&redirected("from=pocket")).is_err());
+    }
+
+    #[test]
+    fn request_times_out() {
+        let server = MockServer::start(vec![
+            MockResponse::new("200 OK", r#"{"status": 1}"#).delay(Duration::from_secs(2))
+        ]);
+        let _runtime = enter_test_runtime();
+        let client = Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let url = Url::parse("https://example.com/a").unwrap();
+        let error = Pocket::new("1234-abcd", Some("token"), client)
+            .with_api_url(&server.url("/v3/"))
+            .add(&url, None, None, None)
+            .unwrap_err();
+        assert!(
+            matches!(error, PocketError::Timeout(_)),
+            "expected a timeout, got {}",
+            error
+        );
+        assert!(!error.is_permanent());
+    }
+
+    #[test]
+    fn add_reports_x_error() {
+        let server = MockServer::start(vec![MockResponse::new("400 Bad Request", "")
+            .header("X-Error-Code", "111")
+            .header("X-Error", "Invalid URL")]);
+        let url = Url::parse("https://example.com/a").unwrap();
+        let error = pocket(&server).add(&url, None, None, None).unwrap_err();
+        match &error {
+            PocketError::Proto(status, code, message, _) => {
+                assert_eq!(*status, StatusCode::BAD_REQUEST);
+                assert_eq!(code, "111");
+                assert_eq!(message, "Invalid URL");
+            }
+            _ => panic!("expected a protocol error, got {}", error),
+        }
+        assert!(error.is_permanent());
+    }
+
+    #[test]
+    fn add_reports_missing_permission() {
+        let server = MockServer::start(vec![MockResponse::new("403 Forbidden", "")
+            .header("X-Error-Code", "150")
+            .header("X-Error", "Forbidden")]);
+        let url = Url::parse("https://example.com/a").unwrap();
+        let error = pocket(&server).add(&url, None, None, None).unwrap_err();
+        assert!(
+            matches!(error, PocketError::MissingPermission("Add", ref message) if message == "Forbidden"),
+            "expected a missing permission, got {}",
+            error
+        );
+        assert!(error.to_string().contains(APPS_URL));
+        assert!(error.is_auth_error());
+        assert!(!error.is_permanent());
+    }
+
+    #[test]
+    fn add_reports_http_error_with_body() {
+        let server = MockServer::start(vec![MockResponse::new(
+            "503 Service Unavailable",
+            "try again later",
+        )]);
+        let url = Url::parse("https://example.com/a").unwrap();
+        let error = pocket(&server).add(&url, None, None, None).unwrap_err();
+        match &error {
+            PocketError::Http(e, body) => {
+                assert_eq!(e.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+                assert_eq!(body.as_deref(), Some("try again later"));
+            }
+            _ => panic!("expected an HTTP error, got {}", error),
+        }
+        assert!(!error.is_permanent());
+    }
+
+    #[test]
+    fn get_response_with_items() {
+        let response: PocketGetResponse = r#"{
+            "status": 1,
+            "complete": 1,
+            "list": {
+                "229279689": {
+                    "item_id": "229279689",
+                    "given_url": "http://example.com/a?utm_source=feed",
+                    "resolved_url": "https://example.com/a"
+                }
+            },
+            "since": 1245626956
+        }"#
+        .decode()
+        .unwrap();
+        assert_eq!(response.len(), 1);
+        assert_eq!(
+            response.urls().collect::<Vec<_>>(),
+            [
+                "http://example.com/a?utm_source=feed",
+                "https://example.com/a"
+            ]
+        );
+        assert_eq!(response.since(), Some(1245626956));
+    }
+
+    #[test]
+    fn get_response_without_items() {
+        let response: PocketGetResponse =
+            r#"{"status": 2, "complete": 1, "list": [], "since": 1245626956}"#
+                .decode()
+                .unwrap();
+        assert_eq!(response.len(), 0);
+        assert_eq!(response.urls().count(), 0);
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The proxies to send requests through,
+//! read from the standard environment variables
+//! (`HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`).
+//!
+//! reqwest reads these variables by default too,
+//! but on some platforms it also reads the system settings.
+//! Reading them here gives the same behavior everywhere,
+//! for both the feeds and Pocket,
+//! and lets `-v` show which proxy each request goes through.
+
+use std::env;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use reqwest::{ClientBuilder, Proxy};
+use url::Url;
+
+static SETTINGS: OnceLock<ProxySettings> = OnceLock::new();
+
+#[derive(Debug, Default)]
+struct ProxySettings {
+    http: Option<Url>,
+    https: Option<Url>,
+    /// The hosts and domains that are reached without a proxy.
+    no_proxy: Vec<String>,
+}
+
+impl ProxySettings {
+    fn from_env() -> ProxySettings {
+        ProxySettings::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads the settings from variables;
+    /// `var` returns the value of a variable.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> ProxySettings {
+        // The lowercase variables take precedence, like in curl.
+        let var = |name: &str| {
+            var(&name.to_lowercase())
+                .or_else(|| var(name))
+                .filter(|value| !value.trim().is_empty())
+        };
+        let proxy = |name: &str| var(name).and_then(|value| proxy_url(name, &value));
+        let all = proxy("ALL_PROXY");
+
+        ProxySettings {
+            http: proxy("HTTP_PROXY").or_else(|| all.clone()),
+            https: proxy("HTTPS_PROXY").or(all),
+            no_proxy: var("NO_PROXY")
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|entry| {
+                            let entry = entry.trim().trim_start_matches("*.");
+                            let entry = entry.trim_start_matches('.');
+                            entry.trim_start_matches('[').trim_end_matches(']')
+                        })
+                        .filter(|entry| !entry.is_empty())
+                        .map(str::to_lowercase)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the proxy to send a request for `url` through, if any.
+    fn proxy_for(&self, url: &Url) -> Option<&Url> {
+        let proxy = match url.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        }?;
+        if self.is_exempt(url) {
+            None
+        } else {
+            Some(proxy)
+        }
+    }
+
+    /// Returns whether `NO_PROXY` lists the host of `url`.
+    /// A domain also covers its subdomains;
+    /// IP addresses must match exactly.
+    fn is_exempt(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return true;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let is_ip = host.parse::<IpAddr>().is_ok();
+        self.no_proxy.iter().any(|entry| {
+            entry == "*"
+                || host == entry
+                || (!is_ip
+                    && host
+                        .strip_suffix(entry.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.')))
+        })
+    }
+}
+
+/// Parses the value of a proxy variable,
+/// which may omit the scheme (e.g. `proxy.example.com:3128`).
+fn proxy_url(name: &str, value: &str) -> Option<Url> {
+    let value = value.trim();
+    let url = if value.contains("://") {
+        Url::parse(value)
+    } else {
+        Url::parse(&format!("http://{}", value))
+    };
+    url.map_err(|e| eprintln!("WARNING: ignoring {} ({})", name, e))
+        .ok()
+}
+
+/// Disables the proxies for this run.
+/// This must be called before any HTTP client is set up.
+pub fn disable() {
+    let _ = SETTINGS.set(ProxySettings::default());
+}
+
+fn settings() -> &'static ProxySettings {
+    SETTINGS.get_or_init(ProxySettings::from_env)
+}
+
+/// Makes a client send its requests through the proxies
+/// from the environment variables.
+pub fn configure(builder: ClientBuilder) -> ClientBuilder {
+    let settings = settings();
+    builder
+        .no_proxy()
+        .proxy(Proxy::custom(move |url| settings.proxy_for(url).cloned()))
+}
+
+/// Prints which proxy a request for `url` goes through
+/// if the `-v` flag was passed on the command line.
+pub fn log_request(url: &Url) {
+    match settings().proxy_for(url) {
+        Some(proxy) => {
+            // Don't print the proxy's password.
+            let mut proxy = proxy.clone();
+            if proxy.password().is_some() {
+                let _ = proxy.set_password(Some("***"));
+            }
+            debug!("requesting {} through proxy {}", url, proxy);
+        }
+        None => debug!("requesting {} without a proxy", url),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> ProxySettings {
+        ProxySettings::from_vars(|name| {
+            vars.iter()
+                .find(|&&(var, _)| var == name)
+                .map(|&(_, value)| value.to_owned())
+        })
+    }
+
+    fn proxy_for(settings: &ProxySettings, url: &str) -> Option<String> {
+        settings
+            .proxy_for(&Url::parse(url).unwrap())
+            .map(Url::to_string)
+    }
+
+    #[test]
+    fn proxies_by_scheme() {
+        let settings = from_vars(&[
+            ("HTTP_PROXY", "proxy.example.com:3128"),
+            ("https_proxy", "http://secure.example.com:3129"),
+            ("HTTPS_PROXY", "http://ignored.example.com"),
+        ]);
+        assert_eq!(
+            proxy_for(&settings, "http://a.example/feed").as_deref(),
+            Some("http://proxy.example.com:3128/")
+        );
+        assert_eq!(
+            proxy_for(&settings, "https://a.example/feed").as_deref(),
+            Some("http://secure.example.com:3129/")
+        );
+
+        let settings = from_vars(&[("ALL_PROXY", "socks5://proxy.example.com")]);
+        assert_eq!(
+            proxy_for(&settings, "https://a.example/feed").as_deref(),
+            Some("socks5://proxy.example.com")
+        );
+
+        assert_eq!(
+            proxy_for(&ProxySettings::default(), "https://a.example/"),
+            None
+        );
+    }
+
+    #[test]
+    fn no_proxy_exempts_hosts() {
+        let settings = from_vars(&[
+            ("HTTPS_PROXY", "http://proxy.example.com"),
+            (
+                "NO_PROXY",
+                "internal.example, .corp.example, 10.0.0.1,[::1]",
+            ),
+        ]);
+        assert_eq!(proxy_for(&settings, "https://internal.example/feed"), None);
+        assert_eq!(
+            proxy_for(&settings, "https://feeds.internal.example/"),
+            None
+        );
+        assert_eq!(proxy_for(&settings, "https://corp.example/"), None);
+        assert_eq!(proxy_for(&settings, "https://a.corp.example:8443/"), None);
+        assert_eq!(proxy_for(&settings, "https://10.0.0.1/feed"), None);
+        assert_eq!(proxy_for(&settings, "https://[::1]/feed"), None);
+        assert!(proxy_for(&settings, "https://notinternal.example/").is_some());
+        assert!(proxy_for(&settings, "https://10.0.0.10/").is_some());
+
+        let settings = from_vars(&[
+            ("HTTPS_PROXY", "http://proxy.example.com"),
+            ("no_proxy", "*"),
+        ]);
+        assert_eq!(proxy_for(&settings, "https://a.example/"), None);
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::Utc;
+use url::Url;
+
+use crate::history::{History, HistoryRecord};
+use crate::pocket::{AddTarget, PocketError, PocketResult};
+
+/// The default number of threads that push entries to Pocket.
+pub const DEFAULT_PUSH_WORKERS: usize = 1;
+
+/// An entry to add to Pocket.
+pub struct PushRequest {
+    /// The URL of the feed the entry is from.
+    pub feed_url: String,
+    /// How the feed is shown in messages (see `FeedConfiguration::label`).
+    pub feed_label: String,
+    pub url: Url,
+    pub title: Option<String>,
+    pub tags: Option<String>,
+}
+
+/// Joins comma-separated lists of tags into one list,
+/// removing the whitespace around each tag
+/// (Pocket considers " rust" and "rust" to be different tags),
+/// empty tags and repeated tags.
+/// Returns `None` if there are no tags left.
+pub fn join_tags<'a>(lists: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let mut tags: Vec<&str> = vec![];
+    for tag in lists.into_iter().flat_map(|list| list.split(',')) {
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Some(tags.join(",")).filter(|tags| !tags.is_empty())
+}
+
+/// A target that only prints the entries that would be added to Pocket,
+/// for `sync --dry-run`.
+#[derive(Clone)]
+pub struct DryRun;
+
+impl AddTarget for DryRun {
+    fn add(&mut self, url: &Url, _title: Option<&str>, _tags: Option<&str>) -> PocketResult<()> {
+        println!("would add {} to Pocket", url);
+        Ok(())
+    }
+}
+
+/// A target that fails every request like an unreachable Pocket,
+/// so that the entries are queued, for `sync --offline`.
+#[derive(Clone)]
+pub struct Offline;
+
+impl AddTarget for Offline {
+    fn add(&mut self, _url: &Url, _title: Option<&str>, _tags: Option<&str>) -> PocketResult<()> {
+        Err(PocketError::Offline)
+    }
+}
+
+/// The entries of one feed, to be added to Pocket in order by one worker.
+struct Job {
+    requests: Vec<PushRequest>,
+    delay: Duration,
+    results: Sender<PocketResult<()>>,
+}
+
+/// A fixed number of threads that add entries to Pocket,
+/// which limits the number of concurrent requests to Pocket.
+///
+/// The entries of a feed are all added by the same worker, in order,
+/// so that they appear in Pocket in reading order.
+pub struct PushPool {
+    jobs: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    /// The tags to add to every request, after the request's own tags.
+    tag_all: Option<String>,
+}
+
+impl PushPool {
+    /// Starts `workers` threads that add entries to a clone of `target`,
+    /// recording the entries that were added in `history`, if any.
+    /// The tags in `tag_all` are added to every entry.
+    pub fn new<T: AddTarget + Clone + Send + 'static>(
+        target: T,
+        workers: usize,
+        history: Option<Arc<History>>,
+        tag_all: Option<String>,
+    ) -> PushPool {
+        let workers = workers.max(1);
+        let (jobs, receiver) = mpsc::sync_channel(workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..workers)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let mut target = target.clone();
+                let history = history.clone();
+                thread::spawn(move || run_worker(&mut target, &receiver, history.as_deref()))
+            })
+            .collect();
+
+        PushPool {
+            jobs: Some(jobs),
+            workers,
+            tag_all,
+        }
+    }
+
+    /// Adds entries to Pocket, in order,
+    /// waiting for `delay` between consecutive entries.
+    ///
+    /// Returns the result for each entry, in the same order as `requests`.
+    pub fn push(&self, mut requests: Vec<PushRequest>, delay: Duration) -> Vec<PocketResult<()>> {
+        let count = requests.len();
+        if count == 0 {
+            return vec![];
+        }
+
+        for request in &mut requests {
+            request.tags = join_tags(
+                request
+                    .tags
+                    .as_deref()
+                    .into_iter()
+                    .chain(self.tag_all.as_deref()),
+            );
+        }
+
+        let (results, receiver) = mpsc::channel();
+        let job = Job {
+            requests,
+            delay,
+            results,
+        };
+        self.jobs
+            .as_ref()
+            .expect("the push pool is shut down")
+            .send(job)
+            .expect("all push workers have stopped");
+
+        let results: Vec<_> = receiver.iter().collect();
+        assert_eq!(results.len(), count, "a push worker stopped unexpectedly");
+        results
+    }
+}
+
+impl Drop for PushPool {
+    fn drop(&mut self) {
+        // Closing the channel makes the workers exit
+        // once they've finished their current job.
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_worker<T: AddTarget>(
+    target: &mut T,
+    jobs: &Mutex<Receiver<Job>>,
+    history: Option<&History>,
+) {
+    loop {
+        // Release the lock before running the job
+        // so that other workers can take jobs in the meantime.
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        let mut push_delay = PushDelay::new(job.delay);
+        for request in job.requests {
+            push_delay.wait();
+            eprintln!(
+                "pushing {} from {} to Pocket",
+                request.url, request.feed_label
+            );
+            let result = target.add(
+                &request.url,
+                request.title.as_deref(),
+                request.tags.as_deref(),
+            );
+            if let (Ok(()), Some(history)) = (&result, history) {
+                let record = HistoryRecord {
+                    timestamp: Utc::now(),
+                    feed_url: request.feed_url,
+                    entry_url: request.url.into(),
+                    title: request.title,
+                    tags: request.tags,
+                };
+                if let Err(e) = history.record(&record) {
+                    eprintln!("WARNING: failed to write to the history file: {}", e);
+                }
+            }
+            if job.results.send(result).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Waits between consecutive pushes to Pocket.
+struct PushDelay {
+    delay: Duration,
+    pushed: bool,
+}
+
+impl PushDelay {
+    fn new(delay: Duration) -> PushDelay {
+        PushDelay {
+            delay,
+            pushed: false,
+        }
+    }
+
+    /// Waits for the delay, except before the first push.
+    fn wait(&mut self) {
+        if self.pushed && !self.delay.is_zero() {
+            thread::sleep(self.delay);
+        }
+        self.pushed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::pocket::PocketError;
+
+    /// Records the URLs (and tags) added by all the clones of the target.
+    #[derive(Clone, Default)]
+    struct RecordingTarget {
+        added: Arc<Mutex<Vec<String>>>,
+        tags: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl AddTarget for RecordingTarget {
+        fn add(&mut self, url: &Url, _title: Option<&str>, tags: Option<&str>) -> PocketResult<()> {
+            self.added.lock().unwrap().push(url.to_string());
+            self.tags.lock().unwrap().push(tags.map(String::from));
+            if url.path() == "/fail" {
+                Err(PocketError::Io(std::io::ErrorKind::Other.into()))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn requests(paths: &[&str]) -> Vec<PushRequest> {
+        paths
+            .iter()
+            .map(|path| PushRequest {
+                feed_url: "https://example.com/atom.xml".into(),
+                feed_label: "https://example.com/atom.xml".into(),
+                url: Url::parse("https://example.com/")
+                    .unwrap()
+                    .join(path)
+                    .unwrap(),
+                title: None,
+                tags: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn push_preserves_order_and_results() {
+        let target = RecordingTarget::default();
+        let pool = PushPool::new(target.clone(), 4, None, None);
+        let results = pool.push(requests(&["1", "2", "fail", "4"]), Duration::ZERO);
+        assert_eq!(
+            results.iter().map(Result::is_ok).collect::<Vec<_>>(),
+            [true, true, false, true]
+        );
+        assert_eq!(
+            *target.added.lock().unwrap(),
+            [
+                "https://example.com/1",
+                "https://example.com/2",
+                "https://example.com/fail",
+                "https://example.com/4"
+            ]
+        );
+    }
+
+    #[test]
+    fn push_records_added_entries_in_history() {
+        let path = std::env::temp_dir().join(format!(
+            "feeds-to-pocket-push-history-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let history = Arc::new(History::open(&path).unwrap());
+        let pool = PushPool::new(RecordingTarget::default(), 1, Some(history), None);
+        pool.push(requests(&["1", "fail"]), Duration::ZERO);
+        drop(pool);
+
+        let records = crate::history::tail(&path, 10).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].entry_url, "https://example.com/1");
+        assert_eq!(records[0].feed_url, "https://example.com/atom.xml");
+    }
+
+    #[test]
+    fn push_adds_tag_all() {
+        let target = RecordingTarget::default();
+        let pool = PushPool::new(target.clone(), 1, None, Some("work, rust".into()));
+        let mut requests = requests(&["1", "2"]);
+        requests[1].tags = Some("rust ,news".into());
+        pool.push(requests, Duration::ZERO);
+        assert_eq!(
+            *target.tags.lock().unwrap(),
+            [
+                Some("work,rust".to_owned()),
+                Some("rust,news,work".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn join_tags_normalizes_tags() {
+        assert_eq!(join_tags(["rust,news"]).as_deref(), Some("rust,news"));
+        assert_eq!(
+            join_tags([" rust , news", "work,rust ", "news"]).as_deref(),
+            Some("rust,news,work")
+        );
+        assert_eq!(join_tags([",a,,b,"]).as_deref(), Some("a,b"));
+        assert_eq!(join_tags(["", " , "]), None);
+        assert_eq!(join_tags([]), None);
+    }
+
+    #[test]
+    fn push_nothing() {
+        let pool = PushPool::new(RecordingTarget::default(), 1, None, None);
+        assert!(pool.push(vec![], Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn push_delay_only_between_pushes() {
+        let mut push_delay = PushDelay::new(Duration::from_millis(200));
+        let start = Instant::now();
+        push_delay.wait();
+        assert!(start.elapsed() < Duration::from_millis(200));
+        push_delay.wait();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
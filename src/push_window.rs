@@ -0,0 +1,213 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveTime, Utc};
+use quick_error::quick_error;
+use serde::{Deserialize, Serialize};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum PushWindowError {
+        Format {
+            display("expected a window like 18:00-23:00, optionally followed by UTC or an offset like +02:00")
+        }
+        InvalidTime(time: String) {
+            display("{:?} isn't a time like 18:00", time)
+        }
+        InvalidTimeZone(time_zone: String) {
+            display("{:?} isn't UTC or an offset like +02:00", time_zone)
+        }
+        Empty {
+            display("the window starts and ends at the same time")
+        }
+    }
+}
+
+/// The time of day during which a feed's entries may be sent to Pocket,
+/// e.g. `18:00-23:00` or `22:00-06:00 +02:00`.
+///
+/// The times are in local time, unless an offset from UTC is given.
+/// The window includes its start and excludes its end;
+/// a window that ends before it starts spans midnight.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PushWindow {
+    source: String,
+    start: NaiveTime,
+    end: NaiveTime,
+    offset: Option<FixedOffset>,
+}
+
+impl PushWindow {
+    /// Returns true if `now` is inside the window.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = match self.offset {
+            Some(offset) => now.with_timezone(&offset).time(),
+            None => now.with_timezone(&Local).time(),
+        };
+        self.contains_time(time)
+    }
+
+    fn contains_time(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl Display for PushWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for PushWindow {
+    type Err = PushWindowError;
+
+    fn from_str(source: &str) -> Result<PushWindow, PushWindowError> {
+        let mut words = source.split_whitespace();
+        let times = words.next().ok_or(PushWindowError::Format)?;
+        let offset = words.next().map(parse_offset).transpose()?;
+        if words.next().is_some() {
+            return Err(PushWindowError::Format);
+        }
+
+        let (start, end) = times.split_once('-').ok_or(PushWindowError::Format)?;
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == end {
+            return Err(PushWindowError::Empty);
+        }
+
+        Ok(PushWindow {
+            source: source.into(),
+            start,
+            end,
+            offset,
+        })
+    }
+}
+
+impl TryFrom<String> for PushWindow {
+    type Error = PushWindowError;
+
+    fn try_from(source: String) -> Result<PushWindow, PushWindowError> {
+        source.parse()
+    }
+}
+
+impl From<PushWindow> for String {
+    fn from(window: PushWindow) -> String {
+        window.source
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, PushWindowError> {
+    NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| PushWindowError::InvalidTime(s.into()))
+}
+
+fn parse_offset(s: &str) -> Result<FixedOffset, PushWindowError> {
+    if s.eq_ignore_ascii_case("UTC") || s == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    s.parse()
+        .map_err(|_| PushWindowError::InvalidTimeZone(s.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveTime {
+        parse_time(s).unwrap()
+    }
+
+    #[test]
+    fn parse_windows() {
+        let window: PushWindow = "18:00-23:30".parse().unwrap();
+        assert_eq!(window.start, time("18:00"));
+        assert_eq!(window.end, time("23:30"));
+        assert_eq!(window.offset, None);
+
+        let window: PushWindow = "22:00-06:00 +02:00".parse().unwrap();
+        assert_eq!(window.offset, FixedOffset::east_opt(2 * 3600));
+        let window: PushWindow = "22:00-06:00 -0430".parse().unwrap();
+        assert_eq!(window.offset, FixedOffset::west_opt(4 * 3600 + 30 * 60));
+        let window: PushWindow = "22:00-06:00 UTC".parse().unwrap();
+        assert_eq!(window.offset, FixedOffset::east_opt(0));
+    }
+
+    #[test]
+    fn invalid_windows() {
+        assert!(matches!(
+            "18:00".parse::<PushWindow>(),
+            Err(PushWindowError::Format)
+        ));
+        assert!(matches!(
+            "18:00-23:00 UTC extra".parse::<PushWindow>(),
+            Err(PushWindowError::Format)
+        ));
+        assert!(matches!(
+            "18:00-25:00".parse::<PushWindow>(),
+            Err(PushWindowError::InvalidTime(time)) if time == "25:00"
+        ));
+        assert!(matches!(
+            "6pm-11pm".parse::<PushWindow>(),
+            Err(PushWindowError::InvalidTime(time)) if time == "6pm"
+        ));
+        assert!(matches!(
+            "18:00-23:00 Europe/Paris".parse::<PushWindow>(),
+            Err(PushWindowError::InvalidTimeZone(time_zone)) if time_zone == "Europe/Paris"
+        ));
+        assert!(matches!(
+            "18:00-18:00".parse::<PushWindow>(),
+            Err(PushWindowError::Empty)
+        ));
+    }
+
+    #[test]
+    fn contains_times_inside_the_window() {
+        let window: PushWindow = "18:00-23:00".parse().unwrap();
+        assert!(!window.contains_time(time("17:59")));
+        assert!(window.contains_time(time("18:00")));
+        assert!(window.contains_time(time("22:59")));
+        assert!(!window.contains_time(time("23:00")));
+        assert!(!window.contains_time(time("03:00")));
+    }
+
+    #[test]
+    fn contains_times_of_overnight_windows() {
+        let window: PushWindow = "22:00-06:00".parse().unwrap();
+        assert!(!window.contains_time(time("21:59")));
+        assert!(window.contains_time(time("22:00")));
+        assert!(window.contains_time(time("00:00")));
+        assert!(window.contains_time(time("05:59")));
+        assert!(!window.contains_time(time("06:00")));
+        assert!(!window.contains_time(time("12:00")));
+    }
+
+    #[test]
+    fn contains_uses_the_offset() {
+        let window: PushWindow = "18:00-23:00 +02:00".parse().unwrap();
+        let now = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        assert!(window.contains(now("2024-01-01T16:30:00Z")));
+        assert!(!window.contains(now("2024-01-01T21:30:00Z")));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let window: PushWindow = serde_yaml::from_str("22:00-06:00 UTC").unwrap();
+        assert_eq!(window.to_string(), "22:00-06:00 UTC");
+        assert_eq!(serde_yaml::to_string(&window).unwrap(), "22:00-06:00 UTC\n");
+        assert!(serde_yaml::from_str::<PushWindow>("evenings").is_err());
+    }
+}
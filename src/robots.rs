@@ -0,0 +1,214 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::Duration;
+
+/// The rules from a robots.txt file that apply to one user agent.
+///
+/// The default value allows everything,
+/// which is what we use when a host has no robots.txt.
+#[derive(Debug, Default, PartialEq)]
+pub struct RobotsRules {
+    crawl_delay: Option<Duration>,
+    /// The Allow (true) and Disallow (false) rules, with their path patterns.
+    rules: Vec<(bool, String)>,
+}
+
+/// The lines of a robots.txt file that start with the same User-agent lines.
+#[derive(Default)]
+struct Group {
+    user_agents: Vec<String>,
+    crawl_delay: Option<Duration>,
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    /// Parses a robots.txt file
+    /// and keeps the rules of the groups that apply to `user_agent`
+    /// (or of the `*` groups if no group names it).
+    pub fn parse(robots_txt: &str, user_agent: &str) -> RobotsRules {
+        let product = product_token(user_agent);
+
+        let mut groups: Vec<Group> = vec![];
+        let mut in_user_agents = false;
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match &*key.trim().to_ascii_lowercase() {
+                "user-agent" => {
+                    // Consecutive User-agent lines start a single group.
+                    if !in_user_agents {
+                        groups.push(Group::default());
+                        in_user_agents = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.user_agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key => {
+                    in_user_agents = false;
+                    let Some(group) = groups.last_mut() else {
+                        continue;
+                    };
+                    match key {
+                        "allow" if !value.is_empty() => group.rules.push((true, value.into())),
+                        "disallow" if !value.is_empty() => group.rules.push((false, value.into())),
+                        "crawl-delay" => {
+                            group.crawl_delay = value
+                                .parse::<f64>()
+                                .ok()
+                                .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                                .map(Duration::from_secs_f64);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let names_product =
+            |group: &&Group| !product.is_empty() && group.user_agents.contains(&product);
+        let matching: Vec<&Group> = if groups.iter().any(|group| names_product(&group)) {
+            groups.iter().filter(names_product).collect()
+        } else {
+            groups
+                .iter()
+                .filter(|group| group.user_agents.iter().any(|agent| agent == "*"))
+                .collect()
+        };
+
+        RobotsRules {
+            crawl_delay: matching.iter().filter_map(|group| group.crawl_delay).max(),
+            rules: matching
+                .iter()
+                .flat_map(|group| group.rules.iter().cloned())
+                .collect(),
+        }
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    /// Returns true if the rules allow fetching `path`
+    /// (the path and query of a URL).
+    ///
+    /// The most specific (longest) matching rule wins,
+    /// and Allow wins over Disallow when they're equally specific.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Returns the product token of a User-Agent,
+/// which is what robots.txt groups name,
+/// e.g. `feeds-to-pocket` for `feeds-to-pocket/0.1.8`.
+pub fn product_token(user_agent: &str) -> String {
+    user_agent
+        .split(|c: char| c == '/' || c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+/// Checks if a path matches a robots.txt path pattern,
+/// where `*` matches any sequence of characters
+/// and a final `$` anchors the pattern at the end of the path.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part must be at the end of the path if the pattern is anchored.
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || parts.is_empty() && rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "\
+# Comments are ignored.
+User-agent: *
+Disallow: /private/
+Crawl-delay: 2
+
+User-agent: Feeds-To-Pocket
+User-agent: otherbot
+Disallow: /
+Allow: /feeds/
+Crawl-delay: 10 # seconds
+";
+
+    #[test]
+    fn parse_specific_group() {
+        let rules = RobotsRules::parse(ROBOTS_TXT, "feeds-to-pocket/0.1.8");
+        assert_eq!(rules.crawl_delay(), Some(Duration::from_secs(10)));
+        assert!(rules.is_allowed("/feeds/atom.xml"));
+        assert!(!rules.is_allowed("/blog/atom.xml"));
+    }
+
+    #[test]
+    fn parse_wildcard_group() {
+        let rules = RobotsRules::parse(ROBOTS_TXT, "Mozilla/5.0 (compatible; MyReader)");
+        assert_eq!(rules.crawl_delay(), Some(Duration::from_secs(2)));
+        assert!(rules.is_allowed("/feeds/atom.xml"));
+        assert!(!rules.is_allowed("/private/atom.xml"));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(
+            RobotsRules::parse("", "feeds-to-pocket"),
+            RobotsRules::default()
+        );
+        assert!(RobotsRules::default().is_allowed("/anything"));
+
+        // An empty Disallow allows everything.
+        let rules = RobotsRules::parse("User-agent: *\nDisallow:\n", "feeds-to-pocket");
+        assert!(rules.is_allowed("/"));
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("/feed", "/feed.xml"));
+        assert!(!matches("/feed", "/blog/feed.xml"));
+        assert!(matches("/*.xml$", "/blog/feed.xml"));
+        assert!(!matches("/*.xml$", "/blog/feed.xml?page=2"));
+        assert!(matches("/blog/*/feed", "/blog/2024/feed/"));
+        assert!(matches("/feed.xml$", "/feed.xml"));
+        assert!(!matches("/feed.xml$", "/feed.xml.bak"));
+    }
+}
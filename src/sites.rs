@@ -0,0 +1,320 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The URLs of the feeds of well-known sites,
+//! so that the URL of a page (e.g. a YouTube channel, a subreddit
+//! or a GitHub repository) can be given instead of the URL of its feed.
+
+use regex::Regex;
+use url::Url;
+
+/// The feed of a page of a well-known site.
+#[derive(Debug, PartialEq)]
+pub enum SiteFeed {
+    /// The URL of the feed.
+    Feed(String),
+    /// A YouTube page (e.g. a `@handle` URL) that must be downloaded
+    /// to find the ID of its channel; see [`youtube_channel_id`].
+    YouTubeChannelPage,
+}
+
+/// Which feed of a GitHub repository to use.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GitHubFeed {
+    #[default]
+    Releases,
+    Commits,
+}
+
+/// Returns the feed of a page of a well-known site,
+/// or `None` if the URL isn't one of those pages.
+pub fn site_feed(url: &str, github_feed: GitHubFeed) -> Option<SiteFeed> {
+    let url = Url::parse(url).ok()?;
+    youtube_feed(&url)
+        .or_else(|| reddit_feed(&url))
+        .or_else(|| github_feed_of(&url, github_feed))
+        .or_else(|| mastodon_feed(&url))
+}
+
+/// Returns the path segments of a URL, without empty segments.
+fn segments(url: &Url) -> Vec<&str> {
+    url.path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// The base URL of the feeds of YouTube channels and playlists.
+const YOUTUBE_FEED_URL: &str = "https://www.youtube.com/feeds/videos.xml";
+
+fn youtube_feed(url: &Url) -> Option<SiteFeed> {
+    let host = url.host_str()?;
+    if !matches!(host, "youtube.com" | "www.youtube.com" | "m.youtube.com") {
+        return None;
+    }
+
+    let feed = |parameter: &str, value: &str| SiteFeed::Feed(youtube_feed_url(parameter, value));
+
+    match segments(url).as_slice() {
+        ["channel", channel_id, ..] => Some(feed("channel_id", channel_id)),
+        ["user", user, ..] => Some(feed("user", user)),
+        ["playlist"] => {
+            let (_, playlist_id) = url.query_pairs().find(|(name, _)| name == "list")?;
+            Some(feed("playlist_id", &playlist_id))
+        }
+        ["c", _, ..] => Some(SiteFeed::YouTubeChannelPage),
+        [handle, ..] if handle.starts_with('@') => Some(SiteFeed::YouTubeChannelPage),
+        _ => None,
+    }
+}
+
+/// Subreddits and users have a feed at their URL followed by `.rss`.
+fn reddit_feed(url: &Url) -> Option<SiteFeed> {
+    let host = url.host_str()?;
+    if !matches!(
+        host,
+        "reddit.com" | "www.reddit.com" | "old.reddit.com" | "new.reddit.com"
+    ) {
+        return None;
+    }
+
+    match segments(url).as_slice() {
+        [kind @ ("r" | "user" | "u"), name] if !name.ends_with(".rss") => {
+            let kind = if *kind == "u" { "user" } else { kind };
+            Some(SiteFeed::Feed(format!(
+                "https://www.reddit.com/{}/{}/.rss",
+                kind, name
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Repositories have Atom feeds for their releases and their commits.
+fn github_feed_of(url: &Url, github_feed: GitHubFeed) -> Option<SiteFeed> {
+    if !matches!(url.host_str()?, "github.com" | "www.github.com") {
+        return None;
+    }
+
+    let segments = segments(url);
+    if segments.last()?.ends_with(".atom") {
+        return None;
+    }
+
+    match segments.as_slice() {
+        [owner, repo, ..] => {
+            let repo = repo.strip_suffix(".git").unwrap_or(repo);
+            let feed = match github_feed {
+                GitHubFeed::Releases => "releases",
+                GitHubFeed::Commits => "commits",
+            };
+            Some(SiteFeed::Feed(format!(
+                "https://github.com/{}/{}/{}.atom",
+                owner, repo, feed
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Mastodon can run on any host, so any URL whose path is only `/@user`
+/// is taken to be a Mastodon profile, whose feed is at its URL followed by `.rss`.
+/// Profiles of users from other instances (`/@user@example.com`) have no feed.
+fn mastodon_feed(url: &Url) -> Option<SiteFeed> {
+    match segments(url).as_slice() {
+        [user]
+            if user.starts_with('@')
+                && !user[1..].contains('@')
+                && !user.ends_with(".rss")
+                && user.len() > 1 =>
+        {
+            let mut feed_url = url.clone();
+            feed_url.set_path(&format!("/{}.rss", user));
+            feed_url.set_query(None);
+            feed_url.set_fragment(None);
+            Some(SiteFeed::Feed(feed_url.into()))
+        }
+        _ => None,
+    }
+}
+
+/// Finds the ID of the channel a YouTube page belongs to.
+pub fn youtube_channel_id(html: &str) -> Option<String> {
+    // The canonical URL of a channel page contains the channel ID;
+    // the page's metadata has it too.
+    [
+        r#"<link rel="canonical" href="https://www\.youtube\.com/channel/(UC[\w-]+)""#,
+        r#""externalId":"(UC[\w-]+)""#,
+        r#"<meta itemprop="(?:channelId|identifier)" content="(UC[\w-]+)""#,
+    ]
+    .iter()
+    .find_map(|pattern| {
+        let captures = Regex::new(pattern).unwrap().captures(html)?;
+        Some(captures[1].to_owned())
+    })
+}
+
+/// Returns the URL of the feed of a YouTube channel.
+pub fn youtube_channel_feed_url(channel_id: &str) -> String {
+    youtube_feed_url("channel_id", channel_id)
+}
+
+fn youtube_feed_url(parameter: &str, value: &str) -> String {
+    let mut feed_url = Url::parse(YOUTUBE_FEED_URL).unwrap();
+    feed_url.query_pairs_mut().append_pair(parameter, value);
+    feed_url.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(url: &str) -> SiteFeed {
+        SiteFeed::Feed(url.into())
+    }
+
+    fn site_feed(url: &str) -> Option<SiteFeed> {
+        super::site_feed(url, GitHubFeed::default())
+    }
+
+    #[test]
+    fn youtube_urls() {
+        assert_eq!(
+            site_feed("https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv"),
+            Some(feed(
+                "https://www.youtube.com/feeds/videos.xml?channel_id=UCabcdefghijklmnopqrstuv"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://youtube.com/channel/UCabcdefghijklmnopqrstuv/videos"),
+            Some(feed(
+                "https://www.youtube.com/feeds/videos.xml?channel_id=UCabcdefghijklmnopqrstuv"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://www.youtube.com/user/someone"),
+            Some(feed(
+                "https://www.youtube.com/feeds/videos.xml?user=someone"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://m.youtube.com/playlist?list=PL1234"),
+            Some(feed(
+                "https://www.youtube.com/feeds/videos.xml?playlist_id=PL1234"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://www.youtube.com/@someone"),
+            Some(SiteFeed::YouTubeChannelPage)
+        );
+        assert_eq!(
+            site_feed("https://www.youtube.com/c/Someone/videos"),
+            Some(SiteFeed::YouTubeChannelPage)
+        );
+
+        // Feed URLs and other pages are left alone.
+        assert_eq!(
+            site_feed("https://www.youtube.com/feeds/videos.xml?channel_id=UCabc"),
+            None
+        );
+        assert_eq!(site_feed("https://www.youtube.com/watch?v=abc"), None);
+        assert_eq!(site_feed("https://www.youtube.com/playlist"), None);
+        assert_eq!(site_feed("https://example.com/channel/UCabc"), None);
+        assert_eq!(site_feed("not a URL"), None);
+    }
+
+    #[test]
+    fn reddit_urls() {
+        assert_eq!(
+            site_feed("https://www.reddit.com/r/rust/"),
+            Some(feed("https://www.reddit.com/r/rust/.rss"))
+        );
+        assert_eq!(
+            site_feed("https://old.reddit.com/r/rust"),
+            Some(feed("https://www.reddit.com/r/rust/.rss"))
+        );
+        assert_eq!(
+            site_feed("https://reddit.com/u/someone"),
+            Some(feed("https://www.reddit.com/user/someone/.rss"))
+        );
+        assert_eq!(site_feed("https://www.reddit.com/r/rust/.rss"), None);
+        assert_eq!(site_feed("https://www.reddit.com/r/rust.rss"), None);
+        assert_eq!(
+            site_feed("https://www.reddit.com/r/rust/comments/abc/title/"),
+            None
+        );
+    }
+
+    #[test]
+    fn github_urls() {
+        assert_eq!(
+            site_feed("https://github.com/FraGag/feeds-to-pocket"),
+            Some(feed(
+                "https://github.com/FraGag/feeds-to-pocket/releases.atom"
+            ))
+        );
+        assert_eq!(
+            super::site_feed(
+                "https://github.com/FraGag/feeds-to-pocket.git",
+                GitHubFeed::Commits
+            ),
+            Some(feed(
+                "https://github.com/FraGag/feeds-to-pocket/commits.atom"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://github.com/FraGag/feeds-to-pocket/tree/master/src"),
+            Some(feed(
+                "https://github.com/FraGag/feeds-to-pocket/releases.atom"
+            ))
+        );
+        assert_eq!(
+            site_feed("https://github.com/FraGag/feeds-to-pocket/releases.atom"),
+            None
+        );
+        assert_eq!(site_feed("https://github.com/FraGag"), None);
+    }
+
+    #[test]
+    fn mastodon_urls() {
+        assert_eq!(
+            site_feed("https://mastodon.social/@someone"),
+            Some(feed("https://mastodon.social/@someone.rss"))
+        );
+        assert_eq!(
+            site_feed("https://example.com/@someone/?page=2#top"),
+            Some(feed("https://example.com/@someone.rss"))
+        );
+        assert_eq!(site_feed("https://mastodon.social/@someone.rss"), None);
+        assert_eq!(
+            site_feed("https://mastodon.social/@someone@example.com"),
+            None
+        );
+        assert_eq!(site_feed("https://mastodon.social/@someone/123456"), None);
+        assert_eq!(site_feed("https://mastodon.social/@"), None);
+    }
+
+    #[test]
+    fn youtube_channel_id_from_page() {
+        let html = r#"<html><head>
+            <link rel="canonical" href="https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv">
+            </head></html>"#;
+        assert_eq!(
+            youtube_channel_id(html).as_deref(),
+            Some("UCabcdefghijklmnopqrstuv")
+        );
+        assert_eq!(
+            youtube_channel_id(r#"{"metadata":{"externalId":"UC_x-y"}}"#).as_deref(),
+            Some("UC_x-y")
+        );
+        assert_eq!(youtube_channel_id("<html></html>"), None);
+        assert_eq!(
+            youtube_channel_feed_url("UC_x-y"),
+            "https://www.youtube.com/feeds/videos.xml?channel_id=UC_x-y"
+        );
+    }
+}
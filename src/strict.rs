@@ -0,0 +1,307 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Detection of the unknown (e.g. misspelled) fields of a configuration file,
+//! which serde otherwise ignores.
+
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+
+use crate::error::UnknownField;
+use crate::{Account, Configuration, FeedConfiguration, IncludeFile};
+
+/// The fields of `Configuration`, including those of the default account.
+/// They're only used to suggest a field name for the unknown fields.
+const CONFIGURATION_FIELDS: &[&str] = &[
+    "consumer_key",
+    "access_token",
+    "username",
+    "pending_login",
+    "accounts",
+    "host_delay_ms",
+    "max_body_size",
+    "max_processed_entries",
+    "command_timeout_secs",
+    "max_redirects",
+    "fetch_retries",
+    "fetch_retry_delay_ms",
+    "user_agent",
+    "push_delay_ms",
+    "pocket_api_url",
+    "pocket_timeout_secs",
+    "push_workers",
+    "max_pushes_per_run",
+    "max_push_attempts",
+    "confirm_pushes_over",
+    "max_url_length",
+    "tag_all",
+    "prefer_ipv4",
+    "force_ipv4",
+    "respect_robots_txt",
+    "history_file",
+    "redirect_uri",
+    "last_sync",
+    "last_successful_sync",
+    "include",
+    "feeds",
+];
+
+/// The fields of `IncludeFile`.
+const INCLUDE_FILE_FIELDS: &[&str] = &["include", "feeds"];
+
+/// The fields of `Account`.
+const ACCOUNT_FIELDS: &[&str] = &["consumer_key", "access_token", "username", "pending_login"];
+
+/// The fields of `FeedConfiguration`.
+const FEED_FIELDS: &[&str] = &[
+    "url",
+    "name",
+    "group",
+    "disabled",
+    "account",
+    "also_push_to",
+    "tags",
+    "processed_entries",
+    "pending_entries",
+    "deferred_entries",
+    "push_queue",
+    "partial_pushes",
+    "invalid_entries",
+    "last_modified",
+    "last_e_tag",
+    "tls_ca_file",
+    "danger_accept_invalid_certs",
+    "title_template",
+    "fetch_titles",
+    "verify_links",
+    "resolve_canonical",
+    "shorten_long_urls",
+    "filter_command",
+    "filter_batch",
+    "push_window",
+    "digest_day",
+    "large_feed",
+    "charset",
+    "always_send_if_modified_since",
+    "max_not_modified_age_days",
+    "exact_entry_urls",
+    "processed_entries_format",
+    "validator_update",
+    "push_delay_ms",
+    "max_pushes_per_run",
+    "user_agent",
+    "prefer_ipv4",
+    "force_ipv4",
+    "resolve",
+    "title",
+    "last_fetched",
+    "last_new_entry",
+    "newest_entry_published",
+    "last_synced",
+    "last_error",
+    "entries_pushed_total",
+    "last_push_date",
+    "not_modified_since",
+    "last_digest",
+    "fetch_error_count",
+    "fresh_until",
+];
+
+/// Returns the fields of a configuration file that would be ignored.
+pub fn unknown_fields(config: &Value) -> Vec<UnknownField> {
+    let mut unknown = vec![];
+    let Some(config) = config.as_mapping() else {
+        return unknown;
+    };
+
+    check::<Configuration>(
+        config,
+        "the configuration",
+        &[],
+        CONFIGURATION_FIELDS,
+        &mut unknown,
+    );
+
+    if let Some(accounts) = config.get("accounts").and_then(Value::as_mapping) {
+        for (name, account) in accounts {
+            if let Some(account) = account.as_mapping() {
+                let location = format!("account {}", display_key(name));
+                check::<Account>(account, &location, &[], ACCOUNT_FIELDS, &mut unknown);
+            }
+        }
+    }
+
+    check_feeds(config, &mut unknown);
+    unknown
+}
+
+/// Returns the unknown fields of a file listed in `include`.
+pub fn unknown_include_fields(file: &Value) -> Vec<UnknownField> {
+    let mut unknown = vec![];
+    let Some(file) = file.as_mapping() else {
+        return unknown;
+    };
+
+    check::<IncludeFile>(
+        file,
+        "the included file",
+        &[],
+        INCLUDE_FILE_FIELDS,
+        &mut unknown,
+    );
+    check_feeds(file, &mut unknown);
+    unknown
+}
+
+/// Finds the unknown fields of the feeds of a file.
+fn check_feeds(file: &Mapping, unknown: &mut Vec<UnknownField>) {
+    if let Some(feeds) = file.get("feeds").and_then(Value::as_sequence) {
+        for feed in feeds.iter().filter_map(Value::as_mapping) {
+            let location = match feed.get("url").and_then(Value::as_str) {
+                Some(url) => format!("feed {}", url),
+                None => "a feed".into(),
+            };
+            check::<FeedConfiguration>(feed, &location, &["url"], FEED_FIELDS, unknown);
+        }
+    }
+}
+
+/// Finds the keys of a mapping that `T` ignores.
+///
+/// A key is ignored if `T` can still be deserialized
+/// once the key's value is replaced with a value that no field accepts.
+/// `required` lists the fields that `T` can't be deserialized without.
+fn check<T: DeserializeOwned>(
+    mapping: &Mapping,
+    location: &str,
+    required: &[&str],
+    fields: &'static [&'static str],
+    unknown: &mut Vec<UnknownField>,
+) {
+    for key in mapping.keys() {
+        let mut probe: Mapping = required
+            .iter()
+            .filter_map(|&field| Some((field.into(), mapping.get(field)?.clone())))
+            .collect();
+        probe.insert(key.clone(), unacceptable_value());
+        if serde_yaml::from_value::<T>(Value::Mapping(probe)).is_ok() {
+            let key = display_key(key);
+            let suggestion = suggest(&key, fields);
+            unknown.push(UnknownField::UnknownField(location.into(), key, suggestion));
+        }
+    }
+}
+
+/// Returns a value that none of the configuration's fields accept:
+/// a sequence containing a mapping with an empty key.
+fn unacceptable_value() -> Value {
+    let mut mapping = Mapping::new();
+    mapping.insert("".into(), Value::Null);
+    Value::Sequence(vec![Value::Mapping(mapping)])
+}
+
+fn display_key(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        key => serde_yaml::to_string(key)
+            .map(|key| key.trim_end().to_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Returns the field whose name is closest to `key`, if one is close enough.
+fn suggest(key: &str, fields: &'static [&'static str]) -> Option<&'static str> {
+    fields
+        .iter()
+        .map(|&field| (strsim::levenshtein(key, field), field))
+        .filter(|&(distance, _)| distance <= (key.len() / 3).max(2))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, field)| field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(yaml: &str) -> Vec<String> {
+        let config: Value = serde_yaml::from_str(yaml).unwrap();
+        unknown_fields(&config)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn known_fields_are_accepted() {
+        let mut yaml = String::new();
+        for field in CONFIGURATION_FIELDS
+            .iter()
+            .filter(|&&field| field != "accounts" && field != "feeds")
+        {
+            yaml.push_str(&format!("{}: {{}}\n", field));
+        }
+        yaml.push_str("accounts:\n  alice:\n");
+        for field in ACCOUNT_FIELDS {
+            yaml.push_str(&format!("    {}: {{}}\n", field));
+        }
+        yaml.push_str("feeds:\n- url: https://example.com/feed.xml\n");
+        for field in FEED_FIELDS.iter().filter(|&&field| field != "url") {
+            yaml.push_str(&format!("  {}: {{}}\n", field));
+        }
+        assert_eq!(messages(&yaml), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let yaml = "\
+consumer_key: key
+host_delay: 100
+accounts:
+  alice:
+    acess_token: token
+feeds:
+- url: https://example.com/feed.xml
+  procesed_entries:
+  - https://example.com/posts/1
+  colour: blue
+";
+        assert_eq!(
+            messages(yaml),
+            [
+                "unknown field `host_delay` in the configuration (did you mean `host_delay_ms`?)",
+                "unknown field `acess_token` in account alice (did you mean `access_token`?)",
+                "unknown field `procesed_entries` in feed https://example.com/feed.xml \
+                (did you mean `processed_entries`?)",
+                "unknown field `colour` in feed https://example.com/feed.xml",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_of_included_files_are_reported() {
+        let yaml = "\
+include: [more.yaml]
+consumer_key: key
+feeds:
+- url: https://example.com/feed.xml
+  tag: rust
+";
+        let file: Value = serde_yaml::from_str(yaml).unwrap();
+        let messages: Vec<String> = unknown_include_fields(&file)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown field `consumer_key` in the included file",
+                "unknown field `tag` in feed https://example.com/feed.xml (did you mean `tags`?)",
+            ]
+        );
+    }
+}
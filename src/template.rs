@@ -0,0 +1,194 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::str::FromStr;
+
+use quick_error::quick_error;
+use serde::{Deserialize, Serialize};
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum TemplateError {
+        UnknownPlaceholder(name: String) {
+            display("unknown placeholder {{{}}} (expected {{title}}, {{feed_title}} or {{feed_host}})", name)
+        }
+        UnclosedPlaceholder {
+            display("a placeholder is missing its closing brace (use {{{{ for a literal brace)")
+        }
+        UnmatchedClosingBrace {
+            display("unmatched closing brace (use }}}} for a literal brace)")
+        }
+    }
+}
+
+/// A template for the titles of the entries sent to Pocket,
+/// e.g. `[LWN] {title}`.
+///
+/// `{{` and `}}` produce literal braces.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TitleTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Title,
+    FeedTitle,
+    FeedHost,
+}
+
+/// The values of the placeholders in a title template.
+pub struct TitleValues<'a> {
+    pub title: &'a str,
+    pub feed_title: &'a str,
+    pub feed_host: &'a str,
+}
+
+impl TitleTemplate {
+    pub fn render(&self, values: &TitleValues) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            rendered.push_str(match part {
+                Part::Text(text) => text,
+                Part::Title => values.title,
+                Part::FeedTitle => values.feed_title,
+                Part::FeedHost => values.feed_host,
+            });
+        }
+
+        rendered
+    }
+}
+
+impl FromStr for TitleTemplate {
+    type Err = TemplateError;
+
+    fn from_str(source: &str) -> Result<TitleTemplate, TemplateError> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(TemplateError::UnmatchedClosingBrace),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::UnclosedPlaceholder),
+                        }
+                    }
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(match &*name {
+                        "title" => Part::Title,
+                        "feed_title" => Part::FeedTitle,
+                        "feed_host" => Part::FeedHost,
+                        _ => return Err(TemplateError::UnknownPlaceholder(name)),
+                    });
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(TitleTemplate {
+            source: source.into(),
+            parts,
+        })
+    }
+}
+
+impl TryFrom<String> for TitleTemplate {
+    type Error = TemplateError;
+
+    fn try_from(source: String) -> Result<TitleTemplate, TemplateError> {
+        source.parse()
+    }
+}
+
+impl From<TitleTemplate> for String {
+    fn from(template: TitleTemplate) -> String {
+        template.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: TitleValues = TitleValues {
+        title: "Article title",
+        feed_title: "LWN.net",
+        feed_host: "lwn.net",
+    };
+
+    fn render(template: &str) -> String {
+        template.parse::<TitleTemplate>().unwrap().render(&VALUES)
+    }
+
+    #[test]
+    fn render_placeholders() {
+        assert_eq!(render("[LWN] {title}"), "[LWN] Article title");
+        assert_eq!(
+            render("{feed_title} ({feed_host}): {title}"),
+            "LWN.net (lwn.net): Article title"
+        );
+        assert_eq!(render("no placeholders"), "no placeholders");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn render_escaped_braces() {
+        assert_eq!(render("{{{title}}}"), "{Article title}");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(matches!(
+            "{author}: {title}".parse::<TitleTemplate>(),
+            Err(TemplateError::UnknownPlaceholder(name)) if name == "author"
+        ));
+        assert!(matches!(
+            "{title".parse::<TitleTemplate>(),
+            Err(TemplateError::UnclosedPlaceholder)
+        ));
+        assert!(matches!(
+            "title}".parse::<TitleTemplate>(),
+            Err(TemplateError::UnmatchedClosingBrace)
+        ));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let template: TitleTemplate = serde_yaml::from_str("'[LWN] {title}'").unwrap();
+        assert_eq!(template.render(&VALUES), "[LWN] Article title");
+        assert_eq!(
+            serde_yaml::to_string(&template).unwrap(),
+            "'[LWN] {title}'\n"
+        );
+        assert!(serde_yaml::from_str::<TitleTemplate>("'{nope}'").is_err());
+    }
+}
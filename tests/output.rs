@@ -0,0 +1,119 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checks that the subcommands only print their results on the standard output
+//! and everything else on the standard error.
+
+mod common;
+#[allow(dead_code)]
+#[path = "../src/mock_server.rs"]
+mod mock_server;
+
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use common::config_file;
+use mock_server::{MockResponse, MockServer};
+
+/// Runs the program, which must succeed,
+/// and returns its standard output and standard error.
+fn run(config: &Path, args: &[&str], stdin: &str) -> (String, String) {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = common::run(config, args, stdin);
+    let (stdout, stderr) = (
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    );
+    assert!(status.success(), "{:?} failed:\n{}", args, stderr);
+    (stdout, stderr)
+}
+
+#[test]
+fn list_prints_only_the_feeds() {
+    let config = config_file(
+        "output-list",
+        "username: alice\n\
+         feeds:\n\
+         - url: https://xkcd.com/atom.xml\n\
+         - url: https://example.com/feed.xml\n  \
+           disabled: true\n",
+    );
+    let (stdout, stderr) = run(&config, &["list"], "");
+    fs::remove_file(&config).unwrap();
+
+    assert_eq!(
+        stdout,
+        "https://xkcd.com/atom.xml\nhttps://example.com/feed.xml [disabled]\n"
+    );
+    assert_eq!(stderr, "Account default: logged in as alice\n");
+}
+
+#[test]
+fn login_prints_only_the_authorization_url() {
+    let pocket = MockServer::start(vec![
+        MockResponse::new("200 OK", r#"{"code": "request-token"}"#),
+        MockResponse::new(
+            "200 OK",
+            r#"{"access_token": "new-token", "username": "alice"}"#,
+        ),
+    ]);
+    let config = config_file(
+        "output-login",
+        &format!(
+            "consumer_key: 1234-abcd\n\
+             access_token: old-token\n\
+             pocket_api_url: {}\n",
+            pocket.url("/v3/")
+        ),
+    );
+    let (stdout, stderr) = run(&config, &["login"], "\n");
+    let saved = fs::read_to_string(&config).unwrap();
+    fs::remove_file(&config).unwrap();
+
+    assert!(
+        stdout.starts_with("https://getpocket.com/auth/authorize?request_token=request-token&"),
+        "{}",
+        stdout
+    );
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stderr.contains("note: There's already an access token"));
+    assert!(stderr.contains("Go to the following webpage to login:"));
+    assert!(stderr.ends_with("Logged in as alice\n"));
+    assert!(saved.contains("access_token: new-token"));
+}
+
+#[test]
+fn sync_prints_only_the_entries() {
+    let config = config_file(
+        "output-sync",
+        &format!(
+            "feeds:\n- url: {}/tests/fixtures/atom.xml\n",
+            env!("CARGO_MANIFEST_DIR")
+        ),
+    );
+    let (dry_run_stdout, dry_run_stderr) = run(&config, &["sync", "--dry-run"], "");
+    let (stdout, stderr) = run(&config, &["sync", "--offline"], "");
+    fs::remove_file(&config).unwrap();
+
+    assert_eq!(
+        dry_run_stdout,
+        "would add https://example.com/posts/1 to Pocket\n\
+         would add https://example.com/posts/2 to Pocket\n\
+         would add https://example.com/posts/3 to Pocket\n"
+    );
+    assert!(dry_run_stderr.contains("/tests/fixtures/atom.xml"));
+    assert!(dry_run_stderr.contains("pushing https://example.com/posts/1 from"));
+
+    // Nothing was sent to Pocket.
+    assert_eq!(stdout, "");
+    assert!(stderr.contains("Pocket is unreachable, URL https://example.com/posts/1"));
+}
@@ -0,0 +1,64 @@
+// Copyright 2016 Francis Gagné
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Checks that nothing is written to disk with `--read-only`.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{self, Output};
+
+use common::config_file;
+
+/// Runs the program with `--read-only`.
+fn run(config: &Path, args: &[&str]) -> Output {
+    common::run(config, &[&["--read-only"], args].concat(), "")
+}
+
+#[test]
+fn add_is_refused() {
+    let contents = "feeds: []\n";
+    let config = config_file("read-only-add", contents);
+    let output = run(&config, &["add", "https://xkcd.com/atom.xml"]);
+    let saved = fs::read_to_string(&config).unwrap();
+    fs::remove_file(&config).unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("nothing can be saved with --read-only"),
+        "{}",
+        stderr
+    );
+    assert_eq!(saved, contents);
+}
+
+#[test]
+fn sync_keeps_its_changes_in_memory() {
+    let history = std::env::temp_dir().join(format!(
+        "feeds-to-pocket-read-only-history-{}.jsonl",
+        process::id()
+    ));
+    let contents = format!(
+        "history_file: {}\n\
+         feeds:\n- url: {}/tests/fixtures/atom.xml\n",
+        history.display(),
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let config = config_file("read-only-sync", &contents);
+    let output = run(&config, &["sync", "--offline"]);
+    let saved = fs::read_to_string(&config).unwrap();
+    fs::remove_file(&config).unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("note: --read-only doesn't save"));
+    assert_eq!(saved, contents);
+    assert!(!history.exists());
+}